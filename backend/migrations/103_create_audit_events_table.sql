@@ -0,0 +1,35 @@
+-- Create append-only security audit log
+-- Account ids refer to nano-iam accounts and are intentionally not foreign keys,
+-- so events outlive the accounts they describe
+CREATE TABLE IF NOT EXISTS audit_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    actor_account_id UUID,
+    target_account_id UUID,
+    event_type VARCHAR(64) NOT NULL,
+    ip_address TEXT,
+    user_agent TEXT,
+    metadata JSONB NOT NULL DEFAULT '{}'::jsonb,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Create index for per-account security log queries
+CREATE INDEX IF NOT EXISTS idx_audit_events_target ON audit_events(target_account_id, created_at DESC);
+
+-- Create index for admin filtering by actor
+CREATE INDEX IF NOT EXISTS idx_audit_events_actor ON audit_events(actor_account_id, created_at DESC);
+
+-- Create index for admin filtering by event type
+CREATE INDEX IF NOT EXISTS idx_audit_events_type ON audit_events(event_type, created_at DESC);
+
+-- Reject any modification of existing rows
+CREATE OR REPLACE FUNCTION prevent_audit_events_modification()
+RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER prevent_audit_events_modification
+    BEFORE UPDATE OR DELETE ON audit_events
+    FOR EACH ROW
+    EXECUTE FUNCTION prevent_audit_events_modification();
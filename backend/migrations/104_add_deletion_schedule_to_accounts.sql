@@ -0,0 +1,9 @@
+-- Track accounts pending deletion during their grace period
+ALTER TABLE app_accounts
+ADD COLUMN IF NOT EXISTS deletion_requested_at TIMESTAMPTZ,
+ADD COLUMN IF NOT EXISTS deletion_scheduled_for TIMESTAMPTZ;
+
+-- Create partial index for the purge job
+CREATE INDEX IF NOT EXISTS idx_app_accounts_deletion_scheduled_for
+    ON app_accounts(deletion_scheduled_for)
+    WHERE deletion_scheduled_for IS NOT NULL;
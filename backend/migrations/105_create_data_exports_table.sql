@@ -0,0 +1,17 @@
+-- Create personal data export archives
+CREATE TABLE IF NOT EXISTS data_exports (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    account_id UUID NOT NULL REFERENCES app_accounts(id) ON DELETE CASCADE,
+    status VARCHAR(20) NOT NULL CHECK (status IN ('pending', 'ready', 'failed')),
+    archive BYTEA,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMP WITH TIME ZONE,
+    expires_at TIMESTAMP WITH TIME ZONE
+);
+
+-- Create index on account_id for listing a user's exports
+CREATE INDEX IF NOT EXISTS idx_data_exports_account_id ON data_exports(account_id, created_at DESC);
+
+-- Create partial index for the expiry cleanup job
+CREATE INDEX IF NOT EXISTS idx_data_exports_expires_at ON data_exports(expires_at)
+    WHERE archive IS NOT NULL;
@@ -0,0 +1,37 @@
+-- Create invite codes for closed registration
+CREATE TABLE IF NOT EXISTS invite_codes (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    code VARCHAR(64) NOT NULL UNIQUE,
+    created_by UUID REFERENCES app_accounts(id) ON DELETE SET NULL,
+    -- Restricts the invite to a single address, used for waitlist approvals
+    email VARCHAR(255),
+    max_uses INTEGER NOT NULL DEFAULT 1 CHECK (max_uses > 0),
+    use_count INTEGER NOT NULL DEFAULT 0 CHECK (use_count >= 0),
+    expires_at TIMESTAMP WITH TIME ZONE,
+    revoked_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Create index on created_by for listing a user's invites
+CREATE INDEX IF NOT EXISTS idx_invite_codes_created_by ON invite_codes(created_by, created_at DESC);
+
+-- Record which accounts were created with which invite
+CREATE TABLE IF NOT EXISTS invite_redemptions (
+    invite_code_id UUID NOT NULL REFERENCES invite_codes(id) ON DELETE CASCADE,
+    iam_account_id UUID NOT NULL,
+    redeemed_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (invite_code_id, iam_account_id)
+);
+
+-- Create waitlist for waitlist registration mode
+CREATE TABLE IF NOT EXISTS waitlist_entries (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    email VARCHAR(255) NOT NULL UNIQUE,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'approved')),
+    invite_code_id UUID REFERENCES invite_codes(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    approved_at TIMESTAMP WITH TIME ZONE
+);
+
+-- Create index on status for the admin queue
+CREATE INDEX IF NOT EXISTS idx_waitlist_entries_status ON waitlist_entries(status, created_at);
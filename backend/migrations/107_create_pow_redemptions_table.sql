@@ -0,0 +1,8 @@
+-- Track redeemed proof-of-work challenges to prevent replay
+CREATE TABLE IF NOT EXISTS pow_redemptions (
+    nonce VARCHAR(64) PRIMARY KEY,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+-- Create index on expires_at for cleanup
+CREATE INDEX IF NOT EXISTS idx_pow_redemptions_expires_at ON pow_redemptions(expires_at);
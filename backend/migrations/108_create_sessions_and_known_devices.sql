@@ -0,0 +1,36 @@
+-- Create known devices table for new-device login alerts
+CREATE TABLE IF NOT EXISTS known_devices (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    account_id UUID NOT NULL REFERENCES app_accounts(id) ON DELETE CASCADE,
+    fingerprint VARCHAR(64) NOT NULL,
+    description TEXT NOT NULL,
+    ip_prefix TEXT,
+    location TEXT,
+    first_seen_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (account_id, fingerprint)
+);
+
+-- Create sessions table tracking each sign-in and its current tokens
+CREATE TABLE IF NOT EXISTS sessions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    account_id UUID NOT NULL REFERENCES app_accounts(id) ON DELETE CASCADE,
+    device_id UUID REFERENCES known_devices(id) ON DELETE SET NULL,
+    access_token_hash VARCHAR(64) NOT NULL UNIQUE,
+    refresh_token_hash VARCHAR(64) NOT NULL UNIQUE,
+    ip_address TEXT,
+    user_agent TEXT,
+    location TEXT,
+    -- Hash of the "this wasn't me" token sent with a new-device alert
+    alert_token_hash VARCHAR(64) UNIQUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    revoked_at TIMESTAMP WITH TIME ZONE
+);
+
+-- Create index on account_id for listing and revoking an account's sessions
+CREATE INDEX IF NOT EXISTS idx_sessions_account_id ON sessions(account_id);
+
+-- Accounts that must change their password before doing anything else
+ALTER TABLE app_accounts
+ADD COLUMN IF NOT EXISTS password_reset_required_at TIMESTAMPTZ;
@@ -0,0 +1,12 @@
+-- Create verification attempts table counting wrong codes per account
+CREATE TABLE IF NOT EXISTS verification_attempts (
+    iam_account_id UUID PRIMARY KEY REFERENCES accounts(id) ON DELETE CASCADE,
+    failed_attempts INTEGER NOT NULL DEFAULT 0,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Create table of verification links already used, kept until they expire
+CREATE TABLE IF NOT EXISTS used_verification_links (
+    token_hash VARCHAR(64) PRIMARY KEY,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Track step-up re-authentication per session
+ALTER TABLE sessions
+ADD COLUMN IF NOT EXISTS elevated_until TIMESTAMPTZ;
@@ -0,0 +1,4 @@
+-- Track the timeout policy chosen at sign-in and the absolute end of each session
+ALTER TABLE sessions
+ADD COLUMN IF NOT EXISTS remember_me BOOLEAN NOT NULL DEFAULT FALSE,
+ADD COLUMN IF NOT EXISTS expires_at TIMESTAMPTZ;
@@ -0,0 +1,34 @@
+-- Normalize existing usernames to their canonical lowercase form
+UPDATE app_accounts
+SET username = NULLIF(LOWER(TRIM(username)), '')
+WHERE username IS NOT NULL;
+
+-- Clear usernames that usernames::validate rejects (length, characters, reserved
+-- names; keep the list in sync with RESERVED_NAMES) so they can't be used to
+-- sign in; their owners can pick a new one
+UPDATE app_accounts
+SET username = NULL
+WHERE username IS NOT NULL
+  AND (char_length(username) NOT BETWEEN 3 AND 32
+       OR username !~ '^[a-z0-9]([a-z0-9_.-]*[a-z0-9])?$'
+       OR username IN ('admin', 'administrator', 'root', 'system', 'support', 'help', 'security',
+                       'staff', 'moderator', 'owner', 'official', 'api', 'www', 'mail', 'email',
+                       'postmaster', 'abuse', 'noreply', 'no-reply', 'webmaster', 'me', 'settings',
+                       'account', 'accounts', 'dashboard', 'signin', 'signup', 'login', 'logout',
+                       'register', 'verify', 'null', 'undefined', 'anonymous', 'everyone'));
+
+-- Resolve duplicates: the oldest account keeps the name, later ones get a suffix
+-- that keeps them within 32 characters
+WITH ranked AS (
+    SELECT id, username,
+           ROW_NUMBER() OVER (PARTITION BY username ORDER BY created_at, id) AS position
+    FROM app_accounts
+    WHERE username IS NOT NULL
+)
+UPDATE app_accounts
+SET username = LEFT(ranked.username, 23) || '_' || LEFT(REPLACE(app_accounts.id::text, '-', ''), 8)
+FROM ranked
+WHERE app_accounts.id = ranked.id AND ranked.position > 1;
+
+-- Create unique index so usernames can be used to sign in
+CREATE UNIQUE INDEX IF NOT EXISTS idx_app_accounts_username ON app_accounts(username);
@@ -0,0 +1,14 @@
+-- Create notification events table backing the real-time stream and its resume
+CREATE TABLE IF NOT EXISTS notification_events (
+    id BIGSERIAL PRIMARY KEY,
+    account_id UUID NOT NULL REFERENCES app_accounts(id) ON DELETE CASCADE,
+    event_type VARCHAR(20) NOT NULL,
+    data JSONB NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Create index for replaying an account's events after a Last-Event-ID
+CREATE INDEX IF NOT EXISTS idx_notification_events_account_id ON notification_events(account_id, id);
+
+-- Create index on created_at for cleanup
+CREATE INDEX IF NOT EXISTS idx_notification_events_created_at ON notification_events(created_at);
@@ -0,0 +1,13 @@
+-- Create realtime connections table for presence across replicas
+CREATE TABLE IF NOT EXISTS realtime_connections (
+    id UUID PRIMARY KEY,
+    account_id UUID NOT NULL REFERENCES app_accounts(id) ON DELETE CASCADE,
+    connected_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Create index on account_id for presence lookups
+CREATE INDEX IF NOT EXISTS idx_realtime_connections_account_id ON realtime_connections(account_id);
+
+-- Create index on last_seen_at for expiring connections of crashed replicas
+CREATE INDEX IF NOT EXISTS idx_realtime_connections_last_seen_at ON realtime_connections(last_seen_at);
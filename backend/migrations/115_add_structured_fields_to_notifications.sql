@@ -0,0 +1,11 @@
+-- Add a kind, title, typed payload, actions and source to notifications
+ALTER TABLE notifications
+ADD COLUMN IF NOT EXISTS kind VARCHAR(100) NOT NULL DEFAULT 'general',
+ADD COLUMN IF NOT EXISTS title TEXT,
+ADD COLUMN IF NOT EXISTS data JSONB NOT NULL DEFAULT '{}'::jsonb,
+ADD COLUMN IF NOT EXISTS action_url TEXT,
+ADD COLUMN IF NOT EXISTS actions JSONB NOT NULL DEFAULT '[]'::jsonb,
+ADD COLUMN IF NOT EXISTS source VARCHAR(100);
+
+-- Create index for filtering notifications by kind
+CREATE INDEX IF NOT EXISTS idx_notifications_kind ON notifications(account_id, kind, created_at DESC);
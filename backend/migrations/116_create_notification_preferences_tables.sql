@@ -0,0 +1,41 @@
+-- Create notification routing rules, each covering one kind or one level
+CREATE TABLE IF NOT EXISTS notification_preferences (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    account_id UUID NOT NULL REFERENCES app_accounts(id) ON DELETE CASCADE,
+    kind VARCHAR(100),
+    level VARCHAR(20) CHECK (level IN ('info', 'warning', 'error')),
+    channels TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    CHECK ((kind IS NULL) <> (level IS NULL))
+);
+
+-- Create unique indexes so each kind and level has at most one rule per account
+CREATE UNIQUE INDEX IF NOT EXISTS idx_notification_preferences_kind
+    ON notification_preferences(account_id, kind) WHERE kind IS NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_notification_preferences_level
+    ON notification_preferences(account_id, level) WHERE level IS NOT NULL;
+
+-- Create per-account notification delivery settings
+CREATE TABLE IF NOT EXISTS notification_settings (
+    account_id UUID PRIMARY KEY REFERENCES app_accounts(id) ON DELETE CASCADE,
+    webhook_url TEXT,
+    -- Key for the HMAC signature sent with each webhook delivery
+    webhook_secret TEXT,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Create queue of notifications waiting for the next digest email
+CREATE TABLE IF NOT EXISTS notification_digest_items (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    account_id UUID NOT NULL REFERENCES app_accounts(id) ON DELETE CASCADE,
+    kind VARCHAR(100) NOT NULL,
+    level VARCHAR(20) NOT NULL,
+    title TEXT,
+    message TEXT NOT NULL,
+    action_url TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Create index for collecting an account's pending digest items
+CREATE INDEX IF NOT EXISTS idx_notification_digest_items_account_id
+    ON notification_digest_items(account_id, created_at);
@@ -0,0 +1,39 @@
+-- Add digest schedule to notification settings
+ALTER TABLE notification_settings
+ADD COLUMN IF NOT EXISTS digest_frequency VARCHAR(10) NOT NULL DEFAULT 'off'
+    CHECK (digest_frequency IN ('off', 'daily', 'weekly')),
+ADD COLUMN IF NOT EXISTS digest_time_zone TEXT NOT NULL DEFAULT 'UTC',
+ADD COLUMN IF NOT EXISTS digest_hour SMALLINT NOT NULL DEFAULT 8
+    CHECK (digest_hour BETWEEN 0 AND 23),
+-- ISO weekday of weekly digests, Monday = 1
+ADD COLUMN IF NOT EXISTS digest_weekday SMALLINT NOT NULL DEFAULT 1
+    CHECK (digest_weekday BETWEEN 1 AND 7),
+ADD COLUMN IF NOT EXISTS last_digest_at TIMESTAMPTZ,
+ADD COLUMN IF NOT EXISTS next_digest_at TIMESTAMPTZ;
+
+-- Create index for finding accounts whose digest is due
+CREATE INDEX IF NOT EXISTS idx_notification_settings_next_digest_at
+    ON notification_settings(next_digest_at) WHERE digest_frequency <> 'off';
+
+-- Create record of digests sent, with what each one included
+CREATE TABLE IF NOT EXISTS notification_digests (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    account_id UUID NOT NULL REFERENCES app_accounts(id) ON DELETE CASCADE,
+    -- The slot the digest was due at; unique so two runs can't send the same digest
+    scheduled_for TIMESTAMPTZ NOT NULL,
+    period_start TIMESTAMPTZ,
+    period_end TIMESTAMPTZ NOT NULL,
+    notification_ids UUID[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    sent_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_notification_digests_slot
+    ON notification_digests(account_id, scheduled_for);
+
+-- Link queued digest items to the digest that included them
+ALTER TABLE notification_digest_items
+ADD COLUMN IF NOT EXISTS digest_id UUID REFERENCES notification_digests(id) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS idx_notification_digest_items_pending
+    ON notification_digest_items(account_id) WHERE digest_id IS NULL;
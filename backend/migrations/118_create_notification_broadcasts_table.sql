@@ -0,0 +1,36 @@
+-- Create admin broadcasts, delivered to their segment in batches by a background job
+CREATE TABLE IF NOT EXISTS notification_broadcasts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    -- nano-iam account ID of the admin who sent it
+    created_by UUID NOT NULL,
+    kind VARCHAR(100) NOT NULL,
+    level VARCHAR(20) NOT NULL CHECK (level IN ('info', 'warning', 'error')),
+    title TEXT,
+    message TEXT NOT NULL,
+    action_url TEXT,
+    actions JSONB NOT NULL DEFAULT '[]'::jsonb,
+    segment JSONB NOT NULL DEFAULT '{}'::jsonb,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'running', 'completed', 'retracting', 'retracted')),
+    -- Last app account processed, in ID order, so delivery resumes where it stopped
+    cursor UUID,
+    -- Accounts in the segment when the broadcast was created
+    total_recipients INTEGER NOT NULL DEFAULT 0,
+    delivered_count INTEGER NOT NULL DEFAULT 0,
+    retracted_count INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    started_at TIMESTAMPTZ,
+    completed_at TIMESTAMPTZ,
+    retracted_at TIMESTAMPTZ
+);
+
+-- Create index for finding broadcasts the job still has work on
+CREATE INDEX IF NOT EXISTS idx_notification_broadcasts_status
+    ON notification_broadcasts(created_at) WHERE status IN ('pending', 'running', 'retracting');
+
+-- Link notifications to the broadcast that created them
+ALTER TABLE notifications
+ADD COLUMN IF NOT EXISTS broadcast_id UUID REFERENCES notification_broadcasts(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_notifications_broadcast_id
+    ON notifications(broadcast_id) WHERE broadcast_id IS NOT NULL;
@@ -0,0 +1,19 @@
+-- Add expiry and an archived state to notifications
+ALTER TABLE notifications
+ADD COLUMN IF NOT EXISTS expires_at TIMESTAMPTZ,
+ADD COLUMN IF NOT EXISTS archived_at TIMESTAMPTZ;
+
+-- Create index for the cleanup job to find expired notifications
+CREATE INDEX IF NOT EXISTS idx_notifications_expires_at
+    ON notifications(expires_at) WHERE expires_at IS NOT NULL;
+
+-- Create index for listing archived notifications
+CREATE INDEX IF NOT EXISTS idx_notifications_archived
+    ON notifications(account_id, created_at DESC) WHERE archived_at IS NOT NULL;
+
+-- Let broadcasts carry an expiry to the notifications they create
+ALTER TABLE notification_broadcasts
+ADD COLUMN IF NOT EXISTS expires_at TIMESTAMPTZ;
+
+-- Create index for the cleanup job to find notifications past their retention period
+CREATE INDEX IF NOT EXISTS idx_notifications_created_at_all ON notifications(created_at);
@@ -0,0 +1,9 @@
+-- Add group keys and repeat counts to notifications
+ALTER TABLE notifications
+ADD COLUMN IF NOT EXISTS group_key TEXT,
+ADD COLUMN IF NOT EXISTS occurrences INTEGER NOT NULL DEFAULT 1,
+ADD COLUMN IF NOT EXISTS last_occurred_at TIMESTAMPTZ;
+
+-- Create index for finding a group's latest notification when de-duplicating and grouping
+CREATE INDEX IF NOT EXISTS idx_notifications_group_key
+    ON notifications(account_id, group_key, created_at DESC) WHERE group_key IS NOT NULL;
@@ -0,0 +1,14 @@
+-- Fail all but the newest pending export of each account, so at most one remains
+UPDATE data_exports
+SET status = 'failed', completed_at = NOW()
+WHERE status = 'pending'
+  AND id NOT IN (
+      SELECT DISTINCT ON (account_id) id
+      FROM data_exports
+      WHERE status = 'pending'
+      ORDER BY account_id, created_at DESC
+  );
+
+-- Create unique partial index so an account can only have one export in progress
+CREATE UNIQUE INDEX IF NOT EXISTS idx_data_exports_one_pending
+    ON data_exports(account_id) WHERE status = 'pending';
@@ -0,0 +1,4 @@
+-- Track when each session's current access token expires, so cached
+-- authentications never outlive it
+ALTER TABLE sessions
+ADD COLUMN IF NOT EXISTS access_token_expires_at TIMESTAMPTZ;
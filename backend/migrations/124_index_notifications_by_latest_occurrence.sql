@@ -0,0 +1,3 @@
+-- Create index for listing notifications by when they last occurred
+CREATE INDEX IF NOT EXISTS idx_notifications_occurred_at
+    ON notifications(account_id, (COALESCE(last_occurred_at, created_at)) DESC, id DESC);
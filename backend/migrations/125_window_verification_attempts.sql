@@ -0,0 +1,3 @@
+-- Count wrong verification codes per time window instead of per issued code
+ALTER TABLE verification_attempts
+ADD COLUMN IF NOT EXISTS window_started_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW();
@@ -0,0 +1,11 @@
+-- Count wrong tries against the code last issued to an address, keyed by a
+-- keyed hash of the address rather than by account
+DROP TABLE IF EXISTS verification_attempts;
+
+CREATE TABLE verification_attempts (
+    email_hash VARCHAR(64) PRIMARY KEY,
+    failed_attempts INTEGER NOT NULL DEFAULT 0,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_verification_attempts_updated_at ON verification_attempts(updated_at);
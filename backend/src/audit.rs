@@ -0,0 +1,151 @@
+use actix_web::{Error, FromRequest, HttpRequest};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use std::env;
+use std::future::{ready, Ready};
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+use crate::client_ip;
+use crate::dba::DbContext;
+
+/// Security-relevant event types recorded in the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditEventType {
+    Signup,
+    EmailVerified,
+    LoginSucceeded,
+    LoginFailed,
+    GoogleLogin,
+    RefreshTokenReuse,
+    PasswordChanged,
+    AccountDeletionRequested,
+    AccountRestored,
+    AccountDeleted,
+    SettingsChanged,
+    DataExportRequested,
+    DataExportDownloaded,
+    NewDeviceLogin,
+    UnrecognizedLoginReported,
+    SignupExistingEmail,
+    VerificationResendRejected,
+    Reauthenticated,
+}
+
+impl AuditEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEventType::Signup => "signup",
+            AuditEventType::EmailVerified => "email_verified",
+            AuditEventType::LoginSucceeded => "login_succeeded",
+            AuditEventType::LoginFailed => "login_failed",
+            AuditEventType::GoogleLogin => "google_login",
+            AuditEventType::RefreshTokenReuse => "refresh_token_reuse",
+            AuditEventType::PasswordChanged => "password_changed",
+            AuditEventType::AccountDeletionRequested => "account_deletion_requested",
+            AuditEventType::AccountRestored => "account_restored",
+            AuditEventType::AccountDeleted => "account_deleted",
+            AuditEventType::SettingsChanged => "settings_changed",
+            AuditEventType::DataExportRequested => "data_export_requested",
+            AuditEventType::DataExportDownloaded => "data_export_downloaded",
+            AuditEventType::NewDeviceLogin => "new_device_login",
+            AuditEventType::UnrecognizedLoginReported => "unrecognized_login_reported",
+            AuditEventType::SignupExistingEmail => "signup_existing_email",
+            AuditEventType::VerificationResendRejected => "verification_resend_rejected",
+            AuditEventType::Reauthenticated => "reauthenticated",
+        }
+    }
+}
+
+/// Client details attached to every audit event
+#[derive(Debug, Clone, Default)]
+pub struct RequestMeta {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl FromRequest for RequestMeta {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
+        let user_agent = req
+            .headers()
+            .get(actix_web::http::header::USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string());
+
+        ready(Ok(RequestMeta {
+            ip_address: client_ip::resolve(req).map(|ip| ip.to_string()),
+            user_agent,
+        }))
+    }
+}
+
+/// A single audit log entry to be written
+pub struct AuditEntry {
+    pub event_type: AuditEventType,
+    pub actor_account_id: Option<Uuid>,
+    pub target_account_id: Option<Uuid>,
+    pub metadata: serde_json::Value,
+}
+
+impl AuditEntry {
+    /// Event performed by an account on itself
+    pub fn own(event_type: AuditEventType, account_id: Uuid) -> Self {
+        Self {
+            event_type,
+            actor_account_id: Some(account_id),
+            target_account_id: Some(account_id),
+            metadata: serde_json::json!({}),
+        }
+    }
+
+    /// Event without an authenticated actor, such as a failed login
+    pub fn anonymous(event_type: AuditEventType, target_account_id: Option<Uuid>) -> Self {
+        Self {
+            event_type,
+            actor_account_id: None,
+            target_account_id,
+            metadata: serde_json::json!({}),
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+/// Pseudonymise an email, username or webhook URL before it is written to an
+/// event. The audit log is append-only and outlives account purges, so
+/// identifiers are hashed when recorded and never stored as such; a keyed hash
+/// still lets repeated attempts against one identifier be correlated.
+pub fn identifier_hash(identifier: &str) -> String {
+    static KEY: OnceLock<Vec<u8>> = OnceLock::new();
+    let key = KEY.get_or_init(|| match env::var("AUDIT_IDENTIFIER_SECRET") {
+        Ok(secret) if !secret.is_empty() => secret.into_bytes(),
+        _ => {
+            log::warn!("AUDIT_IDENTIFIER_SECRET not set, using a random key; identifier hashes will not match across replicas or restarts");
+            let mut key = vec![0u8; 32];
+            rand::thread_rng().fill_bytes(&mut key);
+            key
+        }
+    });
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(identifier.trim().to_lowercase().as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Write an audit event, logging instead of failing the request on error
+pub async fn record(db: &DbContext, meta: &RequestMeta, entry: AuditEntry) {
+    if let Err(e) = db.insert_audit_event(&entry, meta).await {
+        log::error!(
+            "Failed to record audit event {}: {:?}",
+            entry.event_type.as_str(),
+            e
+        );
+    }
+}
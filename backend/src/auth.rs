@@ -1,8 +1,17 @@
-use actix_web::{dev::ServiceRequest, web, Error, HttpMessage, FromRequest};
+use actix_web::{dev::ServiceRequest, web, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
+use chrono::Utc;
 use nano_iam::{AuthService, IamError};
+use std::collections::HashSet;
+use std::env;
 use std::sync::Arc;
-use std::future::{ready, Ready};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+
+use crate::dba::DbContext;
+use crate::models::Account;
+use crate::sessions::{self, SessionConfig};
+use crate::token_cache::{Principal, TokenCache};
 
 #[derive(Clone)]
 pub struct AuthenticatedUser {
@@ -14,38 +23,139 @@ pub async fn validator(
     req: ServiceRequest,
     credentials: BearerAuth,
 ) -> Result<ServiceRequest, (Error, ServiceRequest)> {
-    let auth_service = match req.app_data::<web::Data<Arc<AuthService>>>() {
-        Some(service) => service,
-        None => {
-            return Err((
-                actix_web::error::ErrorInternalServerError("Auth service not configured"),
-                req,
-            ));
+    let principal = match authenticate(req.request(), credentials.token()).await {
+        Ok(principal) => principal,
+        Err(e) => return Err((e, req)),
+    };
+
+    if let Err(e) = check_password_reset(req.path(), &principal) {
+        return Err((e, req));
+    }
+    insert_principal(&req, principal);
+    Ok(req)
+}
+
+/// Resolve an access token to its principal, the way the validator does for
+/// every protected request. Also used by routes that can't carry a bearer
+/// header, such as the WebSocket.
+pub async fn authenticate(req: &HttpRequest, token: &str) -> Result<Principal, Error> {
+    let auth_service = req
+        .app_data::<web::Data<Arc<AuthService>>>()
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("Auth service not configured"))?;
+
+    let token_cache = req.app_data::<web::Data<Arc<TokenCache>>>().cloned();
+    if let Some(principal) = token_cache.as_ref().and_then(|cache| cache.get(token)) {
+        return Ok(principal);
+    }
+    let generation = token_cache.as_ref().map(|cache| cache.generation());
+
+    let account = match auth_service.authenticate_access_token(token).await {
+        Ok(acc) => acc,
+        Err(IamError::TokenExpired | IamError::TokenNotFound | IamError::TokenRevoked) => {
+            return Err(actix_web::error::ErrorUnauthorized("Invalid or expired token"));
         }
+        Err(_) => return Err(actix_web::error::ErrorInternalServerError("Authentication failed")),
     };
 
-    let account = match auth_service
-        .authenticate_access_token(credentials.token())
+    let db = req
+        .app_data::<web::Data<DbContext>>()
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("Database not configured"))?;
+
+    let session_config = req
+        .app_data::<web::Data<SessionConfig>>()
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("Session configuration missing"))?;
+
+    // Tokens can be revoked app-side, e.g. from a new-device alert, and sessions
+    // end after their idle timeout or lifetime even while the token is valid
+    let valid_until = match db
+        .get_session_by_access_token_hash(&sessions::hash_token(token))
         .await
     {
-        Ok(acc) => acc,
-        Err(e) => {
-            let error = match e {
-                IamError::TokenExpired | IamError::TokenNotFound | IamError::TokenRevoked => {
-                    actix_web::error::ErrorUnauthorized("Invalid or expired token")
+        Ok(Some(session)) if session.revoked_at.is_some() => {
+            return Err(actix_web::error::ErrorUnauthorized("Session has been revoked"));
+        }
+        Ok(Some(session)) if session_config.is_expired(&session) => {
+            return Err(actix_web::error::ErrorUnauthorized("Session has expired"));
+        }
+        Ok(Some(session)) => {
+            if session_config.needs_touch(&session) {
+                let stale_before = Utc::now() - session_config.touch_interval;
+                if let Err(e) = db.touch_session(session.id, stale_before).await {
+                    log::error!("Failed to record session activity: {:?}", e);
                 }
-                _ => actix_web::error::ErrorInternalServerError("Authentication failed"),
-            };
-            return Err((error, req));
+            }
+            // Cached only until the token or the session runs out, whichever is
+            // first. Cache hits aren't recorded as activity, so the idle deadline
+            // is the one from before this request.
+            let session_valid_until = session_config.valid_until(&session);
+            Some(
+                session
+                    .access_token_expires_at
+                    .map_or(session_valid_until, |t| t.min(session_valid_until)),
+            )
+        }
+        Ok(None) => None,
+        Err(e) => {
+            log::error!("Failed to load session during authentication: {:?}", e);
+            return Err(actix_web::error::ErrorInternalServerError("Authentication failed"));
         }
     };
 
-    req.extensions_mut().insert(AuthenticatedUser {
-        account_id: account.id,
-        email: account.email,
-    });
+    // Load the app account alongside the token so handlers don't have to
+    let app_account = match db.get_account_by_iam_id(account.id).await {
+        Ok(app_account) => app_account,
+        Err(e) => {
+            log::error!("Failed to load account during authentication: {:?}", e);
+            return Err(actix_web::error::ErrorInternalServerError("Authentication failed"));
+        }
+    };
 
-    Ok(req)
+    let principal = Principal {
+        user: AuthenticatedUser {
+            account_id: account.id,
+            email: account.email,
+        },
+        account: app_account,
+    };
+
+    if let (Some(cache), Some(generation)) = (token_cache, generation) {
+        cache.insert(token, principal.clone(), generation, valid_until);
+    }
+    Ok(principal)
+}
+
+/// Routes still usable while an account is required to reset its password;
+/// the reset itself goes through the emailed code, not a signed-in session
+const PASSWORD_RESET_ALLOWED_PATHS: &[&str] = &[
+    "/api/auth/logout",
+    "/api/auth/me",
+];
+
+pub fn check_password_reset(path: &str, principal: &Principal) -> Result<(), Error> {
+    let reset_required = principal
+        .account
+        .as_ref()
+        .is_some_and(|account| account.password_reset_required_at.is_some());
+    if !reset_required || PASSWORD_RESET_ALLOWED_PATHS.contains(&path) {
+        return Ok(());
+    }
+
+    Err(actix_web::error::InternalError::from_response(
+        "Password reset required",
+        HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Password reset required",
+            "password_reset_required": true
+        })),
+    )
+    .into())
+}
+
+fn insert_principal(req: &ServiceRequest, principal: Principal) {
+    let mut extensions = req.extensions_mut();
+    extensions.insert(principal.user);
+    if let Some(account) = principal.account {
+        extensions.insert(account);
+    }
 }
 
 impl FromRequest for AuthenticatedUser {
@@ -65,3 +175,114 @@ impl FromRequest for AuthenticatedUser {
     }
 }
 
+/// The app account of the authenticated user.
+///
+/// Taken from the request extensions when the validator already loaded it,
+/// otherwise loaded from the database and provisioned if it doesn't exist yet.
+#[derive(Clone)]
+pub struct CurrentAccount(pub Account);
+
+impl FromRequest for CurrentAccount {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(
+        req: &actix_web::HttpRequest,
+        _payload: &mut actix_web::dev::Payload,
+    ) -> Self::Future {
+        if let Some(account) = req.extensions().get::<Account>().cloned() {
+            return Box::pin(ready(Ok(CurrentAccount(account))));
+        }
+
+        let user = req.extensions().get::<AuthenticatedUser>().cloned();
+        let db = req.app_data::<web::Data<DbContext>>().cloned();
+        let req = req.clone();
+
+        Box::pin(async move {
+            let user = user.ok_or_else(|| actix_web::error::ErrorUnauthorized("User not authenticated"))?;
+            let db = db.ok_or_else(|| actix_web::error::ErrorInternalServerError("Database not configured"))?;
+
+            match db
+                .get_or_create_account_by_iam_id(user.account_id, user.email.clone())
+                .await
+            {
+                Ok(account) => {
+                    req.extensions_mut().insert(account.clone());
+                    Ok(CurrentAccount(account))
+                }
+                Err(e) => {
+                    log::error!("Failed to get account: {:?}", e);
+                    Err(actix_web::error::InternalError::from_response(
+                        "Failed to get account",
+                        HttpResponse::InternalServerError().json(serde_json::json!({
+                            "error": "Failed to get account"
+                        })),
+                    )
+                    .into())
+                }
+            }
+        })
+    }
+}
+
+
+/// Administrator allowlist, configured via comma-separated `ADMIN_EMAILS`
+#[derive(Clone, Default)]
+pub struct AdminConfig {
+    emails: HashSet<String>,
+}
+
+impl AdminConfig {
+    pub fn from_env() -> Self {
+        let emails = env::var("ADMIN_EMAILS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|e| e.trim().to_lowercase())
+            .filter(|e| !e.is_empty())
+            .collect();
+        Self { emails }
+    }
+
+    pub fn is_admin(&self, email: &str) -> bool {
+        self.emails.contains(&email.to_lowercase())
+    }
+
+    /// Lowercased admin emails
+    pub fn emails(&self) -> Vec<String> {
+        self.emails.iter().cloned().collect()
+    }
+}
+
+/// Authenticated user whose email is in the admin allowlist
+#[derive(Clone)]
+pub struct AdminUser(pub AuthenticatedUser);
+
+impl FromRequest for AdminUser {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(
+        req: &actix_web::HttpRequest,
+        _payload: &mut actix_web::dev::Payload,
+    ) -> Self::Future {
+        let user = match req.extensions().get::<AuthenticatedUser>().cloned() {
+            Some(user) => user,
+            None => {
+                return ready(Err(actix_web::error::ErrorUnauthorized(
+                    "User not authenticated",
+                )))
+            }
+        };
+
+        let is_admin = req
+            .app_data::<web::Data<AdminConfig>>()
+            .map(|config| config.is_admin(&user.email))
+            .unwrap_or(false);
+
+        if is_admin {
+            ready(Ok(AdminUser(user)))
+        } else {
+            ready(Err(actix_web::error::ErrorForbidden("Admin access required")))
+        }
+    }
+}
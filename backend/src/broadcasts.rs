@@ -0,0 +1,120 @@
+use std::env;
+use std::time::{Duration, Instant};
+
+use crate::auth::AdminConfig;
+use crate::dba::DbContext;
+use crate::models::{BroadcastSegment, NotificationBroadcast};
+use crate::notifications::{self, Channel};
+
+/// Auth types a segment can select, as reported by nano-iam
+const AUTH_TYPES: &[&str] = &["email", "google"];
+
+const ROLES: &[&str] = &["admin", "user"];
+
+/// Broadcast delivery configuration
+#[derive(Clone)]
+pub struct BroadcastConfig {
+    /// Accounts notified, or notifications retracted, per statement
+    pub batch_size: i64,
+    /// How long one run of the job keeps working before leaving the rest for the next run
+    pub run_budget: Duration,
+}
+
+impl BroadcastConfig {
+    /// Create broadcast configuration from environment or defaults
+    pub fn from_env() -> Self {
+        Self {
+            batch_size: env::var("BROADCAST_BATCH_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1000),
+            run_budget: Duration::from_secs(
+                env::var("BROADCAST_RUN_BUDGET_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(30),
+            ),
+        }
+    }
+}
+
+/// Check a segment submitted by an admin
+pub fn validate_segment(segment: &BroadcastSegment) -> Result<(), String> {
+    if segment
+        .auth_type
+        .as_deref()
+        .is_some_and(|auth_type| !AUTH_TYPES.contains(&auth_type))
+    {
+        return Err("Invalid auth_type. Must be 'email' or 'google'".to_string());
+    }
+    if segment.role.as_deref().is_some_and(|role| !ROLES.contains(&role)) {
+        return Err("Invalid role. Must be 'admin' or 'user'".to_string());
+    }
+    if let (Some(after), Some(before)) = (segment.signed_up_after, segment.signed_up_before) {
+        if after >= before {
+            return Err("signed_up_after must be before signed_up_before".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Deliver or retract broadcasts in batches until there is nothing left or the run budget is spent
+pub async fn process_broadcasts(db: &DbContext, admin_config: &AdminConfig, config: &BroadcastConfig) {
+    let started = Instant::now();
+    let admin_emails = admin_config.emails();
+
+    while started.elapsed() < config.run_budget {
+        let broadcast = match db.get_active_notification_broadcast().await {
+            Ok(Some(broadcast)) => broadcast,
+            Ok(None) => return,
+            Err(e) => {
+                log::error!("Failed to get active notification broadcast: {:?}", e);
+                return;
+            }
+        };
+
+        let result = if broadcast.status == "retracting" {
+            db.retract_notification_broadcast_batch(broadcast.id, config.batch_size)
+                .await
+        } else {
+            deliver_batch(db, &broadcast, &admin_emails, config).await
+        };
+        match result {
+            Ok(Some(status)) if status == "completed" || status == "retracted" => {
+                log::info!("Notification broadcast {} {}", broadcast.id, status);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                log::error!("Failed to process notification broadcast {}: {:?}", broadcast.id, e);
+                return;
+            }
+        }
+    }
+}
+
+async fn deliver_batch(
+    db: &DbContext,
+    broadcast: &NotificationBroadcast,
+    admin_emails: &[String],
+    config: &BroadcastConfig,
+) -> Result<Option<String>, sqlx::Error> {
+    let segment: BroadcastSegment =
+        serde_json::from_value(broadcast.segment.clone()).unwrap_or_default();
+    let (default_in_app, mandatory_in_app) = match notifications::kind(&broadcast.kind) {
+        Some(kind) => (
+            kind.default_channels.contains(&Channel::InApp),
+            kind.mandatory_channels.contains(&Channel::InApp),
+        ),
+        None => (true, false),
+    };
+
+    db.deliver_notification_broadcast_batch(
+        broadcast,
+        &segment,
+        admin_emails,
+        default_in_app,
+        mandatory_in_app,
+        config.batch_size,
+    )
+    .await
+}
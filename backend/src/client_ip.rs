@@ -0,0 +1,269 @@
+use actix_web::http::header::HeaderMap;
+use actix_web::{web, Error, FromRequest, HttpRequest};
+use ipnet::IpNet;
+use std::env;
+use std::future::{ready, Ready};
+use std::net::{IpAddr, SocketAddr};
+
+/// Proxies whose forwarding headers are believed, configured via
+/// comma-separated CIDRs in `TRUSTED_PROXIES`
+#[derive(Clone)]
+pub struct TrustedProxies {
+    networks: Vec<IpNet>,
+    /// Whether the proxies rewrite the RFC 7239 `Forwarded` header. Off by
+    /// default: the bundled nginx only sets `X-Forwarded-For`, so a `Forwarded`
+    /// header reaching us was written by the client.
+    honour_forwarded: bool,
+}
+
+impl TrustedProxies {
+    /// Create trusted proxy configuration from environment, trusting only loopback by default
+    pub fn from_env() -> Self {
+        let value = env::var("TRUSTED_PROXIES").unwrap_or_else(|_| "127.0.0.0/8,::1/128".to_string());
+        let networks = value
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| {
+                // Accept bare addresses as single-host networks
+                let parsed = s
+                    .parse::<IpNet>()
+                    .or_else(|_| s.parse::<IpAddr>().map(IpNet::from));
+                if parsed.is_err() {
+                    log::error!("Ignoring invalid TRUSTED_PROXIES entry: {}", s);
+                }
+                parsed.ok()
+            })
+            .collect();
+        let honour_forwarded = env::var("TRUST_FORWARDED_HEADER")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(false);
+        Self {
+            networks,
+            honour_forwarded,
+        }
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        let ip = canonical(*ip);
+        self.networks.iter().any(|net| net.contains(&ip))
+    }
+}
+
+/// Resolve the address of the client that made a request.
+///
+/// Forwarding headers are only honoured when the connection comes from a
+/// trusted proxy. The hop list in `X-Forwarded-For` (or `Forwarded`, when
+/// `TRUST_FORWARDED_HEADER` says the proxies rewrite it) is walked from the
+/// nearest hop backwards, skipping trusted proxies, so a client can't spoof its
+/// address by sending the headers itself.
+pub fn resolve(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = canonical(req.peer_addr()?.ip());
+    let trusted = match req.app_data::<web::Data<TrustedProxies>>() {
+        Some(trusted) => trusted,
+        None => return Some(peer),
+    };
+    if !trusted.contains(&peer) {
+        return Some(peer);
+    }
+
+    let headers = req.headers();
+    let hops = if trusted.honour_forwarded {
+        forwarded_for(headers).or_else(|| x_forwarded_for(headers))
+    } else {
+        x_forwarded_for(headers)
+    };
+    if let Some(hops) = hops {
+        let mut nearest = peer;
+        for hop in hops.iter().rev() {
+            match hop {
+                Some(ip) if trusted.contains(ip) => nearest = *ip,
+                Some(ip) => return Some(*ip),
+                // An obfuscated or garbled hop; nothing before it can be trusted
+                None => return Some(nearest),
+            }
+        }
+        return Some(nearest);
+    }
+
+    let real_ip = headers
+        .get("X-Real-IP")
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_node);
+    Some(real_ip.unwrap_or(peer))
+}
+
+/// `for=` parameters of the RFC 7239 `Forwarded` header, in hop order
+fn forwarded_for(headers: &HeaderMap) -> Option<Vec<Option<IpAddr>>> {
+    let mut hops = Vec::new();
+    for value in headers.get_all("Forwarded") {
+        let value = value.to_str().ok()?;
+        for element in value.split(',') {
+            let node = element.split(';').find_map(|pair| {
+                let (key, value) = pair.split_once('=')?;
+                key.trim().eq_ignore_ascii_case("for").then(|| value.trim())
+            });
+            if let Some(node) = node {
+                hops.push(parse_node(node));
+            }
+        }
+    }
+    (!hops.is_empty()).then_some(hops)
+}
+
+/// Entries of `X-Forwarded-For`, in hop order
+fn x_forwarded_for(headers: &HeaderMap) -> Option<Vec<Option<IpAddr>>> {
+    let mut hops = Vec::new();
+    for value in headers.get_all("X-Forwarded-For") {
+        let value = value.to_str().ok()?;
+        hops.extend(value.split(',').map(|node| parse_node(node.trim())));
+    }
+    (!hops.is_empty()).then_some(hops)
+}
+
+/// Parse a node such as `192.0.2.1`, `192.0.2.1:443`, `"[2001:db8::1]:443"` or `2001:db8::1`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| {
+            node.strip_prefix('[')
+                .and_then(|rest| rest.split_once(']'))
+                .and_then(|(ip, _)| ip.parse().ok())
+        })
+        .map(canonical)
+}
+
+/// Treat IPv4-mapped IPv6 addresses as the IPv4 address they carry
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        ip => ip,
+    }
+}
+
+/// The real client address, resolved through trusted proxies
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub IpAddr);
+
+impl FromRequest for ClientIp {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
+        ready(
+            resolve(req)
+                .map(ClientIp)
+                .ok_or_else(|| actix_web::error::ErrorBadRequest("Client address unavailable")),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn trusted(networks: &[&str], honour_forwarded: bool) -> web::Data<TrustedProxies> {
+        web::Data::new(TrustedProxies {
+            networks: networks.iter().map(|n| n.parse().unwrap()).collect(),
+            honour_forwarded,
+        })
+    }
+
+    fn request(peer: &str, headers: &[(&str, &str)]) -> HttpRequest {
+        request_with(peer, headers, false)
+    }
+
+    fn request_with(peer: &str, headers: &[(&str, &str)], honour_forwarded: bool) -> HttpRequest {
+        let mut req = TestRequest::default()
+            .peer_addr(peer.parse().unwrap())
+            .app_data(trusted(&["10.0.0.0/8"], honour_forwarded));
+        for (name, value) in headers {
+            req = req.insert_header((*name, *value));
+        }
+        req.to_http_request()
+    }
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn ignores_headers_from_untrusted_peers() {
+        let req = request("203.0.113.9:1234", &[("X-Forwarded-For", "198.51.100.1")]);
+        assert_eq!(resolve(&req), ip("203.0.113.9"));
+    }
+
+    #[test]
+    fn uses_peer_without_trusted_proxy_configuration() {
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:1234".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "198.51.100.1"))
+            .to_http_request();
+        assert_eq!(resolve(&req), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn walks_back_over_trusted_hops() {
+        let req = request(
+            "10.0.0.1:1234",
+            &[("X-Forwarded-For", "198.51.100.1, 203.0.113.7, 10.0.0.2")],
+        );
+        assert_eq!(resolve(&req), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn ignores_spoofed_leftmost_entries() {
+        // The client sent its own X-Forwarded-For claiming to be 127.0.0.1;
+        // the trusted proxy appended the real address
+        let req = request("10.0.0.1:1234", &[("X-Forwarded-For", "127.0.0.1, 198.51.100.1")]);
+        assert_eq!(resolve(&req), ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn stops_at_garbled_hops() {
+        let req = request("10.0.0.1:1234", &[("X-Forwarded-For", "198.51.100.1, garbage, 10.0.0.2")]);
+        assert_eq!(resolve(&req), ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn ignores_forwarded_unless_enabled() {
+        // nginx passes a client-supplied Forwarded header through untouched
+        let req = request(
+            "10.0.0.1:1234",
+            &[("Forwarded", "for=1.2.3.4"), ("X-Forwarded-For", "203.0.113.7")],
+        );
+        assert_eq!(resolve(&req), ip("203.0.113.7"));
+
+        let req = request("10.0.0.1:1234", &[("Forwarded", "for=1.2.3.4"), ("X-Real-IP", "198.51.100.1")]);
+        assert_eq!(resolve(&req), ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn prefers_forwarded_over_x_forwarded_for_when_enabled() {
+        let req = request_with(
+            "10.0.0.1:1234",
+            &[
+                ("Forwarded", "for=198.51.100.1;proto=https, for=\"[2001:db8::1]:443\""),
+                ("X-Forwarded-For", "203.0.113.7"),
+            ],
+            true,
+        );
+        assert_eq!(resolve(&req), ip("2001:db8::1"));
+    }
+
+    #[test]
+    fn falls_back_to_x_real_ip() {
+        let req = request("10.0.0.1:1234", &[("X-Real-IP", "198.51.100.1")]);
+        assert_eq!(resolve(&req), ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn treats_ipv4_mapped_addresses_as_ipv4() {
+        let req = request("[::ffff:10.0.0.1]:1234", &[("X-Forwarded-For", "::ffff:198.51.100.1")]);
+        assert_eq!(resolve(&req), ip("198.51.100.1"));
+    }
+}
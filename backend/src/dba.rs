@@ -1,9 +1,19 @@
 use sqlx::PgPool;
 use std::env;
 use nano_iam::Repo;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
-use crate::models::{Account, Notification};
+use crate::audit::{AuditEntry, RequestMeta};
+use crate::sessions::SessionPolicy;
+use crate::models::{
+    Account, AuditEvent, AuditEventsQuery, BroadcastSegment, DataExport, DigestSchedule,
+    DigestSettings, InviteCode, KnownDevice, Notification, NotificationBroadcast,
+    NotificationDigestItem, NotificationEvent, NotificationFilter, NotificationPreference,
+    NotificationSettings, NotificationsQuery, OnlineAccount, Session, WaitlistEntry,
+};
+use crate::notification_stream::NOTIFICATION_EVENTS_CHANNEL;
+use crate::notifications::{NewNotification, NotificationCursor};
+use crate::retention::RetentionRule;
 
 /// Database connection configuration
 pub struct DbConfig {
@@ -109,7 +119,9 @@ impl DbContext {
             r#"
             INSERT INTO app_accounts (id, iam_account_id, display_name, created_at, updated_at)
             VALUES ($1, $2, $3, $4, $4)
-            RETURNING id, iam_account_id, display_name, avatar_url, username, created_at, updated_at
+            RETURNING id, iam_account_id, display_name, avatar_url, username,
+                   deletion_requested_at, deletion_scheduled_for, password_reset_required_at,
+                   created_at, updated_at
             "#,
         )
         .bind(Uuid::new_v4())
@@ -127,7 +139,9 @@ impl DbContext {
     ) -> Result<Option<Account>, sqlx::Error> {
         sqlx::query_as::<_, Account>(
             r#"
-            SELECT id, iam_account_id, display_name, avatar_url, username, created_at, updated_at
+            SELECT id, iam_account_id, display_name, avatar_url, username,
+                   deletion_requested_at, deletion_scheduled_for, password_reset_required_at,
+                   created_at, updated_at
             FROM app_accounts
             WHERE iam_account_id = $1
             "#,
@@ -137,6 +151,22 @@ impl DbContext {
         .await
     }
 
+    /// Get account by ID
+    pub async fn get_account_by_id(&self, account_id: Uuid) -> Result<Option<Account>, sqlx::Error> {
+        sqlx::query_as::<_, Account>(
+            r#"
+            SELECT id, iam_account_id, display_name, avatar_url, username,
+                   deletion_requested_at, deletion_scheduled_for, password_reset_required_at,
+                   created_at, updated_at
+            FROM app_accounts
+            WHERE id = $1
+            "#,
+        )
+        .bind(account_id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
     /// Get account by IAM account ID, or create it if it doesn't exist
     pub async fn get_or_create_account_by_iam_id(
         &self,
@@ -152,43 +182,201 @@ impl DbContext {
         self.create_account(iam_account_id, display_name).await
     }
 
-    /// Delete account by IAM account ID
-    pub async fn delete_account_by_iam_id(
+    /// Mark an account as pending deletion until the given time
+    pub async fn schedule_account_deletion(
+        &self,
+        iam_account_id: Uuid,
+        scheduled_for: DateTime<Utc>,
+    ) -> Result<Account, sqlx::Error> {
+        sqlx::query_as::<_, Account>(
+            r#"
+            UPDATE app_accounts
+            SET deletion_requested_at = $1, deletion_scheduled_for = $2, updated_at = $1
+            WHERE iam_account_id = $3
+            RETURNING id, iam_account_id, display_name, avatar_url, username,
+                   deletion_requested_at, deletion_scheduled_for, password_reset_required_at,
+                   created_at, updated_at
+            "#,
+        )
+        .bind(Utc::now())
+        .bind(scheduled_for)
+        .bind(iam_account_id)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Clear a pending deletion
+    pub async fn cancel_account_deletion(
+        &self,
+        iam_account_id: Uuid,
+    ) -> Result<Account, sqlx::Error> {
+        sqlx::query_as::<_, Account>(
+            r#"
+            UPDATE app_accounts
+            SET deletion_requested_at = NULL, deletion_scheduled_for = NULL, updated_at = $1
+            WHERE iam_account_id = $2
+            RETURNING id, iam_account_id, display_name, avatar_url, username,
+                   deletion_requested_at, deletion_scheduled_for, password_reset_required_at,
+                   created_at, updated_at
+            "#,
+        )
+        .bind(Utc::now())
+        .bind(iam_account_id)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Get accounts whose deletion grace period has expired
+    pub async fn get_accounts_due_for_purge(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<Account>, sqlx::Error> {
+        sqlx::query_as::<_, Account>(
+            r#"
+            SELECT id, iam_account_id, display_name, avatar_url, username,
+                   deletion_requested_at, deletion_scheduled_for, password_reset_required_at,
+                   created_at, updated_at
+            FROM app_accounts
+            WHERE deletion_scheduled_for IS NOT NULL AND deletion_scheduled_for <= $1
+            ORDER BY deletion_scheduled_for
+            LIMIT $2
+            "#,
+        )
+        .bind(Utc::now())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Permanently delete an account from both our tables and nano-iam
+    pub async fn purge_account(
         &self,
+        account_id: Uuid,
         iam_account_id: Uuid,
     ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // Notifications cascade from app_accounts
         sqlx::query(
             r#"
             DELETE FROM app_accounts
-            WHERE iam_account_id = $1
+            WHERE id = $1
+            "#,
+        )
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            DELETE FROM accounts
+            WHERE id = $1
             "#,
         )
         .bind(iam_account_id)
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
-        Ok(())
+
+        tx.commit().await
     }
 
-    /// Create a new notification
+    /// Create a new notification, or collapse it into a recent repeat in the same group
     pub async fn create_notification(
         &self,
         account_id: Uuid,
-        level: &str,
-        message: &str,
+        new: &NewNotification,
     ) -> Result<Notification, sqlx::Error> {
-        sqlx::query_as::<_, Notification>(
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+
+        if let (Some(group_key), Some(window)) = (&new.group_key, new.dedupe_window) {
+            // Serialize repeats of one group so they can't both miss the other and insert
+            sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+                .bind(format!("notification-group:{}:{}", account_id, group_key))
+                .execute(&mut *tx)
+                .await?;
+
+            let collapsed = sqlx::query_as::<_, Notification>(
+                r#"
+                UPDATE notifications
+                SET level = $1, title = $2, message = $3, data = $4, action_url = $5, actions = $6,
+                    source = $7, expires_at = COALESCE($8, expires_at),
+                    occurrences = occurrences + 1, last_occurred_at = $9, read = false, updated_at = $9
+                WHERE id = (
+                    SELECT id FROM notifications
+                    WHERE account_id = $10 AND group_key = $11
+                      AND archived_at IS NULL
+                      AND (expires_at IS NULL OR expires_at > $9)
+                      AND created_at > $12
+                    ORDER BY created_at DESC
+                    LIMIT 1
+                )
+                RETURNING id, account_id, kind, level, title, message, data, action_url, actions, source, read,
+                          group_key, occurrences, last_occurred_at, expires_at, archived_at,
+                          created_at, updated_at
+                "#,
+            )
+            .bind(new.level())
+            .bind(&new.title)
+            .bind(&new.message)
+            .bind(&new.data)
+            .bind(&new.action_url)
+            .bind(serde_json::json!(new.actions))
+            .bind(&new.source)
+            .bind(new.expires_at)
+            .bind(now)
+            .bind(account_id)
+            .bind(group_key)
+            .bind(now - window)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            if let Some(notification) = collapsed {
+                tx.commit().await?;
+                self.publish_notification_event(
+                    account_id,
+                    "updated",
+                    serde_json::json!({ "notifications": [&notification] }),
+                )
+                .await;
+                return Ok(notification);
+            }
+        }
+
+        let notification = sqlx::query_as::<_, Notification>(
             r#"
-            INSERT INTO notifications (account_id, level, message, read, created_at, updated_at)
-            VALUES ($1, $2, $3, false, $4, $4)
-            RETURNING id, account_id, level, message, read, created_at, updated_at
+            INSERT INTO notifications (account_id, kind, level, title, message, data, action_url,
+                                       actions, source, expires_at, group_key, read, created_at,
+                                       updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $11, $12, false, $10, $10)
+            RETURNING id, account_id, kind, level, title, message, data, action_url, actions, source, read,
+                      group_key, occurrences, last_occurred_at, expires_at, archived_at,
+                      created_at, updated_at
             "#,
         )
         .bind(account_id)
-        .bind(level)
-        .bind(message)
-        .bind(Utc::now())
-        .fetch_one(&self.pool)
-        .await
+        .bind(&new.kind)
+        .bind(new.level())
+        .bind(&new.title)
+        .bind(&new.message)
+        .bind(&new.data)
+        .bind(&new.action_url)
+        .bind(serde_json::json!(new.actions))
+        .bind(&new.source)
+        .bind(now)
+        .bind(new.expires_at)
+        .bind(&new.group_key)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.publish_notification_event(
+            account_id,
+            "created",
+            serde_json::json!({ "notification": notification }),
+        )
+        .await;
+        Ok(notification)
     }
 
     /// Get all notifications for an account
@@ -198,7 +386,9 @@ impl DbContext {
     ) -> Result<Vec<Notification>, sqlx::Error> {
         sqlx::query_as::<_, Notification>(
             r#"
-            SELECT id, account_id, level, message, read, created_at, updated_at
+            SELECT id, account_id, kind, level, title, message, data, action_url, actions, source, read,
+                   group_key, occurrences, last_occurred_at, expires_at, archived_at,
+                   created_at, updated_at
             FROM notifications
             WHERE account_id = $1
             ORDER BY created_at DESC
@@ -209,6 +399,146 @@ impl DbContext {
         .await
     }
 
+    /// Get one page of an account's notifications, most recently occurred first
+    pub async fn get_notifications_page(
+        &self,
+        account_id: Uuid,
+        query: &NotificationsQuery,
+        cursor: Option<NotificationCursor>,
+        limit: i64,
+    ) -> Result<Vec<Notification>, sqlx::Error> {
+        sqlx::query_as::<_, Notification>(
+            r#"
+            SELECT id, account_id, kind, level, title, message, data, action_url, actions, source, read,
+                   group_key, occurrences, last_occurred_at, expires_at, archived_at,
+                   created_at, updated_at
+            FROM notifications
+            WHERE account_id = $1
+              AND ($2::text IS NULL OR level = $2)
+              AND ($3::boolean IS NULL OR read = $3)
+              AND ($4::timestamptz IS NULL OR created_at >= $4)
+              AND ($5::timestamptz IS NULL OR created_at < $5)
+              AND ($6::timestamptz IS NULL OR (COALESCE(last_occurred_at, created_at), id) < ($6, $7))
+              AND ($9::text IS NULL OR kind = $9)
+              AND (archived_at IS NOT NULL) = COALESCE($10, false)
+              AND (expires_at IS NULL OR expires_at > $11)
+              AND ($12::text IS NULL OR group_key = $12)
+            ORDER BY COALESCE(last_occurred_at, created_at) DESC, id DESC
+            LIMIT $8
+            "#,
+        )
+        .bind(account_id)
+        .bind(&query.level)
+        .bind(query.read)
+        .bind(query.since)
+        .bind(query.until)
+        .bind(cursor.map(|c| c.occurred_at))
+        .bind(cursor.map(|c| c.id))
+        .bind(limit)
+        .bind(&query.kind)
+        .bind(query.archived)
+        .bind(Utc::now())
+        .bind(&query.group_key)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Get one page of an account's notification groups, most recently occurred first.
+    ///
+    /// Each group is represented by its latest notification, with `group_size`
+    /// set; notifications without a group key are groups of their own.
+    pub async fn get_notification_groups_page(
+        &self,
+        account_id: Uuid,
+        query: &NotificationsQuery,
+        cursor: Option<NotificationCursor>,
+        limit: i64,
+    ) -> Result<Vec<Notification>, sqlx::Error> {
+        sqlx::query_as::<_, Notification>(
+            r#"
+            WITH matching AS (
+                SELECT id, account_id, kind, level, title, message, data, action_url, actions, source, read,
+                       group_key, occurrences, last_occurred_at, expires_at, archived_at,
+                       created_at, updated_at,
+                       ROW_NUMBER() OVER groups AS position,
+                       COUNT(*) OVER (PARTITION BY COALESCE(group_key, id::text)) AS group_size
+                FROM notifications
+                WHERE account_id = $1
+                  AND ($2::text IS NULL OR level = $2)
+                  AND ($3::boolean IS NULL OR read = $3)
+                  AND ($4::timestamptz IS NULL OR created_at >= $4)
+                  AND ($5::timestamptz IS NULL OR created_at < $5)
+                  AND ($9::text IS NULL OR kind = $9)
+                  AND (archived_at IS NOT NULL) = COALESCE($10, false)
+                  AND (expires_at IS NULL OR expires_at > $11)
+                WINDOW groups AS (PARTITION BY COALESCE(group_key, id::text)
+                                  ORDER BY COALESCE(last_occurred_at, created_at) DESC, id DESC)
+            )
+            SELECT id, account_id, kind, level, title, message, data, action_url, actions, source, read,
+                   group_key, occurrences, last_occurred_at, expires_at, archived_at,
+                   created_at, updated_at, group_size
+            FROM matching
+            WHERE position = 1
+              AND ($6::timestamptz IS NULL OR (COALESCE(last_occurred_at, created_at), id) < ($6, $7))
+            ORDER BY COALESCE(last_occurred_at, created_at) DESC, id DESC
+            LIMIT $8
+            "#,
+        )
+        .bind(account_id)
+        .bind(&query.level)
+        .bind(query.read)
+        .bind(query.since)
+        .bind(query.until)
+        .bind(cursor.map(|c| c.occurred_at))
+        .bind(cursor.map(|c| c.id))
+        .bind(limit)
+        .bind(&query.kind)
+        .bind(query.archived)
+        .bind(Utc::now())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Count an account's notifications, or groups with `grouped`, matching a query, and how
+    /// many of the notifications are unread
+    pub async fn count_notifications(
+        &self,
+        account_id: Uuid,
+        query: &NotificationsQuery,
+    ) -> Result<(i64, i64), sqlx::Error> {
+        sqlx::query_as::<_, (i64, i64)>(
+            r#"
+            SELECT CASE
+                       WHEN $9 THEN COUNT(DISTINCT COALESCE(group_key, id::text))
+                                    FILTER (WHERE $3::boolean IS NULL OR read = $3)
+                       ELSE COUNT(*) FILTER (WHERE $3::boolean IS NULL OR read = $3)
+                   END,
+                   COUNT(*) FILTER (WHERE NOT read)
+            FROM notifications
+            WHERE account_id = $1
+              AND ($2::text IS NULL OR level = $2)
+              AND ($4::timestamptz IS NULL OR created_at >= $4)
+              AND ($5::timestamptz IS NULL OR created_at < $5)
+              AND ($6::text IS NULL OR kind = $6)
+              AND (archived_at IS NOT NULL) = COALESCE($7, false)
+              AND (expires_at IS NULL OR expires_at > $8)
+              AND ($10::text IS NULL OR group_key = $10)
+            "#,
+        )
+        .bind(account_id)
+        .bind(&query.level)
+        .bind(query.read)
+        .bind(query.since)
+        .bind(query.until)
+        .bind(&query.kind)
+        .bind(query.archived)
+        .bind(Utc::now())
+        .bind(query.grouped.unwrap_or(false))
+        .bind(&query.group_key)
+        .fetch_one(&self.pool)
+        .await
+    }
+
     /// Get unread notifications count for an account
     pub async fn get_unread_count(
         &self,
@@ -217,36 +547,55 @@ impl DbContext {
         let result = sqlx::query_scalar::<_, i64>(
             r#"
             SELECT COUNT(*) FROM notifications
-            WHERE account_id = $1 AND read = false
+            WHERE account_id = $1 AND read = false AND archived_at IS NULL
+              AND (expires_at IS NULL OR expires_at > $2)
             "#,
         )
         .bind(account_id)
+        .bind(Utc::now())
         .fetch_one(&self.pool)
         .await?;
         Ok(result)
     }
 
-    /// Update notification read status
-    pub async fn update_notification_read(
+    /// Mark a notification read or unread, and archive or unarchive it; unset fields are kept
+    pub async fn update_notification(
         &self,
         notification_id: Uuid,
         account_id: Uuid,
-        read: bool,
+        read: Option<bool>,
+        archived: Option<bool>,
     ) -> Result<Notification, sqlx::Error> {
-        sqlx::query_as::<_, Notification>(
+        let notification = sqlx::query_as::<_, Notification>(
             r#"
             UPDATE notifications
-            SET read = $1, updated_at = $2
+            SET read = COALESCE($1, read),
+                archived_at = CASE
+                    WHEN $5::boolean IS NULL THEN archived_at
+                    WHEN $5 THEN COALESCE(archived_at, $2)
+                END,
+                updated_at = $2
             WHERE id = $3 AND account_id = $4
-            RETURNING id, account_id, level, message, read, created_at, updated_at
+            RETURNING id, account_id, kind, level, title, message, data, action_url, actions, source, read,
+                      group_key, occurrences, last_occurred_at, expires_at, archived_at,
+                      created_at, updated_at
             "#,
         )
         .bind(read)
         .bind(Utc::now())
         .bind(notification_id)
         .bind(account_id)
+        .bind(archived)
         .fetch_one(&self.pool)
-        .await
+        .await?;
+
+        self.publish_notification_event(
+            account_id,
+            "updated",
+            serde_json::json!({ "notifications": [&notification] }),
+        )
+        .await;
+        Ok(notification)
     }
 
     /// Mark multiple notifications as read/unread
@@ -256,12 +605,14 @@ impl DbContext {
         account_id: Uuid,
         read: bool,
     ) -> Result<Vec<Notification>, sqlx::Error> {
-        sqlx::query_as::<_, Notification>(
+        let notifications = sqlx::query_as::<_, Notification>(
             r#"
             UPDATE notifications
             SET read = $1, updated_at = $2
             WHERE id = ANY($3) AND account_id = $4
-            RETURNING id, account_id, level, message, read, created_at, updated_at
+            RETURNING id, account_id, kind, level, title, message, data, action_url, actions, source, read,
+                      group_key, occurrences, last_occurred_at, expires_at, archived_at,
+                      created_at, updated_at
             "#,
         )
         .bind(read)
@@ -269,7 +620,17 @@ impl DbContext {
         .bind(notification_ids)
         .bind(account_id)
         .fetch_all(&self.pool)
-        .await
+        .await?;
+
+        if !notifications.is_empty() {
+            self.publish_notification_event(
+                account_id,
+                "updated",
+                serde_json::json!({ "notifications": notifications }),
+            )
+            .await;
+        }
+        Ok(notifications)
     }
 
     /// Delete a single notification
@@ -278,7 +639,7 @@ impl DbContext {
         notification_id: Uuid,
         account_id: Uuid,
     ) -> Result<(), sqlx::Error> {
-        sqlx::query(
+        let result = sqlx::query(
             r#"
             DELETE FROM notifications
             WHERE id = $1 AND account_id = $2
@@ -288,6 +649,15 @@ impl DbContext {
         .bind(account_id)
         .execute(&self.pool)
         .await?;
+
+        if result.rows_affected() > 0 {
+            self.publish_notification_event(
+                account_id,
+                "deleted",
+                serde_json::json!({ "ids": [notification_id] }),
+            )
+            .await;
+        }
         Ok(())
     }
 
@@ -297,14 +667,273 @@ impl DbContext {
         notification_ids: &[Uuid],
         account_id: Uuid,
     ) -> Result<u64, sqlx::Error> {
-        let result = sqlx::query(
+        let deleted = sqlx::query_scalar::<_, Uuid>(
             r#"
             DELETE FROM notifications
             WHERE id = ANY($1) AND account_id = $2
+            RETURNING id
             "#,
         )
         .bind(notification_ids)
         .bind(account_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        if !deleted.is_empty() {
+            self.publish_notification_event(
+                account_id,
+                "deleted",
+                serde_json::json!({ "ids": deleted }),
+            )
+            .await;
+        }
+        Ok(deleted.len() as u64)
+    }
+
+    /// Mark every notification matching a filter read or unread, in batches
+    /// so each stream event stays small. Returns how many changed.
+    pub async fn update_notifications_read_matching(
+        &self,
+        account_id: Uuid,
+        filter: &NotificationFilter,
+        read: bool,
+        batch_size: i64,
+    ) -> Result<u64, sqlx::Error> {
+        let mut total = 0;
+        loop {
+            let notifications = sqlx::query_as::<_, Notification>(
+                r#"
+                UPDATE notifications
+                SET read = $1, updated_at = $2
+                WHERE id IN (
+                    SELECT id FROM notifications
+                    WHERE account_id = $3
+                      AND read <> $1
+                      AND ($4::text IS NULL OR kind = $4)
+                      AND ($5::text IS NULL OR level = $5)
+                      AND ($6::boolean IS NULL OR read = $6)
+                      AND ($7::timestamptz IS NULL OR created_at < $7)
+                      AND (archived_at IS NOT NULL) = COALESCE($8, false)
+                      AND (expires_at IS NULL OR expires_at > $2)
+                    LIMIT $9
+                )
+                RETURNING id, account_id, kind, level, title, message, data, action_url, actions, source, read,
+                          group_key, occurrences, last_occurred_at, expires_at, archived_at,
+                          created_at, updated_at
+                "#,
+            )
+            .bind(read)
+            .bind(Utc::now())
+            .bind(account_id)
+            .bind(&filter.kind)
+            .bind(&filter.level)
+            .bind(filter.read)
+            .bind(filter.before)
+            .bind(filter.archived)
+            .bind(batch_size)
+            .fetch_all(&self.pool)
+            .await?;
+
+            let count = notifications.len();
+            if count > 0 {
+                self.publish_notification_event(
+                    account_id,
+                    "updated",
+                    serde_json::json!({ "notifications": notifications }),
+                )
+                .await;
+            }
+            total += count as u64;
+            if (count as i64) < batch_size {
+                return Ok(total);
+            }
+        }
+    }
+
+    /// Delete every notification matching a filter, in batches so each stream
+    /// event stays small. Returns how many were deleted.
+    pub async fn delete_notifications_matching(
+        &self,
+        account_id: Uuid,
+        filter: &NotificationFilter,
+        batch_size: i64,
+    ) -> Result<u64, sqlx::Error> {
+        let mut total = 0;
+        loop {
+            let deleted = sqlx::query_scalar::<_, Uuid>(
+                r#"
+                DELETE FROM notifications
+                WHERE id IN (
+                    SELECT id FROM notifications
+                    WHERE account_id = $1
+                      AND ($2::text IS NULL OR kind = $2)
+                      AND ($3::text IS NULL OR level = $3)
+                      AND ($4::boolean IS NULL OR read = $4)
+                      AND ($5::timestamptz IS NULL OR created_at < $5)
+                      AND (archived_at IS NOT NULL) = COALESCE($6, false)
+                      AND (expires_at IS NULL OR expires_at > $7)
+                    LIMIT $8
+                )
+                RETURNING id
+                "#,
+            )
+            .bind(account_id)
+            .bind(&filter.kind)
+            .bind(&filter.level)
+            .bind(filter.read)
+            .bind(filter.before)
+            .bind(filter.archived)
+            .bind(Utc::now())
+            .bind(batch_size)
+            .fetch_all(&self.pool)
+            .await?;
+
+            let count = deleted.len();
+            if count > 0 {
+                self.publish_notification_event(
+                    account_id,
+                    "deleted",
+                    serde_json::json!({ "ids": deleted }),
+                )
+                .await;
+            }
+            total += count as u64;
+            if (count as i64) < batch_size {
+                return Ok(total);
+            }
+        }
+    }
+
+    /// Record a change to an account's notifications and wake up its open streams.
+    ///
+    /// Failures are logged rather than returned: the change itself has already
+    /// been made, and streams fall back to the unread count on reconnect.
+    async fn publish_notification_event(
+        &self,
+        account_id: Uuid,
+        event_type: &str,
+        mut data: serde_json::Value,
+    ) {
+        let result: Result<(), sqlx::Error> = async {
+            data["unread_count"] = self.get_unread_count(account_id).await?.into();
+            let event_id = sqlx::query_scalar::<_, i64>(
+                r#"
+                INSERT INTO notification_events (account_id, event_type, data, created_at)
+                VALUES ($1, $2, $3, $4)
+                RETURNING id
+                "#,
+            )
+            .bind(account_id)
+            .bind(event_type)
+            .bind(&data)
+            .bind(Utc::now())
+            .fetch_one(&self.pool)
+            .await?;
+
+            let payload = serde_json::json!({ "id": event_id, "account_id": account_id });
+            self.notify(NOTIFICATION_EVENTS_CHANNEL, &payload.to_string()).await
+        }
+        .await;
+
+        if let Err(e) = result {
+            log::error!("Failed to publish notification event: {:?}", e);
+        }
+    }
+
+    /// Get a single notification event
+    pub async fn get_notification_event(
+        &self,
+        event_id: i64,
+    ) -> Result<Option<NotificationEvent>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationEvent>(
+            r#"
+            SELECT id, account_id, event_type, data, created_at
+            FROM notification_events
+            WHERE id = $1
+            "#,
+        )
+        .bind(event_id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Get an account's notification events after a given event, oldest first
+    pub async fn get_notification_events_after(
+        &self,
+        account_id: Uuid,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<NotificationEvent>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationEvent>(
+            r#"
+            SELECT id, account_id, event_type, data, created_at
+            FROM notification_events
+            WHERE account_id = $1 AND id > $2
+            ORDER BY id
+            LIMIT $3
+            "#,
+        )
+        .bind(account_id)
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// An account's events with IDs below `before_id` that were written within
+    /// `lookback_secs` of it. IDs are assigned before commit, so these may have
+    /// become visible only after `before_id` was delivered.
+    pub async fn get_notification_events_near(
+        &self,
+        account_id: Uuid,
+        before_id: i64,
+        lookback_secs: f64,
+        limit: i64,
+    ) -> Result<Vec<NotificationEvent>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationEvent>(
+            r#"
+            SELECT id, account_id, event_type, data, created_at
+            FROM notification_events
+            WHERE account_id = $1 AND id < $2
+              AND created_at >= (SELECT created_at FROM notification_events WHERE id = $2)
+                                - make_interval(secs => $3)
+            ORDER BY id
+            LIMIT $4
+            "#,
+        )
+        .bind(account_id)
+        .bind(before_id)
+        .bind(lookback_secs)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// ID of an account's latest notification event, 0 if there is none
+    pub async fn get_latest_notification_event_id(&self, account_id: Uuid) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COALESCE(MAX(id), 0) FROM notification_events
+            WHERE account_id = $1
+            "#,
+        )
+        .bind(account_id)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Delete notification events too old to be resumed from
+    pub async fn delete_notification_events_before(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM notification_events
+            WHERE created_at < $1
+            "#,
+        )
+        .bind(cutoff)
         .execute(&self.pool)
         .await?;
         Ok(result.rows_affected())
@@ -321,7 +950,9 @@ impl DbContext {
             UPDATE app_accounts
             SET username = $1, updated_at = $2
             WHERE id = $3
-            RETURNING id, iam_account_id, display_name, avatar_url, username, created_at, updated_at
+            RETURNING id, iam_account_id, display_name, avatar_url, username,
+                   deletion_requested_at, deletion_scheduled_for, password_reset_required_at,
+                   created_at, updated_at
             "#,
         )
         .bind(username)
@@ -330,4 +961,1984 @@ impl DbContext {
         .fetch_one(&self.pool)
         .await
     }
+
+    /// Look up a nano-iam account ID by email
+    pub async fn find_iam_account_id_by_email(
+        &self,
+        email: &str,
+    ) -> Result<Option<Uuid>, sqlx::Error> {
+        sqlx::query_scalar::<_, Uuid>(
+            r#"
+            SELECT id FROM accounts
+            WHERE email = $1
+            "#,
+        )
+        .bind(email)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Look up the sign-in email of the account with a (normalized) username
+    pub async fn find_email_by_username(
+        &self,
+        username: &str,
+    ) -> Result<Option<String>, sqlx::Error> {
+        sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT accounts.email FROM app_accounts
+            JOIN accounts ON accounts.id = app_accounts.iam_account_id
+            WHERE app_accounts.username = $1
+            "#,
+        )
+        .bind(username)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// The password hash nano-iam stores for an account; `None` for accounts
+    /// without a password, such as Google accounts
+    pub async fn get_password_hash(&self, iam_account_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+        let hash = sqlx::query_scalar::<_, Option<String>>(
+            r#"
+            SELECT password_hash FROM accounts
+            WHERE id = $1
+            "#,
+        )
+        .bind(iam_account_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(hash.flatten())
+    }
+
+    /// Append an event to the audit log
+    pub async fn insert_audit_event(
+        &self,
+        entry: &AuditEntry,
+        meta: &RequestMeta,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_events
+                (actor_account_id, target_account_id, event_type, ip_address, user_agent, metadata, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(entry.actor_account_id)
+        .bind(entry.target_account_id)
+        .bind(entry.event_type.as_str())
+        .bind(&meta.ip_address)
+        .bind(&meta.user_agent)
+        .bind(&entry.metadata)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Get audit events concerning an account, newest first
+    pub async fn get_audit_events_for_account(
+        &self,
+        iam_account_id: Uuid,
+        before: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<AuditEvent>, sqlx::Error> {
+        sqlx::query_as::<_, AuditEvent>(
+            r#"
+            SELECT id, actor_account_id, target_account_id, event_type, ip_address, user_agent, metadata, created_at
+            FROM audit_events
+            WHERE target_account_id = $1
+              AND ($2::timestamptz IS NULL OR created_at < $2)
+            ORDER BY created_at DESC
+            LIMIT $3
+            "#,
+        )
+        .bind(iam_account_id)
+        .bind(before)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Search audit events across all accounts (admin)
+    pub async fn search_audit_events(
+        &self,
+        query: &AuditEventsQuery,
+        limit: i64,
+    ) -> Result<Vec<AuditEvent>, sqlx::Error> {
+        sqlx::query_as::<_, AuditEvent>(
+            r#"
+            SELECT id, actor_account_id, target_account_id, event_type, ip_address, user_agent, metadata, created_at
+            FROM audit_events
+            WHERE ($1::uuid IS NULL OR actor_account_id = $1 OR target_account_id = $1)
+              AND ($2::text IS NULL OR event_type = $2)
+              AND ($3::text IS NULL OR ip_address = $3)
+              AND ($4::timestamptz IS NULL OR created_at >= $4)
+              AND ($5::timestamptz IS NULL OR created_at < $5)
+            ORDER BY created_at DESC
+            LIMIT $6
+            "#,
+        )
+        .bind(query.account_id)
+        .bind(&query.event_type)
+        .bind(&query.ip_address)
+        .bind(query.since)
+        .bind(query.until)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Get every audit event concerning an account, oldest first
+    pub async fn get_all_audit_events_for_account(
+        &self,
+        iam_account_id: Uuid,
+    ) -> Result<Vec<AuditEvent>, sqlx::Error> {
+        sqlx::query_as::<_, AuditEvent>(
+            r#"
+            SELECT id, actor_account_id, target_account_id, event_type, ip_address, user_agent, metadata, created_at
+            FROM audit_events
+            WHERE target_account_id = $1
+            ORDER BY created_at
+            "#,
+        )
+        .bind(iam_account_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Create a pending data export
+    pub async fn create_data_export(
+        &self,
+        account_id: Uuid,
+    ) -> Result<DataExport, sqlx::Error> {
+        sqlx::query_as::<_, DataExport>(
+            r#"
+            INSERT INTO data_exports (account_id, status, created_at)
+            VALUES ($1, 'pending', $2)
+            RETURNING id, account_id, status, created_at, completed_at, expires_at
+            "#,
+        )
+        .bind(account_id)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Get the export still being built for an account, if any
+    pub async fn get_pending_data_export(
+        &self,
+        account_id: Uuid,
+    ) -> Result<Option<DataExport>, sqlx::Error> {
+        sqlx::query_as::<_, DataExport>(
+            r#"
+            SELECT id, account_id, status, created_at, completed_at, expires_at
+            FROM data_exports
+            WHERE account_id = $1 AND status = 'pending'
+            "#,
+        )
+        .bind(account_id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Get a data export belonging to an account
+    pub async fn get_data_export(
+        &self,
+        export_id: Uuid,
+        account_id: Uuid,
+    ) -> Result<Option<DataExport>, sqlx::Error> {
+        sqlx::query_as::<_, DataExport>(
+            r#"
+            SELECT id, account_id, status, created_at, completed_at, expires_at
+            FROM data_exports
+            WHERE id = $1 AND account_id = $2
+            "#,
+        )
+        .bind(export_id)
+        .bind(account_id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Get the archive of a ready, unexpired data export
+    pub async fn get_data_export_archive(
+        &self,
+        export_id: Uuid,
+        account_id: Uuid,
+    ) -> Result<Option<Vec<u8>>, sqlx::Error> {
+        sqlx::query_scalar::<_, Vec<u8>>(
+            r#"
+            SELECT archive FROM data_exports
+            WHERE id = $1 AND account_id = $2 AND status = 'ready'
+              AND archive IS NOT NULL AND expires_at > $3
+            "#,
+        )
+        .bind(export_id)
+        .bind(account_id)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Store a finished export archive
+    pub async fn complete_data_export(
+        &self,
+        export_id: Uuid,
+        archive: &[u8],
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE data_exports
+            SET status = 'ready', archive = $1, completed_at = $2, expires_at = $3
+            WHERE id = $4 AND status = 'pending'
+            "#,
+        )
+        .bind(archive)
+        .bind(Utc::now())
+        .bind(expires_at)
+        .bind(export_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Mark an export as failed
+    pub async fn fail_data_export(
+        &self,
+        export_id: Uuid,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE data_exports
+            SET status = 'failed', completed_at = $1
+            WHERE id = $2
+            "#,
+        )
+        .bind(Utc::now())
+        .bind(export_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Mark exports still pending since before `before` as failed; they were
+    /// lost, e.g. to a restart while being built. Limited to one account if given.
+    pub async fn fail_stale_data_exports(
+        &self,
+        account_id: Option<Uuid>,
+        before: DateTime<Utc>,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE data_exports
+            SET status = 'failed', completed_at = $1
+            WHERE status = 'pending' AND created_at <= $2
+              AND ($3::uuid IS NULL OR account_id = $3)
+            "#,
+        )
+        .bind(Utc::now())
+        .bind(before)
+        .bind(account_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Drop the archives of expired exports, keeping their metadata
+    pub async fn clear_expired_data_exports(&self) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE data_exports
+            SET archive = NULL
+            WHERE archive IS NOT NULL AND expires_at <= $1
+            "#,
+        )
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Create an invite code
+    pub async fn create_invite_code(
+        &self,
+        code: &str,
+        created_by: Option<Uuid>,
+        email: Option<&str>,
+        max_uses: i32,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<InviteCode, sqlx::Error> {
+        sqlx::query_as::<_, InviteCode>(
+            r#"
+            INSERT INTO invite_codes (code, created_by, email, max_uses, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, code, created_by, email, max_uses, use_count, expires_at, revoked_at, created_at
+            "#,
+        )
+        .bind(code)
+        .bind(created_by)
+        .bind(email)
+        .bind(max_uses)
+        .bind(expires_at)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Get invite codes created by an account
+    pub async fn get_invite_codes_by_creator(
+        &self,
+        account_id: Uuid,
+    ) -> Result<Vec<InviteCode>, sqlx::Error> {
+        sqlx::query_as::<_, InviteCode>(
+            r#"
+            SELECT id, code, created_by, email, max_uses, use_count, expires_at, revoked_at, created_at
+            FROM invite_codes
+            WHERE created_by = $1
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(account_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Revoke an invite code, restricted to its creator unless `created_by` is None
+    pub async fn revoke_invite_code(
+        &self,
+        invite_id: Uuid,
+        created_by: Option<Uuid>,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE invite_codes
+            SET revoked_at = $1
+            WHERE id = $2 AND revoked_at IS NULL
+              AND ($3::uuid IS NULL OR created_by = $3)
+            "#,
+        )
+        .bind(Utc::now())
+        .bind(invite_id)
+        .bind(created_by)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Atomically consume one use of a valid invite code for an email
+    pub async fn reserve_invite_code(
+        &self,
+        code: &str,
+        email: &str,
+    ) -> Result<Option<Uuid>, sqlx::Error> {
+        sqlx::query_scalar::<_, Uuid>(
+            r#"
+            UPDATE invite_codes
+            SET use_count = use_count + 1
+            WHERE code = $1
+              AND revoked_at IS NULL
+              AND use_count < max_uses
+              AND (expires_at IS NULL OR expires_at > $2)
+              AND (email IS NULL OR LOWER(email) = LOWER($3))
+            RETURNING id
+            "#,
+        )
+        .bind(code.trim().to_uppercase())
+        .bind(Utc::now())
+        .bind(email)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Give back a use of an invite code after a failed signup
+    pub async fn release_invite_code(
+        &self,
+        invite_id: Uuid,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE invite_codes
+            SET use_count = use_count - 1
+            WHERE id = $1 AND use_count > 0
+            "#,
+        )
+        .bind(invite_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Record that an account was created with an invite code
+    pub async fn record_invite_redemption(
+        &self,
+        invite_id: Uuid,
+        iam_account_id: Uuid,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO invite_redemptions (invite_code_id, iam_account_id, redeemed_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(invite_id)
+        .bind(iam_account_id)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Add an email to the waitlist, ignoring addresses already on it
+    pub async fn join_waitlist(
+        &self,
+        email: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO waitlist_entries (email, status, created_at)
+            VALUES ($1, 'pending', $2)
+            ON CONFLICT (email) DO NOTHING
+            "#,
+        )
+        .bind(email.trim().to_lowercase())
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Get waitlist entries, optionally filtered by status
+    pub async fn get_waitlist_entries(
+        &self,
+        status: Option<&str>,
+    ) -> Result<Vec<WaitlistEntry>, sqlx::Error> {
+        sqlx::query_as::<_, WaitlistEntry>(
+            r#"
+            SELECT id, email, status, invite_code_id, created_at, approved_at
+            FROM waitlist_entries
+            WHERE ($1::text IS NULL OR status = $1)
+            ORDER BY created_at
+            "#,
+        )
+        .bind(status)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Approve pending waitlist entries, issuing each a single-use invite bound to its email
+    pub async fn approve_waitlist_entries(
+        &self,
+        entry_ids: &[Uuid],
+        created_by: Uuid,
+        expires_at: DateTime<Utc>,
+        generate_code: impl Fn() -> String,
+    ) -> Result<Vec<(WaitlistEntry, InviteCode)>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let now = Utc::now();
+
+        let entries = sqlx::query_as::<_, WaitlistEntry>(
+            r#"
+            SELECT id, email, status, invite_code_id, created_at, approved_at
+            FROM waitlist_entries
+            WHERE id = ANY($1) AND status = 'pending'
+            FOR UPDATE
+            "#,
+        )
+        .bind(entry_ids)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut approved = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let invite = sqlx::query_as::<_, InviteCode>(
+                r#"
+                INSERT INTO invite_codes (code, created_by, email, max_uses, expires_at, created_at)
+                VALUES ($1, $2, $3, 1, $4, $5)
+                RETURNING id, code, created_by, email, max_uses, use_count, expires_at, revoked_at, created_at
+                "#,
+            )
+            .bind(generate_code())
+            .bind(created_by)
+            .bind(&entry.email)
+            .bind(expires_at)
+            .bind(now)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            let entry = sqlx::query_as::<_, WaitlistEntry>(
+                r#"
+                UPDATE waitlist_entries
+                SET status = 'approved', invite_code_id = $1, approved_at = $2
+                WHERE id = $3
+                RETURNING id, email, status, invite_code_id, created_at, approved_at
+                "#,
+            )
+            .bind(invite.id)
+            .bind(now)
+            .bind(entry.id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            approved.push((entry, invite));
+        }
+
+        tx.commit().await?;
+        Ok(approved)
+    }
+
+    /// Record a proof-of-work nonce, returning false if it was already redeemed
+    pub async fn redeem_pow_nonce(
+        &self,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO pow_redemptions (nonce, expires_at)
+            VALUES ($1, $2)
+            ON CONFLICT (nonce) DO NOTHING
+            "#,
+        )
+        .bind(nonce)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Delete redemption records of expired challenges
+    pub async fn delete_expired_pow_redemptions(&self) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM pow_redemptions
+            WHERE expires_at <= $1
+            "#,
+        )
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Send a Postgres notification to all listeners on a channel
+    pub async fn notify(
+        &self,
+        channel: &str,
+        payload: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(channel)
+            .bind(payload)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Update the last-seen details of a device the account already signed in from
+    pub async fn touch_known_device(
+        &self,
+        account_id: Uuid,
+        fingerprint: &str,
+        location: Option<&str>,
+    ) -> Result<Option<KnownDevice>, sqlx::Error> {
+        sqlx::query_as::<_, KnownDevice>(
+            r#"
+            UPDATE known_devices
+            SET last_seen_at = $1, location = COALESCE($2, location)
+            WHERE account_id = $3 AND fingerprint = $4
+            RETURNING id, account_id, description, ip_prefix, location,
+                      first_seen_at, last_seen_at
+            "#,
+        )
+        .bind(Utc::now())
+        .bind(location)
+        .bind(account_id)
+        .bind(fingerprint)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Remember a device for an account
+    pub async fn create_known_device(
+        &self,
+        account_id: Uuid,
+        fingerprint: &str,
+        description: &str,
+        ip_prefix: Option<&str>,
+        location: Option<&str>,
+    ) -> Result<KnownDevice, sqlx::Error> {
+        let now = Utc::now();
+        sqlx::query_as::<_, KnownDevice>(
+            r#"
+            INSERT INTO known_devices (account_id, fingerprint, description, ip_prefix, location, first_seen_at, last_seen_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $6)
+            ON CONFLICT (account_id, fingerprint) DO UPDATE SET last_seen_at = EXCLUDED.last_seen_at
+            RETURNING id, account_id, description, ip_prefix, location,
+                      first_seen_at, last_seen_at
+            "#,
+        )
+        .bind(account_id)
+        .bind(fingerprint)
+        .bind(description)
+        .bind(ip_prefix)
+        .bind(location)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Count the devices an account has signed in from
+    pub async fn count_known_devices(&self, account_id: Uuid) -> Result<i64, sqlx::Error> {
+        let row: (i64,) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*) FROM known_devices
+            WHERE account_id = $1
+            "#,
+        )
+        .bind(account_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row.0)
+    }
+
+    /// Forget a device so the next sign-in from it raises an alert again
+    pub async fn delete_known_device(&self, device_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM known_devices
+            WHERE id = $1
+            "#,
+        )
+        .bind(device_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Record a new session for freshly issued tokens
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create_session(
+        &self,
+        account_id: Uuid,
+        device_id: Option<Uuid>,
+        access_token_hash: &str,
+        refresh_token_hash: &str,
+        access_token_expires_at: DateTime<Utc>,
+        meta: &RequestMeta,
+        location: Option<&str>,
+        alert_token_hash: Option<&str>,
+        policy: &SessionPolicy,
+    ) -> Result<Session, sqlx::Error> {
+        let now = Utc::now();
+        sqlx::query_as::<_, Session>(
+            r#"
+            INSERT INTO sessions (account_id, device_id, access_token_hash, refresh_token_hash,
+                                  ip_address, user_agent, location, alert_token_hash, created_at, last_seen_at,
+                                  remember_me, expires_at, access_token_expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $9, $10, $11, $12)
+            RETURNING id, account_id, device_id, ip_address, user_agent, location,
+                      created_at, last_seen_at, revoked_at, elevated_until,
+                      remember_me, expires_at, access_token_expires_at
+            "#,
+        )
+        .bind(account_id)
+        .bind(device_id)
+        .bind(access_token_hash)
+        .bind(refresh_token_hash)
+        .bind(&meta.ip_address)
+        .bind(&meta.user_agent)
+        .bind(location)
+        .bind(alert_token_hash)
+        .bind(now)
+        .bind(policy.remember_me)
+        .bind(now + policy.lifetime)
+        .bind(access_token_expires_at)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Get the session an access token was issued to
+    pub async fn get_session_by_access_token_hash(
+        &self,
+        access_token_hash: &str,
+    ) -> Result<Option<Session>, sqlx::Error> {
+        sqlx::query_as::<_, Session>(
+            r#"
+            SELECT id, account_id, device_id, ip_address, user_agent, location,
+                   created_at, last_seen_at, revoked_at, elevated_until,
+                   remember_me, expires_at, access_token_expires_at
+            FROM sessions
+            WHERE access_token_hash = $1
+            "#,
+        )
+        .bind(access_token_hash)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Get the session a refresh token was issued to
+    pub async fn get_session_by_refresh_token_hash(
+        &self,
+        refresh_token_hash: &str,
+    ) -> Result<Option<Session>, sqlx::Error> {
+        sqlx::query_as::<_, Session>(
+            r#"
+            SELECT id, account_id, device_id, ip_address, user_agent, location,
+                   created_at, last_seen_at, revoked_at, elevated_until,
+                   remember_me, expires_at, access_token_expires_at
+            FROM sessions
+            WHERE refresh_token_hash = $1
+            "#,
+        )
+        .bind(refresh_token_hash)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Point a session at the tokens issued by a refresh
+    pub async fn rotate_session_tokens(
+        &self,
+        session_id: Uuid,
+        access_token_hash: &str,
+        refresh_token_hash: &str,
+        access_token_expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE sessions
+            SET access_token_hash = $1, refresh_token_hash = $2, access_token_expires_at = $3,
+                last_seen_at = $4
+            WHERE id = $5
+            "#,
+        )
+        .bind(access_token_hash)
+        .bind(refresh_token_hash)
+        .bind(access_token_expires_at)
+        .bind(Utc::now())
+        .bind(session_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Record activity on a session, unless another request already did since `stale_before`
+    pub async fn touch_session(
+        &self,
+        session_id: Uuid,
+        stale_before: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE sessions
+            SET last_seen_at = $1
+            WHERE id = $2 AND last_seen_at <= $3
+            "#,
+        )
+        .bind(Utc::now())
+        .bind(session_id)
+        .bind(stale_before)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Revoke the session a new-device alert was sent for, if the alert is still valid
+    pub async fn revoke_session_by_alert_token(
+        &self,
+        alert_token_hash: &str,
+        issued_after: DateTime<Utc>,
+    ) -> Result<Option<Session>, sqlx::Error> {
+        sqlx::query_as::<_, Session>(
+            r#"
+            UPDATE sessions
+            SET revoked_at = COALESCE(revoked_at, $1), alert_token_hash = NULL
+            WHERE alert_token_hash = $2 AND created_at > $3
+            RETURNING id, account_id, device_id, ip_address, user_agent, location,
+                      created_at, last_seen_at, revoked_at, elevated_until,
+                      remember_me, expires_at, access_token_expires_at
+            "#,
+        )
+        .bind(Utc::now())
+        .bind(alert_token_hash)
+        .bind(issued_after)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Require the account to change its password before using the app again
+    pub async fn set_password_reset_required(
+        &self,
+        account_id: Uuid,
+        required: bool,
+    ) -> Result<Account, sqlx::Error> {
+        let now = Utc::now();
+        sqlx::query_as::<_, Account>(
+            r#"
+            UPDATE app_accounts
+            SET password_reset_required_at = $1, updated_at = $2
+            WHERE id = $3
+            RETURNING id, iam_account_id, display_name, avatar_url, username,
+                   deletion_requested_at, deletion_scheduled_for, password_reset_required_at,
+                   created_at, updated_at
+            "#,
+        )
+        .bind(required.then_some(now))
+        .bind(now)
+        .bind(account_id)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Get all devices an account has signed in from
+    pub async fn get_known_devices(&self, account_id: Uuid) -> Result<Vec<KnownDevice>, sqlx::Error> {
+        sqlx::query_as::<_, KnownDevice>(
+            r#"
+            SELECT id, account_id, description, ip_prefix, location, first_seen_at, last_seen_at
+            FROM known_devices
+            WHERE account_id = $1
+            ORDER BY last_seen_at DESC
+            "#,
+        )
+        .bind(account_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Get all sessions of an account, newest first
+    pub async fn get_sessions_for_account(&self, account_id: Uuid) -> Result<Vec<Session>, sqlx::Error> {
+        sqlx::query_as::<_, Session>(
+            r#"
+            SELECT id, account_id, device_id, ip_address, user_agent, location,
+                   created_at, last_seen_at, revoked_at, elevated_until,
+                   remember_me, expires_at, access_token_expires_at
+            FROM sessions
+            WHERE account_id = $1
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(account_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Count a try against the code last issued to an address before checking
+    /// it, and return the total, so concurrent tries can't all pass the limit
+    /// check. Addresses are identified by their `audit::identifier_hash`.
+    pub async fn reserve_verification_attempt(&self, email_hash: &str) -> Result<i32, sqlx::Error> {
+        sqlx::query_scalar::<_, i32>(
+            r#"
+            INSERT INTO verification_attempts (email_hash, failed_attempts, updated_at)
+            VALUES ($1, 1, $2)
+            ON CONFLICT (email_hash)
+            DO UPDATE SET failed_attempts = verification_attempts.failed_attempts + 1, updated_at = $2
+            RETURNING failed_attempts
+            "#,
+        )
+        .bind(email_hash)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Wrong tries counted against the code last issued to an address
+    pub async fn get_verification_attempts(&self, email_hash: &str) -> Result<i32, sqlx::Error> {
+        let attempts = sqlx::query_scalar::<_, i32>(
+            r#"
+            SELECT failed_attempts FROM verification_attempts
+            WHERE email_hash = $1
+            "#,
+        )
+        .bind(email_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(attempts.unwrap_or(0))
+    }
+
+    /// Give back a reserved attempt whose code couldn't be checked
+    pub async fn release_verification_attempt(&self, email_hash: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE verification_attempts
+            SET failed_attempts = GREATEST(failed_attempts - 1, 0)
+            WHERE email_hash = $1
+            "#,
+        )
+        .bind(email_hash)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Start counting afresh, once the email is verified or a new code is issued
+    pub async fn clear_verification_attempts(&self, email_hash: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM verification_attempts
+            WHERE email_hash = $1
+            "#,
+        )
+        .bind(email_hash)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Forget counters for codes that have expired since their last try
+    pub async fn delete_stale_verification_attempts(
+        &self,
+        older_than: DateTime<Utc>,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM verification_attempts
+            WHERE updated_at < $1
+            "#,
+        )
+        .bind(older_than)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Whether a verification link has already been used
+    pub async fn is_verification_link_used(&self, token_hash: &str) -> Result<bool, sqlx::Error> {
+        let used = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT token_hash FROM used_verification_links
+            WHERE token_hash = $1
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(used.is_some())
+    }
+
+    /// Remember a verification link as used until it would have expired anyway
+    pub async fn mark_verification_link_used(
+        &self,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO used_verification_links (token_hash, expires_at)
+            VALUES ($1, $2)
+            ON CONFLICT (token_hash) DO NOTHING
+            "#,
+        )
+        .bind(token_hash)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Delete records of used verification links that have expired
+    pub async fn delete_expired_verification_links(&self) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM used_verification_links
+            WHERE expires_at <= $1
+            "#,
+        )
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Mark a session as recently re-authenticated until the given time
+    pub async fn elevate_session(
+        &self,
+        session_id: Uuid,
+        elevated_until: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE sessions
+            SET elevated_until = $1
+            WHERE id = $2 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(elevated_until)
+        .bind(session_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Revoke a single session
+    pub async fn revoke_session(&self, session_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE sessions
+            SET revoked_at = COALESCE(revoked_at, $1)
+            WHERE id = $2
+            "#,
+        )
+        .bind(Utc::now())
+        .bind(session_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Revoke every live session of an account, returning how many were revoked
+    pub async fn revoke_all_sessions(&self, account_id: Uuid) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE sessions
+            SET revoked_at = $1
+            WHERE account_id = $2 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(Utc::now())
+        .bind(account_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Revoke all but the `keep` most recent live sessions of an account
+    pub async fn revoke_oldest_sessions(
+        &self,
+        account_id: Uuid,
+        keep: i64,
+    ) -> Result<Vec<Session>, sqlx::Error> {
+        let now = Utc::now();
+        sqlx::query_as::<_, Session>(
+            r#"
+            UPDATE sessions
+            SET revoked_at = $1
+            WHERE id IN (
+                SELECT id FROM sessions
+                WHERE account_id = $2 AND revoked_at IS NULL
+                  AND (expires_at IS NULL OR expires_at > $1)
+                ORDER BY created_at DESC
+                OFFSET $3
+            )
+            RETURNING id, account_id, device_id, ip_address, user_agent, location,
+                      created_at, last_seen_at, revoked_at, elevated_until,
+                      remember_me, expires_at, access_token_expires_at
+            "#,
+        )
+        .bind(now)
+        .bind(account_id)
+        .bind(keep)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Record an open realtime connection
+    pub async fn add_realtime_connection(
+        &self,
+        connection_id: Uuid,
+        account_id: Uuid,
+    ) -> Result<(), sqlx::Error> {
+        let now = Utc::now();
+        sqlx::query(
+            r#"
+            INSERT INTO realtime_connections (id, account_id, connected_at, last_seen_at)
+            VALUES ($1, $2, $3, $3)
+            "#,
+        )
+        .bind(connection_id)
+        .bind(account_id)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Mark a realtime connection as still alive
+    pub async fn touch_realtime_connection(&self, connection_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE realtime_connections
+            SET last_seen_at = $1
+            WHERE id = $2
+            "#,
+        )
+        .bind(Utc::now())
+        .bind(connection_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Forget a closed realtime connection
+    pub async fn remove_realtime_connection(&self, connection_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM realtime_connections
+            WHERE id = $1
+            "#,
+        )
+        .bind(connection_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Accounts with a realtime connection seen since a point in time
+    pub async fn get_online_accounts(
+        &self,
+        seen_since: DateTime<Utc>,
+    ) -> Result<Vec<OnlineAccount>, sqlx::Error> {
+        sqlx::query_as::<_, OnlineAccount>(
+            r#"
+            SELECT app_accounts.iam_account_id AS account_id,
+                   COUNT(*) AS connections,
+                   MIN(realtime_connections.connected_at) AS online_since
+            FROM realtime_connections
+            JOIN app_accounts ON app_accounts.id = realtime_connections.account_id
+            WHERE realtime_connections.last_seen_at > $1
+            GROUP BY app_accounts.iam_account_id
+            ORDER BY online_since
+            "#,
+        )
+        .bind(seen_since)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Delete connections left behind by replicas that stopped without closing them
+    pub async fn delete_stale_realtime_connections(
+        &self,
+        seen_before: DateTime<Utc>,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM realtime_connections
+            WHERE last_seen_at < $1
+            "#,
+        )
+        .bind(seen_before)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Get an account's notification routing rules
+    pub async fn get_notification_preferences(
+        &self,
+        account_id: Uuid,
+    ) -> Result<Vec<NotificationPreference>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationPreference>(
+            r#"
+            SELECT kind, level, channels
+            FROM notification_preferences
+            WHERE account_id = $1
+            ORDER BY kind NULLS LAST, level
+            "#,
+        )
+        .bind(account_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Replace an account's notification routing rules and webhook
+    pub async fn replace_notification_preferences(
+        &self,
+        account_id: Uuid,
+        rules: &[NotificationPreference],
+        webhook_url: Option<&str>,
+        webhook_secret: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM notification_preferences WHERE account_id = $1")
+            .bind(account_id)
+            .execute(&mut *tx)
+            .await?;
+        for rule in rules {
+            sqlx::query(
+                r#"
+                INSERT INTO notification_preferences (account_id, kind, level, channels)
+                VALUES ($1, $2, $3, $4)
+                "#,
+            )
+            .bind(account_id)
+            .bind(&rule.kind)
+            .bind(&rule.level)
+            .bind(&rule.channels)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO notification_settings (account_id, webhook_url, webhook_secret, updated_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (account_id) DO UPDATE
+            SET webhook_url = EXCLUDED.webhook_url,
+                webhook_secret = EXCLUDED.webhook_secret,
+                updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind(account_id)
+        .bind(webhook_url)
+        .bind(webhook_secret)
+        .bind(Utc::now())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    }
+
+    /// Get an account's notification delivery settings, if it has saved any
+    pub async fn get_notification_settings(
+        &self,
+        account_id: Uuid,
+    ) -> Result<Option<NotificationSettings>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationSettings>(
+            r#"
+            SELECT account_id, webhook_url, webhook_secret, digest_frequency, digest_time_zone,
+                   digest_hour, digest_weekday, last_digest_at, next_digest_at, updated_at
+            FROM notification_settings
+            WHERE account_id = $1
+            "#,
+        )
+        .bind(account_id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Queue a notification for an account's next digest email
+    pub async fn add_notification_digest_item(
+        &self,
+        account_id: Uuid,
+        new: &NewNotification,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO notification_digest_items (account_id, kind, level, title, message, action_url, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(account_id)
+        .bind(&new.kind)
+        .bind(new.level())
+        .bind(&new.title)
+        .bind(&new.message)
+        .bind(&new.action_url)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Change when an account receives digest emails
+    pub async fn update_digest_settings(
+        &self,
+        account_id: Uuid,
+        digest: &DigestSettings,
+        next_digest_at: Option<DateTime<Utc>>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO notification_settings (account_id, digest_frequency, digest_time_zone,
+                                               digest_hour, digest_weekday, next_digest_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (account_id) DO UPDATE
+            SET digest_frequency = EXCLUDED.digest_frequency,
+                digest_time_zone = EXCLUDED.digest_time_zone,
+                digest_hour = EXCLUDED.digest_hour,
+                digest_weekday = EXCLUDED.digest_weekday,
+                next_digest_at = EXCLUDED.next_digest_at,
+                updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind(account_id)
+        .bind(&digest.frequency)
+        .bind(&digest.time_zone)
+        .bind(digest.hour)
+        .bind(digest.weekday)
+        .bind(next_digest_at)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Stop sending digest emails to an account, returning whether it had them enabled
+    pub async fn disable_digest(&self, account_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE notification_settings
+            SET digest_frequency = 'off', next_digest_at = NULL, updated_at = $2
+            WHERE account_id = $1 AND digest_frequency <> 'off'
+            "#,
+        )
+        .bind(account_id)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Get accounts whose digest email is due
+    pub async fn get_due_digests(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<DigestSchedule>, sqlx::Error> {
+        sqlx::query_as::<_, DigestSchedule>(
+            r#"
+            SELECT notification_settings.account_id, app_accounts.iam_account_id,
+                   digest_frequency, digest_time_zone, digest_hour, digest_weekday,
+                   last_digest_at, next_digest_at
+            FROM notification_settings
+            JOIN app_accounts ON app_accounts.id = notification_settings.account_id
+            WHERE digest_frequency <> 'off'
+              AND next_digest_at <= $1
+              AND app_accounts.deletion_scheduled_for IS NULL
+            ORDER BY next_digest_at
+            LIMIT $2
+            "#,
+        )
+        .bind(now)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Get an account's unread notifications created within a period, newest first
+    pub async fn get_unread_notifications_between(
+        &self,
+        account_id: Uuid,
+        since: Option<DateTime<Utc>>,
+        until: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<Notification>, sqlx::Error> {
+        sqlx::query_as::<_, Notification>(
+            r#"
+            SELECT id, account_id, kind, level, title, message, data, action_url, actions, source, read,
+                   group_key, occurrences, last_occurred_at, expires_at, archived_at,
+                   created_at, updated_at
+            FROM notifications
+            WHERE account_id = $1
+              AND NOT read
+              AND archived_at IS NULL
+              AND (expires_at IS NULL OR expires_at > $3)
+              -- Repeats collapsed into an older notification count as new
+              AND ($2::timestamptz IS NULL OR COALESCE(last_occurred_at, created_at) > $2)
+              AND COALESCE(last_occurred_at, created_at) <= $3
+            ORDER BY created_at DESC, id DESC
+            LIMIT $4
+            "#,
+        )
+        .bind(account_id)
+        .bind(since)
+        .bind(until)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Get notifications queued for an account's next digest, oldest first
+    pub async fn get_pending_digest_items(
+        &self,
+        account_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<NotificationDigestItem>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationDigestItem>(
+            r#"
+            SELECT id, title, message, action_url
+            FROM notification_digest_items
+            WHERE account_id = $1 AND digest_id IS NULL
+            ORDER BY created_at
+            LIMIT $2
+            "#,
+        )
+        .bind(account_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Record a digest and move the account's schedule on.
+    ///
+    /// Returns `None` if a digest for this slot was already recorded, so it is
+    /// never sent twice.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn record_notification_digest(
+        &self,
+        account_id: Uuid,
+        scheduled_for: DateTime<Utc>,
+        period_start: Option<DateTime<Utc>>,
+        period_end: DateTime<Utc>,
+        notification_ids: &[Uuid],
+        item_ids: &[Uuid],
+        next_digest_at: Option<DateTime<Utc>>,
+    ) -> Result<Option<Uuid>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let digest_id = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            INSERT INTO notification_digests (account_id, scheduled_for, period_start, period_end,
+                                              notification_ids, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (account_id, scheduled_for) DO NOTHING
+            RETURNING id
+            "#,
+        )
+        .bind(account_id)
+        .bind(scheduled_for)
+        .bind(period_start)
+        .bind(period_end)
+        .bind(notification_ids)
+        .bind(Utc::now())
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(digest_id) = digest_id else {
+            return Ok(None);
+        };
+
+        sqlx::query(
+            r#"
+            UPDATE notification_digest_items
+            SET digest_id = $1
+            WHERE id = ANY($2) AND digest_id IS NULL
+            "#,
+        )
+        .bind(digest_id)
+        .bind(item_ids)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE notification_settings
+            SET last_digest_at = $2, next_digest_at = $3
+            WHERE account_id = $1
+            "#,
+        )
+        .bind(account_id)
+        .bind(period_end)
+        .bind(next_digest_at)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(digest_id))
+    }
+
+    /// Move an account's digest schedule on without sending, when there was nothing to send
+    pub async fn reschedule_digest(
+        &self,
+        account_id: Uuid,
+        next_digest_at: Option<DateTime<Utc>>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE notification_settings
+            SET next_digest_at = $2
+            WHERE account_id = $1
+            "#,
+        )
+        .bind(account_id)
+        .bind(next_digest_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Mark a digest as delivered to the mailer
+    pub async fn mark_notification_digest_sent(&self, digest_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE notification_digests SET sent_at = $2 WHERE id = $1")
+            .bind(digest_id)
+            .bind(Utc::now())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Count the accounts in a broadcast segment
+    pub async fn count_broadcast_recipients(
+        &self,
+        segment: &BroadcastSegment,
+        admin_emails: &[String],
+    ) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*)
+            FROM app_accounts
+            JOIN accounts ON accounts.id = app_accounts.iam_account_id
+            WHERE app_accounts.deletion_scheduled_for IS NULL
+              AND ($1::text IS NULL OR LOWER(accounts.auth_type::text) = $1)
+              AND ($2::timestamptz IS NULL OR app_accounts.created_at >= $2)
+              AND ($3::timestamptz IS NULL OR app_accounts.created_at < $3)
+              AND ($4::text IS NULL OR (LOWER(accounts.email) = ANY($5)) = ($4 = 'admin'))
+            "#,
+        )
+        .bind(&segment.auth_type)
+        .bind(segment.signed_up_after)
+        .bind(segment.signed_up_before)
+        .bind(&segment.role)
+        .bind(admin_emails)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Create a broadcast for the background job to deliver
+    pub async fn create_notification_broadcast(
+        &self,
+        created_by: Uuid,
+        new: &NewNotification,
+        segment: &BroadcastSegment,
+        total_recipients: i64,
+    ) -> Result<NotificationBroadcast, sqlx::Error> {
+        sqlx::query_as::<_, NotificationBroadcast>(
+            r#"
+            INSERT INTO notification_broadcasts (created_by, kind, level, title, message, action_url,
+                                                 actions, segment, total_recipients, expires_at,
+                                                 created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            RETURNING id, created_by, kind, level, title, message, action_url, actions, segment,
+                      status, cursor, total_recipients, delivered_count, retracted_count, expires_at,
+                      created_at, started_at, completed_at, retracted_at
+            "#,
+        )
+        .bind(created_by)
+        .bind(&new.kind)
+        .bind(new.level())
+        .bind(&new.title)
+        .bind(&new.message)
+        .bind(&new.action_url)
+        .bind(serde_json::json!(new.actions))
+        .bind(serde_json::json!(segment))
+        .bind(total_recipients as i32)
+        .bind(new.expires_at)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Get broadcasts, newest first
+    pub async fn get_notification_broadcasts(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<NotificationBroadcast>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationBroadcast>(
+            r#"
+            SELECT id, created_by, kind, level, title, message, action_url, actions, segment,
+                   status, cursor, total_recipients, delivered_count, retracted_count, expires_at,
+                   created_at, started_at, completed_at, retracted_at
+            FROM notification_broadcasts
+            ORDER BY created_at DESC
+            LIMIT $1
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn get_notification_broadcast(
+        &self,
+        broadcast_id: Uuid,
+    ) -> Result<Option<NotificationBroadcast>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationBroadcast>(
+            r#"
+            SELECT id, created_by, kind, level, title, message, action_url, actions, segment,
+                   status, cursor, total_recipients, delivered_count, retracted_count, expires_at,
+                   created_at, started_at, completed_at, retracted_at
+            FROM notification_broadcasts
+            WHERE id = $1
+            "#,
+        )
+        .bind(broadcast_id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Get the oldest broadcast still being delivered or retracted
+    pub async fn get_active_notification_broadcast(
+        &self,
+    ) -> Result<Option<NotificationBroadcast>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationBroadcast>(
+            r#"
+            SELECT id, created_by, kind, level, title, message, action_url, actions, segment,
+                   status, cursor, total_recipients, delivered_count, retracted_count, expires_at,
+                   created_at, started_at, completed_at, retracted_at
+            FROM notification_broadcasts
+            WHERE status IN ('pending', 'running', 'retracting')
+            ORDER BY created_at
+            LIMIT 1
+            "#,
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Deliver a broadcast to the next batch of accounts in its segment.
+    ///
+    /// Accounts whose preferences keep this kind out of the notifications list
+    /// are skipped. Returns the broadcast's status afterwards, `completed` once
+    /// the whole segment has been covered.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn deliver_notification_broadcast_batch(
+        &self,
+        broadcast: &NotificationBroadcast,
+        segment: &BroadcastSegment,
+        admin_emails: &[String],
+        default_in_app: bool,
+        mandatory_in_app: bool,
+        batch_size: i64,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let result = sqlx::query_as::<_, (String, Vec<i64>, Vec<Uuid>)>(
+            r#"
+            WITH scanned AS (
+                SELECT app_accounts.id
+                FROM app_accounts
+                JOIN accounts ON accounts.id = app_accounts.iam_account_id
+                WHERE app_accounts.deletion_scheduled_for IS NULL
+                  AND ($1::text IS NULL OR LOWER(accounts.auth_type::text) = $1)
+                  AND ($2::timestamptz IS NULL OR app_accounts.created_at >= $2)
+                  AND ($3::timestamptz IS NULL OR app_accounts.created_at < $3)
+                  AND ($4::text IS NULL OR (LOWER(accounts.email) = ANY($5)) = ($4 = 'admin'))
+                  AND ($6::uuid IS NULL OR app_accounts.id > $6)
+                ORDER BY app_accounts.id
+                LIMIT $7
+            ),
+            recipients AS (
+                SELECT scanned.id
+                FROM scanned
+                WHERE $12 OR COALESCE(
+                    (SELECT 'in_app' = ANY(channels) FROM notification_preferences
+                     WHERE account_id = scanned.id AND kind = $9),
+                    (SELECT 'in_app' = ANY(channels) FROM notification_preferences
+                     WHERE account_id = scanned.id AND level = $10),
+                    $11
+                )
+            ),
+            inserted AS (
+                INSERT INTO notifications (account_id, kind, level, title, message, data, action_url,
+                                           actions, source, broadcast_id, expires_at, read,
+                                           created_at, updated_at)
+                SELECT recipients.id, b.kind, b.level, b.title, b.message, '{}'::jsonb, b.action_url,
+                       b.actions, 'broadcast', b.id, b.expires_at, false, $13, $13
+                FROM recipients
+                CROSS JOIN notification_broadcasts b
+                WHERE b.id = $8 AND b.status IN ('pending', 'running')
+                RETURNING id, account_id, kind, level, title, message, data, action_url, actions,
+                          source, group_key, occurrences, last_occurred_at, read, expires_at,
+                          archived_at, created_at, updated_at
+            ),
+            events AS (
+                INSERT INTO notification_events (account_id, event_type, data, created_at)
+                SELECT inserted.account_id, 'created',
+                       jsonb_build_object(
+                           'notification', to_jsonb(inserted),
+                           -- Counted before this statement's inserts are visible
+                           'unread_count', (SELECT COUNT(*) FROM notifications
+                                            WHERE account_id = inserted.account_id AND NOT read
+                                              AND archived_at IS NULL
+                                              AND (expires_at IS NULL OR expires_at > $13)) + 1
+                       ),
+                       $13
+                FROM inserted
+                RETURNING id, account_id
+            )
+            UPDATE notification_broadcasts
+            SET cursor = COALESCE((SELECT id FROM scanned ORDER BY id DESC LIMIT 1), cursor),
+                delivered_count = delivered_count + (SELECT COUNT(*) FROM inserted),
+                status = CASE WHEN (SELECT COUNT(*) FROM scanned) < $7 THEN 'completed' ELSE 'running' END,
+                started_at = COALESCE(started_at, $13),
+                completed_at = CASE WHEN (SELECT COUNT(*) FROM scanned) < $7 THEN $13 END
+            WHERE id = $8 AND status IN ('pending', 'running')
+            RETURNING status, ARRAY(SELECT id FROM events), ARRAY(SELECT account_id FROM events)
+            "#,
+        )
+        .bind(&segment.auth_type)
+        .bind(segment.signed_up_after)
+        .bind(segment.signed_up_before)
+        .bind(&segment.role)
+        .bind(admin_emails)
+        .bind(broadcast.cursor)
+        .bind(batch_size)
+        .bind(broadcast.id)
+        .bind(&broadcast.kind)
+        .bind(&broadcast.level)
+        .bind(default_in_app)
+        .bind(mandatory_in_app)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some((status, event_ids, account_ids)) = result else {
+            return Ok(None);
+        };
+        self.announce_notification_events(&event_ids, &account_ids).await;
+        Ok(Some(status))
+    }
+
+    /// Start retracting a broadcast, returning it unless it was already retracted
+    pub async fn retract_notification_broadcast(
+        &self,
+        broadcast_id: Uuid,
+    ) -> Result<Option<NotificationBroadcast>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationBroadcast>(
+            r#"
+            UPDATE notification_broadcasts
+            SET status = 'retracting'
+            WHERE id = $1 AND status IN ('pending', 'running', 'completed')
+            RETURNING id, created_by, kind, level, title, message, action_url, actions, segment,
+                      status, cursor, total_recipients, delivered_count, retracted_count, expires_at,
+                      created_at, started_at, completed_at, retracted_at
+            "#,
+        )
+        .bind(broadcast_id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Delete the next batch of a retracted broadcast's notifications.
+    ///
+    /// Returns the broadcast's status afterwards, `retracted` once none are left.
+    pub async fn retract_notification_broadcast_batch(
+        &self,
+        broadcast_id: Uuid,
+        batch_size: i64,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let result = sqlx::query_as::<_, (String, Vec<i64>, Vec<Uuid>)>(
+            r#"
+            WITH deleted AS (
+                DELETE FROM notifications
+                WHERE id IN (
+                    SELECT id FROM notifications
+                    WHERE broadcast_id = $1
+                    LIMIT $2
+                )
+                RETURNING id, account_id, read, archived_at, expires_at
+            ),
+            events AS (
+                INSERT INTO notification_events (account_id, event_type, data, created_at)
+                SELECT deleted.account_id, 'deleted',
+                       jsonb_build_object(
+                           'ids', jsonb_agg(deleted.id),
+                           -- Counted before this statement's deletes are visible
+                           'unread_count', (SELECT COUNT(*) FROM notifications
+                                            WHERE account_id = deleted.account_id AND NOT read
+                                              AND archived_at IS NULL
+                                              AND (expires_at IS NULL OR expires_at > $3))
+                                           - COUNT(*) FILTER (WHERE NOT deleted.read
+                                                              AND deleted.archived_at IS NULL
+                                                              AND (deleted.expires_at IS NULL
+                                                                   OR deleted.expires_at > $3))
+                       ),
+                       $3
+                FROM deleted
+                GROUP BY deleted.account_id
+                RETURNING id, account_id
+            )
+            UPDATE notification_broadcasts
+            SET retracted_count = retracted_count + (SELECT COUNT(*) FROM deleted),
+                status = CASE WHEN (SELECT COUNT(*) FROM deleted) < $2 THEN 'retracted' ELSE status END,
+                retracted_at = CASE WHEN (SELECT COUNT(*) FROM deleted) < $2 THEN $3 END
+            WHERE id = $1 AND status = 'retracting'
+            RETURNING status, ARRAY(SELECT id FROM events), ARRAY(SELECT account_id FROM events)
+            "#,
+        )
+        .bind(broadcast_id)
+        .bind(batch_size)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some((status, event_ids, account_ids)) = result else {
+            return Ok(None);
+        };
+        self.announce_notification_events(&event_ids, &account_ids).await;
+        Ok(Some(status))
+    }
+
+    /// Announce events written in bulk, in one round trip, so streams deliver
+    /// them to their accounts like any other event
+    async fn announce_notification_events(&self, event_ids: &[i64], account_ids: &[Uuid]) {
+        if event_ids.is_empty() {
+            return;
+        }
+        let payloads: Vec<String> = event_ids
+            .iter()
+            .zip(account_ids)
+            .map(|(id, account_id)| serde_json::json!({ "id": id, "account_id": account_id }).to_string())
+            .collect();
+        let result = sqlx::query("SELECT pg_notify($1, payload) FROM UNNEST($2::text[]) AS payload")
+            .bind(NOTIFICATION_EVENTS_CHANNEL)
+            .bind(&payloads)
+            .execute(&self.pool)
+            .await;
+        if let Err(e) = result {
+            log::error!("Failed to announce notification events: {:?}", e);
+        }
+    }
+
+    /// Delete up to `batch_size` notifications, telling their accounts' streams.
+    ///
+    /// Without a rule, deletes notifications that expired before `before`; with
+    /// one, those matching it that were created before `before`.
+    pub async fn purge_notifications_batch(
+        &self,
+        rule: Option<&RetentionRule>,
+        before: DateTime<Utc>,
+        batch_size: i64,
+    ) -> Result<u64, sqlx::Error> {
+        let (count, event_ids, account_ids) = sqlx::query_as::<_, (i64, Vec<i64>, Vec<Uuid>)>(
+            r#"
+            WITH deleted AS (
+                DELETE FROM notifications
+                WHERE id IN (
+                    SELECT id FROM notifications
+                    WHERE CASE WHEN $1 THEN created_at < $2 ELSE expires_at <= $2 END
+                      AND ($3::text IS NULL OR kind = $3)
+                      AND ($4::text IS NULL OR level = $4)
+                      AND ($5::boolean IS NULL OR read = $5)
+                      AND ($6::boolean IS NULL OR (archived_at IS NOT NULL) = $6)
+                    LIMIT $7
+                )
+                RETURNING id, account_id, read, archived_at, expires_at
+            ),
+            events AS (
+                INSERT INTO notification_events (account_id, event_type, data, created_at)
+                SELECT deleted.account_id, 'deleted',
+                       jsonb_build_object(
+                           'ids', jsonb_agg(deleted.id),
+                           -- Counted before this statement's deletes are visible
+                           'unread_count', (SELECT COUNT(*) FROM notifications
+                                            WHERE account_id = deleted.account_id AND NOT read
+                                              AND archived_at IS NULL
+                                              AND (expires_at IS NULL OR expires_at > $8))
+                                           - COUNT(*) FILTER (WHERE NOT deleted.read
+                                                              AND deleted.archived_at IS NULL
+                                                              AND (deleted.expires_at IS NULL
+                                                                   OR deleted.expires_at > $8))
+                       ),
+                       $8
+                FROM deleted
+                GROUP BY deleted.account_id
+                RETURNING id, account_id
+            )
+            SELECT (SELECT COUNT(*) FROM deleted), ARRAY(SELECT id FROM events),
+                   ARRAY(SELECT account_id FROM events)
+            "#,
+        )
+        .bind(rule.is_some())
+        .bind(before)
+        .bind(rule.and_then(|r| r.kind.as_deref()))
+        .bind(rule.and_then(|r| r.level.as_deref()))
+        .bind(rule.and_then(|r| r.read))
+        .bind(rule.and_then(|r| r.archived))
+        .bind(batch_size)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        self.announce_notification_events(&event_ids, &account_ids).await;
+        Ok(count as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use nano_iam::email::EmailSender;
+    use nano_iam::{
+        AuthConfig, AuthService, EmailVerificationConfig, IamError, LeaseLock, PasswordPolicy,
+        TokenConfig,
+    };
+    use std::sync::Arc;
+
+    struct NoopEmailSender;
+
+    #[async_trait::async_trait]
+    impl EmailSender for NoopEmailSender {
+        async fn send_verification_email(
+            &self,
+            _to: &str,
+            _code: &str,
+            _service_name: Option<&str>,
+        ) -> Result<(), IamError> {
+            Ok(())
+        }
+
+        async fn send_password_reset_email(
+            &self,
+            _to: &str,
+            _code: &str,
+            _service_name: Option<&str>,
+        ) -> Result<(), IamError> {
+            Ok(())
+        }
+    }
+
+    /// Scratch database with the pinned nano-iam schema and our migrations
+    /// applied, set up the same way `initialize_database` does it
+    async fn test_database() -> PgPool {
+        let url = env::var("TEST_DATABASE_URL")
+            .expect("TEST_DATABASE_URL must point at a scratch Postgres database");
+        let pool = PgPool::connect(&url).await.expect("Failed to connect to test database");
+        init_iam_schema(&pool).await.expect("Failed to create nano-iam schema");
+        run_migrations(&pool).await.expect("Failed to run migrations");
+        pool
+    }
+
+    fn auth_service(pool: &PgPool) -> AuthService {
+        AuthService::new(
+            Repo::new(pool.clone()),
+            Arc::new(NoopEmailSender),
+            AuthConfig {
+                token: TokenConfig {
+                    access_ttl: Duration::hours(1),
+                    refresh_ttl: Duration::days(30),
+                },
+                email_verification: EmailVerificationConfig {
+                    code_ttl: Duration::hours(1),
+                    code_length: 6,
+                },
+                password_policy: PasswordPolicy::default(),
+                service_name: None,
+            },
+            LeaseLock::new(pool.clone()),
+        )
+    }
+
+    /// `purge_account` deletes from nano-iam's `accounts` table directly, so
+    /// check it against the real IAM schema, including the verification code
+    /// nano-iam stores for a new account
+    #[tokio::test]
+    #[ignore = "needs a scratch Postgres database in TEST_DATABASE_URL"]
+    async fn purge_account_removes_iam_account() {
+        let pool = test_database().await;
+        let db = DbContext::new(pool.clone());
+        let auth_service = auth_service(&pool);
+
+        let email = format!("purge-{}@example.com", Uuid::new_v4());
+        let iam_account = auth_service
+            .register(&email, "Purge-Test-Password-91!")
+            .await
+            .expect("Failed to register IAM account");
+        let account = db
+            .create_account(iam_account.id, iam_account.email.clone())
+            .await
+            .expect("Failed to create app account");
+
+        db.purge_account(account.id, iam_account.id)
+            .await
+            .expect("Failed to purge account");
+
+        assert!(db.get_account_by_id(account.id).await.unwrap().is_none());
+        assert!(auth_service.get_account(iam_account.id).await.is_err());
+        let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM accounts WHERE id = $1")
+            .bind(iam_account.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(remaining, 0);
+    }
 }
@@ -0,0 +1,90 @@
+use chrono::Duration;
+use std::env;
+use std::sync::Arc;
+
+use crate::audit::{self, AuditEntry, AuditEventType, RequestMeta};
+use crate::dba::DbContext;
+use crate::mailer::Mailer;
+use crate::token_cache::TokenCache;
+
+/// Maximum number of accounts purged per job run
+const PURGE_BATCH_SIZE: i64 = 100;
+
+/// Account deletion configuration
+#[derive(Clone)]
+pub struct DeletionConfig {
+    /// How long a deleted account can still be restored by signing in
+    pub grace_period: Duration,
+    /// How often the purge job looks for expired accounts
+    pub purge_interval: std::time::Duration,
+}
+
+impl DeletionConfig {
+    /// Create deletion configuration from environment or defaults
+    pub fn from_env() -> Self {
+        let grace_days = env::var("ACCOUNT_DELETION_GRACE_DAYS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(30);
+        let interval_secs = env::var("ACCOUNT_PURGE_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(3600);
+        Self {
+            grace_period: Duration::days(grace_days),
+            purge_interval: std::time::Duration::from_secs(interval_secs),
+        }
+    }
+}
+
+/// Send the email confirming a deletion request
+pub async fn send_deletion_email(
+    mailer: &Arc<dyn Mailer>,
+    to: &str,
+    scheduled_for: chrono::DateTime<chrono::Utc>,
+) {
+    let body = format!(
+        "We received a request to delete your account.\n\n\
+         Your account and all of its data will be permanently deleted on {}.\n\
+         If this was a mistake, sign in before then and restore your account.",
+        scheduled_for.format("%Y-%m-%d %H:%M UTC")
+    );
+    if let Err(e) = mailer
+        .send_email(to, "Your account is scheduled for deletion", &body)
+        .await
+    {
+        log::error!("Failed to send deletion email to {}: {:?}", to, e);
+    }
+}
+
+/// Permanently remove accounts whose grace period has expired
+pub async fn purge_expired_accounts(db: &DbContext, token_cache: &TokenCache) {
+    let accounts = match db.get_accounts_due_for_purge(PURGE_BATCH_SIZE).await {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            log::error!("Failed to list accounts due for purge: {:?}", e);
+            return;
+        }
+    };
+
+    for account in accounts {
+        match db.purge_account(account.id, account.iam_account_id).await {
+            Ok(_) => {
+                log::info!("Purged account {}", account.iam_account_id);
+                token_cache.invalidate_account(account.iam_account_id).await;
+                audit::record(
+                    db,
+                    &RequestMeta::default(),
+                    AuditEntry::anonymous(AuditEventType::AccountDeleted, Some(account.iam_account_id))
+                        .with_metadata(serde_json::json!({
+                            "deletion_requested_at": account.deletion_requested_at,
+                        })),
+                )
+                .await;
+            }
+            Err(e) => {
+                log::error!("Failed to purge account {}: {:?}", account.iam_account_id, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use crate::audit::{self, AuditEntry, AuditEventType, RequestMeta};
+use crate::dba::DbContext;
+use crate::geoip::GeoIp;
+use crate::mailer::{self, Mailer};
+use crate::models::Account;
+use crate::notifications::NewNotification;
+use crate::notifier::Notifier;
+use crate::sessions::{self, SessionPolicy};
+
+/// Where new-device notifications point, since they can't carry the alert token
+const ACCOUNT_SECURITY_PATH: &str = "/dashboard/settings";
+
+/// How long the "this wasn't me" link of a new-device alert can be used
+pub const ALERT_LINK_TTL: Duration = Duration::days(7);
+
+/// Sign-ins from one device within this window collapse into one notification
+const SIGN_IN_DEDUPE_WINDOW: Duration = Duration::days(1);
+
+/// What identifies a device across sign-ins
+pub struct DeviceFingerprint {
+    pub fingerprint: String,
+    pub description: String,
+    pub ip_prefix: Option<String>,
+}
+
+/// Fingerprint a client by its user agent and network prefix.
+///
+/// The prefix (/24 for IPv4, /48 for IPv6) keeps a device recognised when its
+/// address changes within the same network.
+pub fn fingerprint(meta: &RequestMeta) -> DeviceFingerprint {
+    let user_agent = meta.user_agent.as_deref().unwrap_or("").trim();
+    let ip_prefix = meta.ip_address.as_deref().and_then(ip_prefix);
+
+    let hash = Sha256::digest(
+        format!("{}|{}", user_agent, ip_prefix.as_deref().unwrap_or("")).as_bytes(),
+    );
+
+    DeviceFingerprint {
+        fingerprint: hex::encode(hash),
+        description: describe_user_agent(user_agent),
+        ip_prefix,
+    }
+}
+
+fn ip_prefix(ip: &str) -> Option<String> {
+    match ip.parse::<IpAddr>().ok()? {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            Some(format!("{}.{}.{}.0/24", a, b, c))
+        }
+        IpAddr::V6(v6) => {
+            let s = v6.segments();
+            Some(format!("{:x}:{:x}:{:x}::/48", s[0], s[1], s[2]))
+        }
+    }
+}
+
+/// Human readable browser and OS, e.g. "Firefox on Linux"
+pub fn describe_user_agent(user_agent: &str) -> String {
+    let browser = [
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+    ]
+    .iter()
+    .find(|(token, _)| user_agent.contains(token))
+    .map(|(_, name)| *name);
+
+    let os = [
+        ("Android", "Android"),
+        ("iPhone", "iOS"),
+        ("iPad", "iPadOS"),
+        ("Windows", "Windows"),
+        ("Mac OS X", "macOS"),
+        ("CrOS", "ChromeOS"),
+        ("Linux", "Linux"),
+    ]
+    .iter()
+    .find(|(token, _)| user_agent.contains(token))
+    .map(|(_, name)| *name);
+
+    match (browser, os) {
+        (Some(browser), Some(os)) => format!("{} on {}", browser, os),
+        (Some(browser), None) => browser.to_string(),
+        (None, Some(os)) => os.to_string(),
+        (None, None) if user_agent.is_empty() => "Unknown device".to_string(),
+        (None, None) => user_agent.chars().take(100).collect(),
+    }
+}
+
+/// Record a sign-in: remember the device, start a session for the issued
+/// tokens and alert the owner when the device has not been seen before
+#[allow(clippy::too_many_arguments)]
+pub async fn record_sign_in(
+    db: &DbContext,
+    mailer: &Arc<dyn Mailer>,
+    notifier: &Notifier,
+    geoip: &GeoIp,
+    account: &Account,
+    email: &str,
+    meta: &RequestMeta,
+    access_token: &str,
+    refresh_token: &str,
+    access_token_expires_at: DateTime<Utc>,
+    policy: &SessionPolicy,
+) {
+    let device = fingerprint(meta);
+    let location = meta.ip_address.as_deref().and_then(|ip| geoip.lookup(ip));
+
+    let (device_id, is_new_device) = match db
+        .touch_known_device(account.id, &device.fingerprint, location.as_deref())
+        .await
+    {
+        Ok(Some(known)) => (Some(known.id), false),
+        Ok(None) => {
+            // The first device of an account is not worth an alert
+            let had_devices = matches!(db.count_known_devices(account.id).await, Ok(n) if n > 0);
+            match db
+                .create_known_device(
+                    account.id,
+                    &device.fingerprint,
+                    &device.description,
+                    device.ip_prefix.as_deref(),
+                    location.as_deref(),
+                )
+                .await
+            {
+                Ok(created) => (Some(created.id), had_devices),
+                Err(e) => {
+                    log::error!("Failed to remember device: {:?}", e);
+                    (None, had_devices)
+                }
+            }
+        }
+        Err(e) => {
+            log::error!("Failed to look up known device: {:?}", e);
+            (None, false)
+        }
+    };
+
+    let alert_token = is_new_device.then(|| {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        hex::encode(bytes)
+    });
+
+    if let Err(e) = db
+        .create_session(
+            account.id,
+            device_id,
+            &sessions::hash_token(access_token),
+            &sessions::hash_token(refresh_token),
+            access_token_expires_at,
+            meta,
+            location.as_deref(),
+            alert_token.as_deref().map(sessions::hash_token).as_deref(),
+            policy,
+        )
+        .await
+    {
+        log::error!("Failed to record session: {:?}", e);
+    }
+
+    let Some(alert_token) = alert_token else {
+        let notification = NewNotification::new(
+            "security.login",
+            format!("{} signed in from {}", email, device.description),
+        )
+        .with_title("New sign-in")
+        .with_data(serde_json::json!({
+            "device": device.description,
+            "location": location,
+        }))
+        .with_source("devices")
+        // Repeated sign-ins from one device show as a single entry
+        .with_group(format!("security.login:{}", device.fingerprint), SIGN_IN_DEDUPE_WINDOW);
+        if let Err(e) = notifier.notify(account, &notification).await {
+            // Log error but don't fail the login
+            log::warn!("Failed to create sign-in notification: {:?}", e);
+        }
+        return;
+    };
+
+    audit::record(
+        db,
+        meta,
+        AuditEntry::own(AuditEventType::NewDeviceLogin, account.iam_account_id).with_metadata(
+            serde_json::json!({
+                "device": device.description,
+                "location": location,
+            }),
+        ),
+    )
+    .await;
+
+    let origin = match (&location, &meta.ip_address) {
+        (Some(location), Some(ip)) => format!("{} ({})", location, ip),
+        (None, Some(ip)) => ip.clone(),
+        (Some(location), None) => location.clone(),
+        (None, None) => "an unknown location".to_string(),
+    };
+    // The "this wasn't me" token only goes out by email: the notification is
+    // readable by the new session, which may be the one being reported
+    let link = format!("{}/not-me?token={}", mailer::frontend_url(), alert_token);
+
+    let message = format!(
+        "New sign-in from {} near {}. If this wasn't you, follow the link in the email we sent you to secure your account.",
+        device.description, origin
+    );
+    let notification = NewNotification::new("security.new_device", message)
+        .with_title("Sign-in from a new device")
+        .with_data(serde_json::json!({
+            "device": device.description,
+            "location": location,
+            "ip_address": meta.ip_address,
+        }))
+        .with_action("Review account security", ACCOUNT_SECURITY_PATH)
+        .with_source("devices");
+    if let Err(e) = notifier.notify(account, &notification).await {
+        log::warn!("Failed to create new device notification: {:?}", e);
+    }
+
+    let body = format!(
+        "Your account was just signed in to from a new device.\n\n\
+         Device: {}\n\
+         Location: {}\n\
+         Time: {}\n\n\
+         If this was you, you can ignore this email.\n\
+         If this wasn't you, sign that device out and secure your account here:\n{}",
+        device.description,
+        origin,
+        Utc::now().format("%Y-%m-%d %H:%M UTC"),
+        link
+    );
+    if let Err(e) = mailer
+        .send_email(email, "New sign-in to your account", &body)
+        .await
+    {
+        log::error!("Failed to send new device email to {}: {:?}", email, e);
+    }
+}
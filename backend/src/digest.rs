@@ -0,0 +1,274 @@
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use chrono_tz::Tz;
+use hmac::{Hmac, Mac};
+use nano_iam::AuthService;
+use rand::RngCore;
+use sha2::Sha256;
+use std::env;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::dba::DbContext;
+use crate::mailer::{self, Mailer};
+use crate::models::{DigestSchedule, DigestSettings};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Digest frequencies accepted by the API
+pub const FREQUENCIES: &[&str] = &["off", "daily", "weekly"];
+
+/// Digest email configuration
+#[derive(Clone)]
+pub struct DigestConfig {
+    /// HMAC key for signing unsubscribe links; must be shared by all replicas
+    secret: Vec<u8>,
+    /// Accounts handled per run of the digest job
+    pub batch_size: i64,
+    /// Notifications listed in one digest, the rest are only counted
+    pub max_items: i64,
+}
+
+impl DigestConfig {
+    /// Create digest configuration from environment or defaults
+    pub fn from_env() -> Self {
+        let secret = match env::var("DIGEST_LINK_SECRET") {
+            Ok(secret) if !secret.is_empty() => secret.into_bytes(),
+            _ => {
+                log::warn!("DIGEST_LINK_SECRET not set, using a random key; unsubscribe links will not be valid across replicas or restarts");
+                let mut key = vec![0u8; 32];
+                rand::thread_rng().fill_bytes(&mut key);
+                key
+            }
+        };
+
+        Self {
+            secret,
+            batch_size: env::var("DIGEST_BATCH_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(100),
+            max_items: env::var("DIGEST_MAX_ITEMS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(50),
+        }
+    }
+
+    /// Link that turns off an account's digests without signing in
+    pub fn unsubscribe_link(&self, account_id: Uuid) -> String {
+        let payload = account_id.simple().to_string();
+        format!(
+            "{}/unsubscribe?token={}.{}",
+            mailer::frontend_url(),
+            payload,
+            hex::encode(self.sign(&payload))
+        )
+    }
+
+    /// Account an unsubscribe token was issued for, if its signature is valid
+    pub fn parse_unsubscribe_token(&self, token: &str) -> Option<Uuid> {
+        let (payload, signature) = token.rsplit_once('.')?;
+        let signature = hex::decode(signature).ok()?;
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature).ok()?;
+        Uuid::parse_str(payload).ok()
+    }
+
+    fn sign(&self, payload: &str) -> Vec<u8> {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(payload.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+/// Check digest settings submitted by an account
+pub fn validate_settings(settings: &DigestSettings) -> Result<(), String> {
+    if !FREQUENCIES.contains(&settings.frequency.as_str()) {
+        return Err("Invalid frequency. Must be 'off', 'daily', or 'weekly'".to_string());
+    }
+    if settings.time_zone.parse::<Tz>().is_err() {
+        return Err("Unknown time zone".to_string());
+    }
+    if !(0..=23).contains(&settings.hour) {
+        return Err("Hour must be between 0 and 23".to_string());
+    }
+    if !(1..=7).contains(&settings.weekday) {
+        return Err("Weekday must be between 1 (Monday) and 7 (Sunday)".to_string());
+    }
+    Ok(())
+}
+
+/// First digest slot strictly after `after`, or `None` when digests are off
+pub fn next_digest_at(
+    frequency: &str,
+    time_zone: &str,
+    hour: i16,
+    weekday: i16,
+    after: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    if frequency == "off" {
+        return None;
+    }
+    let tz: Tz = time_zone.parse().ok()?;
+    let mut date = after.with_timezone(&tz).date_naive();
+
+    // A week and a day covers every weekly slot, whatever the local time now
+    for _ in 0..8 {
+        let on_day = frequency == "daily" || date.weekday().number_from_monday() == weekday as u32;
+        if on_day {
+            let local = date.and_hms_opt(hour as u32, 0, 0)?;
+            // Skip past hours that don't exist on days the clocks go forward
+            let slot = tz
+                .from_local_datetime(&local)
+                .earliest()
+                .or_else(|| tz.from_local_datetime(&(local + Duration::hours(1))).earliest());
+            if let Some(slot) = slot.map(|slot| slot.with_timezone(&Utc)) {
+                if slot > after {
+                    return Some(slot);
+                }
+            }
+        }
+        date = date.succ_opt()?;
+    }
+    None
+}
+
+/// Email the digests that are due
+pub async fn send_due_digests(
+    db: &DbContext,
+    auth_service: &AuthService,
+    mailer: &Arc<dyn Mailer>,
+    config: &DigestConfig,
+) {
+    let due = match db.get_due_digests(Utc::now(), config.batch_size).await {
+        Ok(due) => due,
+        Err(e) => {
+            log::error!("Failed to list due notification digests: {:?}", e);
+            return;
+        }
+    };
+
+    for schedule in due {
+        if let Err(e) = send_digest(db, auth_service, mailer, config, &schedule).await {
+            log::error!(
+                "Failed to send notification digest for account {}: {:?}",
+                schedule.iam_account_id,
+                e
+            );
+        }
+    }
+}
+
+async fn send_digest(
+    db: &DbContext,
+    auth_service: &AuthService,
+    mailer: &Arc<dyn Mailer>,
+    config: &DigestConfig,
+    schedule: &DigestSchedule,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    let next = next_digest_at(
+        &schedule.digest_frequency,
+        &schedule.digest_time_zone,
+        schedule.digest_hour,
+        schedule.digest_weekday,
+        now,
+    );
+
+    // Fetch one more than listed to learn whether some are left out
+    let notifications = db
+        .get_unread_notifications_between(
+            schedule.account_id,
+            schedule.last_digest_at,
+            now,
+            config.max_items + 1,
+        )
+        .await?;
+    let items = db
+        .get_pending_digest_items(schedule.account_id, config.max_items)
+        .await?;
+    if notifications.is_empty() && items.is_empty() {
+        return db.reschedule_digest(schedule.account_id, next).await;
+    }
+
+    let listed = notifications.len().min(config.max_items as usize);
+    let notification_ids: Vec<Uuid> = notifications[..listed].iter().map(|n| n.id).collect();
+    let item_ids: Vec<Uuid> = items.iter().map(|item| item.id).collect();
+    let Some(digest_id) = db
+        .record_notification_digest(
+            schedule.account_id,
+            schedule.next_digest_at,
+            schedule.last_digest_at,
+            now,
+            &notification_ids,
+            &item_ids,
+            next,
+        )
+        .await?
+    else {
+        // Another run already sent this slot's digest
+        return Ok(());
+    };
+
+    let email = match auth_service.get_account(schedule.iam_account_id).await {
+        Ok(iam_account) => iam_account.email,
+        Err(e) => {
+            log::warn!("Failed to get IAM account for notification digest: {:?}", e);
+            return Ok(());
+        }
+    };
+
+    let mut lines = Vec::new();
+    for n in &notifications[..listed] {
+        lines.push(entry(n.title.as_deref(), &n.message, n.action_url.as_deref()));
+    }
+    for item in &items {
+        lines.push(entry(item.title.as_deref(), &item.message, item.action_url.as_deref()));
+    }
+    let more = if notifications.len() > listed {
+        "\n\n...and more in the app."
+    } else {
+        ""
+    };
+
+    let period = if schedule.digest_frequency == "weekly" {
+        "weekly"
+    } else {
+        "daily"
+    };
+    let subject = format!("Your {} notification digest", period);
+    let body = format!(
+        "Here is what happened since your last digest:\n\n{}{}\n\n\
+         See all your notifications: {}/notifications\n\n\
+         To stop receiving these emails, unsubscribe here:\n{}",
+        lines.join("\n\n"),
+        more,
+        mailer::frontend_url(),
+        config.unsubscribe_link(schedule.account_id)
+    );
+
+    match mailer.send_email(&email, &subject, &body).await {
+        Ok(()) => db.mark_notification_digest_sent(digest_id).await,
+        Err(e) => {
+            log::error!("Failed to send notification digest email: {:?}", e);
+            Ok(())
+        }
+    }
+}
+
+fn entry(title: Option<&str>, message: &str, action_url: Option<&str>) -> String {
+    let mut entry = match title {
+        Some(title) => format!("- {}: {}", title, message),
+        None => format!("- {}", message),
+    };
+    if let Some(url) = action_url {
+        let url = if url.starts_with('/') {
+            format!("{}{}", mailer::frontend_url(), url)
+        } else {
+            url.to_string()
+        };
+        entry.push_str(&format!("\n  {}", url));
+    }
+    entry
+}
@@ -0,0 +1,264 @@
+use chrono::{Duration, Utc};
+use nano_iam::AuthService;
+use serde::Serialize;
+use std::env;
+use std::io::{Cursor, Write};
+use std::sync::Arc;
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::dba::DbContext;
+use crate::models::{Account, AuditEvent, KnownDevice, Notification, Session};
+use crate::notifications::NewNotification;
+use crate::notifier::Notifier;
+
+type ExportError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Personal data export configuration
+#[derive(Clone)]
+pub struct ExportConfig {
+    /// How long a finished archive can be downloaded
+    pub download_ttl: Duration,
+    /// How long an export may stay pending before it is considered lost
+    pub pending_timeout: Duration,
+    /// How often expired archives are removed
+    pub cleanup_interval: std::time::Duration,
+}
+
+impl ExportConfig {
+    /// Create export configuration from environment or defaults
+    pub fn from_env() -> Self {
+        let ttl_hours = env::var("DATA_EXPORT_TTL_HOURS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(24);
+        let timeout_minutes = env::var("DATA_EXPORT_TIMEOUT_MINUTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(30);
+        Self {
+            download_ttl: Duration::hours(ttl_hours),
+            pending_timeout: Duration::minutes(timeout_minutes),
+            cleanup_interval: std::time::Duration::from_secs(3600),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct IamAccountData {
+    id: Uuid,
+    email: String,
+    auth_type: String,
+}
+
+#[derive(Serialize)]
+struct AccountData<'a> {
+    iam_account: IamAccountData,
+    profile: &'a Account,
+}
+
+/// Build the export archive for an account, store it and notify the owner
+pub async fn run_export(
+    db: DbContext,
+    auth_service: Arc<AuthService>,
+    notifier: Arc<Notifier>,
+    config: ExportConfig,
+    export_id: Uuid,
+    account: Account,
+) {
+    let archive = match build_archive(&db, &auth_service, &account).await {
+        Ok(archive) => archive,
+        Err(e) => {
+            log::error!("Failed to build data export {}: {:?}", export_id, e);
+            if let Err(e) = db.fail_data_export(export_id).await {
+                log::error!("Failed to mark data export {} as failed: {:?}", export_id, e);
+            }
+            return;
+        }
+    };
+
+    let expires_at = Utc::now() + config.download_ttl;
+    if let Err(e) = db.complete_data_export(export_id, &archive, expires_at).await {
+        log::error!("Failed to store data export {}: {:?}", export_id, e);
+        if let Err(e) = db.fail_data_export(export_id).await {
+            log::error!("Failed to mark data export {} as failed: {:?}", export_id, e);
+        }
+        return;
+    }
+
+    let message = format!(
+        "Your data export is ready. It can be downloaded until {}.",
+        expires_at.format("%Y-%m-%d %H:%M UTC")
+    );
+    let notification = NewNotification::new("export.ready", message)
+        .with_title("Your data export is ready")
+        .with_data(serde_json::json!({
+            "export_id": export_id,
+            "expires_at": expires_at.to_rfc3339(),
+        }))
+        .with_source("export")
+        .with_expiry(expires_at);
+    if let Err(e) = notifier.notify(&account, &notification).await {
+        log::warn!("Failed to send data export notification: {:?}", e);
+    }
+}
+
+/// Remove archives whose download window has passed, and fail exports that
+/// have been pending for too long
+pub async fn clear_expired_exports(db: &DbContext, config: &ExportConfig) {
+    match db.clear_expired_data_exports().await {
+        Ok(0) => {}
+        Ok(count) => log::info!("Removed {} expired data export archive(s)", count),
+        Err(e) => log::error!("Failed to clear expired data exports: {:?}", e),
+    }
+    match db.fail_stale_data_exports(None, Utc::now() - config.pending_timeout).await {
+        Ok(0) => {}
+        Ok(count) => log::warn!("Marked {} stale data export(s) as failed", count),
+        Err(e) => log::error!("Failed to fail stale data exports: {:?}", e),
+    }
+}
+
+async fn build_archive(
+    db: &DbContext,
+    auth_service: &AuthService,
+    account: &Account,
+) -> Result<Vec<u8>, ExportError> {
+    let iam_account = auth_service.get_account(account.iam_account_id).await?;
+    let notifications = db.get_notifications(account.id).await?;
+    let audit_events = db
+        .get_all_audit_events_for_account(account.iam_account_id)
+        .await?;
+
+    let sessions = db.get_sessions_for_account(account.id).await?;
+    let devices = db.get_known_devices(account.id).await?;
+
+    let account_data = AccountData {
+        iam_account: IamAccountData {
+            id: iam_account.id,
+            email: iam_account.email,
+            auth_type: format!("{:?}", iam_account.auth_type).to_lowercase(),
+        },
+        profile: account,
+    };
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file("account.json", options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&account_data)?)?;
+
+    zip.start_file("notifications.json", options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&notifications)?)?;
+    zip.start_file("notifications.csv", options)?;
+    zip.write_all(notifications_csv(&notifications).as_bytes())?;
+
+    zip.start_file("audit_events.json", options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&audit_events)?)?;
+    zip.start_file("audit_events.csv", options)?;
+    zip.write_all(audit_events_csv(audit_events.iter()).as_bytes())?;
+
+    zip.start_file("sessions.json", options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&sessions)?)?;
+    zip.start_file("sessions.csv", options)?;
+    zip.write_all(sessions_csv(&sessions).as_bytes())?;
+
+    zip.start_file("devices.json", options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&devices)?)?;
+    zip.start_file("devices.csv", options)?;
+    zip.write_all(devices_csv(&devices).as_bytes())?;
+
+    Ok(zip.finish()?.into_inner())
+}
+
+fn notifications_csv(notifications: &[Notification]) -> String {
+    let mut out = String::from("id,kind,level,title,message,action_url,source,read,created_at,updated_at\n");
+    for n in notifications {
+        csv_row(
+            &mut out,
+            &[
+                n.id.to_string(),
+                n.kind.clone(),
+                n.level.clone(),
+                n.title.clone().unwrap_or_default(),
+                n.message.clone(),
+                n.action_url.clone().unwrap_or_default(),
+                n.source.clone().unwrap_or_default(),
+                n.read.to_string(),
+                n.created_at.to_rfc3339(),
+                n.updated_at.to_rfc3339(),
+            ],
+        );
+    }
+    out
+}
+
+fn audit_events_csv<'a>(events: impl Iterator<Item = &'a AuditEvent>) -> String {
+    let mut out = String::from("id,event_type,ip_address,user_agent,metadata,created_at\n");
+    for e in events {
+        csv_row(
+            &mut out,
+            &[
+                e.id.to_string(),
+                e.event_type.clone(),
+                e.ip_address.clone().unwrap_or_default(),
+                e.user_agent.clone().unwrap_or_default(),
+                e.metadata.to_string(),
+                e.created_at.to_rfc3339(),
+            ],
+        );
+    }
+    out
+}
+
+fn sessions_csv(sessions: &[Session]) -> String {
+    let mut out = String::from("id,ip_address,user_agent,location,created_at,last_seen_at,revoked_at\n");
+    for s in sessions {
+        csv_row(
+            &mut out,
+            &[
+                s.id.to_string(),
+                s.ip_address.clone().unwrap_or_default(),
+                s.user_agent.clone().unwrap_or_default(),
+                s.location.clone().unwrap_or_default(),
+                s.created_at.to_rfc3339(),
+                s.last_seen_at.to_rfc3339(),
+                s.revoked_at.map(|t| t.to_rfc3339()).unwrap_or_default(),
+            ],
+        );
+    }
+    out
+}
+
+fn devices_csv(devices: &[KnownDevice]) -> String {
+    let mut out = String::from("id,description,ip_prefix,location,first_seen_at,last_seen_at\n");
+    for d in devices {
+        csv_row(
+            &mut out,
+            &[
+                d.id.to_string(),
+                d.description.clone(),
+                d.ip_prefix.clone().unwrap_or_default(),
+                d.location.clone().unwrap_or_default(),
+                d.first_seen_at.to_rfc3339(),
+                d.last_seen_at.to_rfc3339(),
+            ],
+        );
+    }
+    out
+}
+
+fn csv_row(out: &mut String, fields: &[String]) {
+    let escaped: Vec<String> = fields
+        .iter()
+        .map(|f| {
+            if f.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", f.replace('"', "\"\""))
+            } else {
+                f.clone()
+            }
+        })
+        .collect();
+    out.push_str(&escaped.join(","));
+    out.push('\n');
+}
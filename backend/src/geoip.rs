@@ -0,0 +1,51 @@
+use maxminddb::{geoip2, Reader};
+use std::env;
+use std::net::IpAddr;
+
+/// Offline IP geolocation backed by a MaxMind City database (`.mmdb`)
+pub struct GeoIp {
+    reader: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIp {
+    /// Load the database at `GEOIP_DATABASE_PATH`, or run without geolocation
+    pub fn from_env() -> Self {
+        let path = match env::var("GEOIP_DATABASE_PATH") {
+            Ok(path) if !path.is_empty() => path,
+            _ => return Self { reader: None },
+        };
+
+        match Reader::open_readfile(&path) {
+            Ok(reader) => {
+                log::info!("Loaded GeoIP database from {}", path);
+                Self {
+                    reader: Some(reader),
+                }
+            }
+            Err(e) => {
+                log::error!("Failed to load GeoIP database from {}: {:?}", path, e);
+                Self { reader: None }
+            }
+        }
+    }
+
+    /// Approximate location of an address, e.g. "Berlin, Germany"
+    pub fn lookup(&self, ip: &str) -> Option<String> {
+        let reader = self.reader.as_ref()?;
+        let ip: IpAddr = ip.parse().ok()?;
+        let record = reader.lookup::<geoip2::City>(ip).ok()?;
+
+        let english_name = |names: Option<std::collections::BTreeMap<&str, &str>>| {
+            names.and_then(|names| names.get("en").map(|name| name.to_string()))
+        };
+        let city = record.city.and_then(|c| english_name(c.names));
+        let country = record.country.and_then(|c| english_name(c.names));
+
+        match (city, country) {
+            (Some(city), Some(country)) => Some(format!("{}, {}", city, country)),
+            (None, Some(country)) => Some(country),
+            (Some(city), None) => Some(city),
+            (None, None) => None,
+        }
+    }
+}
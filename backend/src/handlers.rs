@@ -1,29 +1,149 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Responder};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use chrono::Utc;
 use nano_iam::{AuthService, AuthType, IamError};
 use std::sync::Arc;
 use std::env;
+use std::time::Instant;
 
-use crate::auth::AuthenticatedUser;
+use crate::audit::{self, AuditEntry, AuditEventType, RequestMeta};
+use crate::auth::{AdminConfig, AdminUser, AuthenticatedUser, CurrentAccount};
+use crate::broadcasts;
+use crate::client_ip::ClientIp;
 use crate::dba::DbContext;
+use crate::deletion::{self, DeletionConfig};
+use crate::devices;
+use crate::digest::{self, DigestConfig};
+use crate::export::{self, ExportConfig};
+use crate::geoip::GeoIp;
+use crate::mailer::{self, Mailer};
+use crate::notification_stream::NotificationHub;
+use crate::notifications::{self, Channel, CreateError, NewNotification, NotificationCursor};
+use crate::notifier::{self, Notifier};
+use crate::password_check::{self, PasswordCheckConfig};
+use crate::pow::{PowAction, PowService};
+use crate::privacy::{self, PrivacyConfig};
+use crate::reauth::{self, ReauthConfig, ReauthError, RequireRecentAuth};
+use crate::realtime::RealtimeConfig;
+use crate::registration::{self, RegistrationConfig, RegistrationMode};
+use crate::sessions::{self, SessionConfig};
+use crate::token_cache::TokenCache;
+use crate::usernames;
+use crate::verification::{self, LinkError, LinkToken, VerificationConfig};
 use crate::models::{
-    AccountInfo, AccountSettings, AuthResponse, ChangePasswordRequest,
-    CreateNotificationRequest, DeleteAccountRequest, GoogleLoginRequest, LoginRequest,
-    RefreshTokenRequest, SignupRequest, SignupResponse, UpdateAccountSettingsRequest,
-    UpdateNotificationRequest, VerifyEmailRequest,
+    AccountInfo, AccountSettings, ApproveWaitlistRequest, AuditEventsQuery, AuthResponse,
+    BatchDeleteNotificationsRequest, BatchUpdateNotificationsRequest, BulkDeleteNotificationsRequest,
+    BulkUpdateNotificationsRequest, ChallengeQuery, ChangePasswordRequest, CreateBroadcastRequest, CreateInviteRequest, CreateNotificationRequest, DeleteAccountRequest,
+    GoogleLoginRequest, LoginRequest, NotificationFilter, NotificationPage, NotificationsQuery, ReauthRequest, RefreshTokenRequest, ReportLoginRequest, RequestPasswordResetRequest,
+    ResetPasswordRequest, SecurityLogQuery, SignupRequest,
+    SignupResponse, UnsubscribeDigestRequest, UpdateAccountSettingsRequest, UpdateNotificationPreferencesRequest,
+    UpdateNotificationRequest, VerifyEmailLinkQuery,
+    VerifyEmailRequest, WaitlistQuery,
 };
 
+const SIGNUP_MESSAGE: &str = "Account created. Please check your email for verification code.";
+const RESEND_VERIFICATION_MESSAGE: &str = "Verification email sent";
+const LOGIN_FAILED_MESSAGE: &str = "Invalid username, email or password";
+const PASSWORD_RESET_MESSAGE: &str =
+    "If an account with a password uses this email, we've sent it a password reset code.";
+
+/// Most broadcasts listed for admins
+const BROADCASTS_LIST_LIMIT: i64 = 100;
+
+/// Default and maximum page size for audit log queries
+const AUDIT_LOG_DEFAULT_LIMIT: i64 = 50;
+const AUDIT_LOG_MAX_LIMIT: i64 = 500;
+
+/// Default and maximum page size for the notifications list
+const NOTIFICATIONS_DEFAULT_LIMIT: i64 = 50;
+const NOTIFICATIONS_MAX_LIMIT: i64 = 200;
+
+/// Most ids accepted by the batch endpoints
+const NOTIFICATIONS_BATCH_MAX_IDS: usize = 1000;
+
+/// Notifications changed per statement, and per stream event, by the bulk endpoints
+const NOTIFICATIONS_BULK_BATCH_SIZE: i64 = 500;
+
+#[allow(clippy::too_many_arguments)]
 pub async fn signup(
     auth_service: web::Data<Arc<AuthService>>,
     db: web::Data<DbContext>,
+    registration: web::Data<RegistrationConfig>,
+    password_config: web::Data<PasswordCheckConfig>,
+    pow: web::Data<Arc<PowService>>,
+    privacy: web::Data<PrivacyConfig>,
+    mailer: web::Data<Arc<dyn Mailer>>,
+    meta: RequestMeta,
+    http_req: HttpRequest,
     req: web::Json<SignupRequest>,
 ) -> impl Responder {
+    let started = Instant::now();
+
+    if let Err(e) = pow
+        .verify(&db, http_req.headers(), PowAction::Signup, meta.ip_address.as_deref())
+        .await
+    {
+        return e.error_response();
+    }
+
+    if let Err(msg) = registration.check_email_domain(&req.email) {
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg
+        }));
+    }
+
+    if let Err(rejection) =
+        password_check::check_password(&password_config, &req.password, &[&req.email]).await
+    {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": rejection.message,
+            "details": rejection.details
+        }));
+    }
+
+    let invite_id = match admit_new_account(&db, &registration, &req.email, req.invite_code.as_deref())
+        .await
+    {
+        Ok(invite_id) => invite_id,
+        Err(response) => return response,
+    };
+
     // Register with IAM
-    let iam_account = match auth_service.register(&req.email, &req.password).await {
+    let register_result = auth_service.register(&req.email, &req.password).await;
+    if let (Err(_), Some(invite_id)) = (&register_result, invite_id) {
+        if let Err(e) = db.release_invite_code(invite_id).await {
+            log::warn!("Failed to release invite code: {:?}", e);
+        }
+    }
+
+    let iam_account = match register_result {
         Ok(account) => account,
         Err(IamError::Db(sqlx::Error::Database(db_err))) if db_err.constraint() == Some("accounts_email_key") => {
-            return HttpResponse::Conflict().json(serde_json::json!({
-                "error": "Email already exists"
-            }));
+            if !privacy.enabled {
+                return HttpResponse::Conflict().json(serde_json::json!({
+                    "error": "Email already exists"
+                }));
+            }
+
+            // Answer as if the account was created and let the owner know instead
+            let existing_id = db.find_iam_account_id_by_email(&req.email).await.ok().flatten();
+            audit::record(
+                &db,
+                &meta,
+                AuditEntry::anonymous(AuditEventType::SignupExistingEmail, existing_id),
+            )
+            .await;
+            privacy::notify_owner(
+                &mailer,
+                &req.email,
+                "Someone tried to sign up with your email",
+                privacy::signup_attempt_body(),
+            );
+            privacy.pad(started).await;
+            return HttpResponse::Ok().json(SignupResponse {
+                email: req.email.clone(),
+                message: SIGNUP_MESSAGE.to_string(),
+            });
         }
         Err(IamError::WeakPassword(msg)) => {
             return HttpResponse::BadRequest().json(serde_json::json!({
@@ -51,37 +171,185 @@ pub async fn signup(
         }));
     }
 
+    // Tries made against the address before it was registered don't count
+    // against its first code
+    if let Err(e) = db
+        .clear_verification_attempts(&audit::identifier_hash(&iam_account.email))
+        .await
+    {
+        log::warn!("Failed to clear verification attempts: {:?}", e);
+    }
+
+    if let Some(invite_id) = invite_id {
+        if let Err(e) = db.record_invite_redemption(invite_id, iam_account.id).await {
+            log::warn!("Failed to record invite redemption: {:?}", e);
+        }
+    }
+
+    audit::record(
+        &db,
+        &meta,
+        AuditEntry::own(AuditEventType::Signup, iam_account.id)
+            .with_metadata(serde_json::json!({ "invite_code_id": invite_id })),
+    )
+    .await;
+
+    privacy.pad(started).await;
+
     // Return signup response without tokens - user needs to verify email first
     HttpResponse::Ok().json(SignupResponse {
-        account_id: iam_account.id,
         email: iam_account.email,
-        message: "Account created. Please check your email for verification code.".to_string(),
+        message: SIGNUP_MESSAGE.to_string(),
     })
 }
 
+/// Apply the registration mode to a new account.
+///
+/// Returns the invite reserved for it, if any, or the response to send when the
+/// account may not be created: an invite is required, or the address was
+/// added to the waitlist instead.
+async fn admit_new_account(
+    db: &DbContext,
+    registration: &RegistrationConfig,
+    email: &str,
+    invite_code: Option<&str>,
+) -> Result<Option<uuid::Uuid>, HttpResponse> {
+    // Closed registration modes require an invite code
+    let invite_code = invite_code.filter(|c| !c.trim().is_empty());
+    match (registration.mode, invite_code) {
+        (RegistrationMode::Open, _) => Ok(None),
+        (_, Some(code)) => match db.reserve_invite_code(code, email).await {
+            Ok(Some(id)) => Ok(Some(id)),
+            Ok(None) => Err(HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "Invalid or expired invite code"
+            }))),
+            Err(e) => {
+                log::error!("Failed to check invite code: {:?}", e);
+                Err(HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": "Failed to create account"
+                })))
+            }
+        },
+        (RegistrationMode::Waitlist, None) => match db.join_waitlist(email).await {
+            Ok(_) => Err(HttpResponse::Accepted().json(serde_json::json!({
+                "message": "You have been added to the waitlist. We will email you once your signup is approved."
+            }))),
+            Err(e) => {
+                log::error!("Failed to join waitlist: {:?}", e);
+                Err(HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": "Failed to join waitlist"
+                })))
+            }
+        },
+        (RegistrationMode::InviteOnly, None) => {
+            Err(HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "An invite code is required to sign up"
+            })))
+        }
+    }
+}
+
 pub async fn verify_email(
     auth_service: web::Data<Arc<AuthService>>,
+    db: web::Data<DbContext>,
+    verification: web::Data<VerificationConfig>,
+    privacy: web::Data<PrivacyConfig>,
+    meta: RequestMeta,
     req: web::Json<VerifyEmailRequest>,
 ) -> impl Responder {
-    match auth_service
-        .verify_email(req.account_id, &req.code)
-        .await
-    {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-            "message": "Email verified successfully"
-        })),
-        Err(IamError::InvalidVerificationCode) => {
-            HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Invalid verification code"
+    let started = Instant::now();
+    let response =
+        check_verification_code(&auth_service, &db, &verification, &privacy, &meta, &req).await;
+    privacy.pad(started).await;
+    response
+}
+
+/// Check a typed verification code. Addresses that aren't registered are
+/// counted and answered exactly like registered ones, so they can't be told apart.
+async fn check_verification_code(
+    auth_service: &AuthService,
+    db: &DbContext,
+    verification: &VerificationConfig,
+    privacy: &PrivacyConfig,
+    meta: &RequestMeta,
+    req: &VerifyEmailRequest,
+) -> HttpResponse {
+    let email_hash = audit::identifier_hash(&req.email);
+
+    // Count the attempt up front; checking first would let parallel requests
+    // with different codes all get past the limit
+    let attempts = match db.reserve_verification_attempt(&email_hash).await {
+        Ok(attempts) => attempts,
+        Err(e) => {
+            log::error!("Failed to record verification attempt: {:?}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to verify email"
+            }));
+        }
+    };
+    if attempts > verification.max_attempts {
+        return too_many_verification_attempts();
+    }
+    // The code has used up its tries and stops working, even if a later try is right
+    let wrong_code = || {
+        if attempts >= verification.max_attempts {
+            too_many_verification_attempts()
+        } else {
+            invalid_verification_code(verification.max_attempts - attempts)
+        }
+    };
+
+    let account_id = match db.find_iam_account_id_by_email(&req.email).await {
+        Ok(Some(id)) => id,
+        Ok(None) => return wrong_code(),
+        Err(e) => {
+            log::error!("Failed to find account to verify: {:?}", e);
+            if let Err(e) = db.release_verification_attempt(&email_hash).await {
+                log::warn!("Failed to release verification attempt: {:?}", e);
+            }
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to verify email"
+            }));
+        }
+    };
+
+    match auth_service.verify_email(account_id, &req.code).await {
+        Ok(_) => {
+            if let Err(e) = db.clear_verification_attempts(&email_hash).await {
+                log::warn!("Failed to clear verification attempts: {:?}", e);
+            }
+            audit::record(
+                db,
+                meta,
+                AuditEntry::own(AuditEventType::EmailVerified, account_id),
+            )
+            .await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "Email verified successfully"
             }))
         }
+        Err(IamError::InvalidVerificationCode) | Err(IamError::AccountNotFound) => wrong_code(),
+        // Only registered addresses have a code to expire or an email already verified
+        Err(IamError::VerificationCodeExpired) | Err(IamError::EmailAlreadyVerified)
+            if privacy.enabled =>
+        {
+            wrong_code()
+        }
         Err(IamError::VerificationCodeExpired) => {
             HttpResponse::BadRequest().json(serde_json::json!({
                 "error": "Verification code expired. Please request a new one."
             }))
         }
+        Err(IamError::EmailAlreadyVerified) => {
+            HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Email already verified"
+            }))
+        }
         Err(e) => {
             log::error!("Email verification error: {:?}", e);
+            if let Err(e) = db.release_verification_attempt(&email_hash).await {
+                log::warn!("Failed to release verification attempt: {:?}", e);
+            }
             HttpResponse::InternalServerError().json(serde_json::json!({
                 "error": "Failed to verify email"
             }))
@@ -89,10 +357,143 @@ pub async fn verify_email(
     }
 }
 
+fn invalid_verification_code(attempts_remaining: i32) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "error": "Invalid verification code",
+        "attempts_remaining": attempts_remaining
+    }))
+}
+
+fn too_many_verification_attempts() -> HttpResponse {
+    HttpResponse::TooManyRequests().json(serde_json::json!({
+        "error": "Too many incorrect attempts. Please request a new verification code."
+    }))
+}
+
+fn invalid_login() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "error": LOGIN_FAILED_MESSAGE
+    }))
+}
+
+fn password_reset_required() -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({
+        "error": "Please choose a new password with the reset code we emailed you.",
+        "password_reset_required": true
+    }))
+}
+
+/// Verify an email from the signed link in the verification email, then
+/// redirect to the frontend with the outcome
+pub async fn verify_email_link(
+    auth_service: web::Data<Arc<AuthService>>,
+    db: web::Data<DbContext>,
+    verification: web::Data<VerificationConfig>,
+    meta: RequestMeta,
+    query: web::Query<VerifyEmailLinkQuery>,
+) -> impl Responder {
+    let status = match verification.parse_link_token(&query.token) {
+        Ok(link) => verify_link(&auth_service, &db, &verification, &meta, &query.token, link).await,
+        Err(LinkError::Expired) => "expired",
+        Err(LinkError::Invalid) => "invalid",
+    };
+
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, verification::result_url(status)))
+        .finish()
+}
+
+async fn verify_link(
+    auth_service: &AuthService,
+    db: &DbContext,
+    verification: &VerificationConfig,
+    meta: &RequestMeta,
+    token: &str,
+    link: LinkToken,
+) -> &'static str {
+    let token_hash = sessions::hash_token(token);
+    match db.is_verification_link_used(&token_hash).await {
+        Ok(true) => return "already_verified",
+        Ok(false) => {}
+        Err(e) => {
+            log::error!("Failed to check verification link: {:?}", e);
+            return "error";
+        }
+    }
+
+    let account_id = match db.find_iam_account_id_by_email(&link.email).await {
+        Ok(Some(id)) => id,
+        Ok(None) => return "invalid",
+        Err(e) => {
+            log::error!("Failed to find account for verification link: {:?}", e);
+            return "error";
+        }
+    };
+
+    // Links are signed, so they aren't guesses and aren't counted; but a link
+    // carries the same code as the email, which dies once it has used up its tries
+    let email_hash = audit::identifier_hash(&link.email);
+    match db.get_verification_attempts(&email_hash).await {
+        Ok(attempts) if attempts >= verification.max_attempts => return "too_many_attempts",
+        Ok(_) => {}
+        Err(e) => {
+            log::error!("Failed to check verification attempts: {:?}", e);
+            return "error";
+        }
+    }
+
+    match auth_service.verify_email(account_id, &link.code).await {
+        Ok(_) => {
+            if let Err(e) = db.mark_verification_link_used(&token_hash, link.expires_at).await {
+                log::error!("Failed to mark verification link as used: {:?}", e);
+            }
+            if let Err(e) = db.clear_verification_attempts(&email_hash).await {
+                log::warn!("Failed to clear verification attempts: {:?}", e);
+            }
+            audit::record(
+                db,
+                meta,
+                AuditEntry::own(AuditEventType::EmailVerified, account_id)
+                    .with_metadata(serde_json::json!({ "method": "link" })),
+            )
+            .await;
+            "verified"
+        }
+        Err(IamError::EmailAlreadyVerified) => "already_verified",
+        Err(IamError::VerificationCodeExpired) => "expired",
+        Err(IamError::InvalidVerificationCode) | Err(IamError::AccountNotFound) => "invalid",
+        Err(e) => {
+            log::error!("Email verification link error: {:?}", e);
+            "error"
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn resend_verification(
     auth_service: web::Data<Arc<AuthService>>,
+    db: web::Data<DbContext>,
+    pow: web::Data<Arc<PowService>>,
+    privacy: web::Data<PrivacyConfig>,
+    mailer: web::Data<Arc<dyn Mailer>>,
+    meta: RequestMeta,
+    http_req: HttpRequest,
     req: web::Json<serde_json::Value>,
 ) -> impl Responder {
+    let started = Instant::now();
+
+    if let Err(e) = pow
+        .verify(
+            &db,
+            http_req.headers(),
+            PowAction::ResendVerification,
+            meta.ip_address.as_deref(),
+        )
+        .await
+    {
+        return e.error_response();
+    }
+
     let email = match req.get("email").and_then(|v| v.as_str()) {
         Some(e) => e,
         None => {
@@ -103,14 +504,58 @@ pub async fn resend_verification(
     };
 
     match auth_service.resend_verification_email(email).await {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-            "message": "Verification email sent"
-        })),
+        Ok(_) => {
+            // The new code gets its own tries
+            if let Err(e) = db.clear_verification_attempts(&audit::identifier_hash(email)).await {
+                log::warn!("Failed to clear verification attempts: {:?}", e);
+            }
+            privacy.pad(started).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": RESEND_VERIFICATION_MESSAGE
+            }))
+        }
+        Err(IamError::AccountNotFound) if privacy.enabled => {
+            audit::record(
+                &db,
+                &meta,
+                AuditEntry::anonymous(AuditEventType::VerificationResendRejected, None).with_metadata(
+                    serde_json::json!({
+                        "email_hash": audit::identifier_hash(email),
+                        "reason": "account_not_found",
+                    }),
+                ),
+            )
+            .await;
+            privacy.pad(started).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": RESEND_VERIFICATION_MESSAGE
+            }))
+        }
         Err(IamError::AccountNotFound) => {
             HttpResponse::NotFound().json(serde_json::json!({
                 "error": "Account not found"
             }))
         }
+        Err(IamError::EmailAlreadyVerified) if privacy.enabled => {
+            let account_id = db.find_iam_account_id_by_email(email).await.ok().flatten();
+            audit::record(
+                &db,
+                &meta,
+                AuditEntry::anonymous(AuditEventType::VerificationResendRejected, account_id)
+                    .with_metadata(serde_json::json!({ "reason": "already_verified" })),
+            )
+            .await;
+            privacy::notify_owner(
+                &mailer,
+                email,
+                "Your email is already verified",
+                privacy::already_verified_body(),
+            );
+            privacy.pad(started).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": RESEND_VERIFICATION_MESSAGE
+            }))
+        }
         Err(IamError::EmailAlreadyVerified) => {
             HttpResponse::BadRequest().json(serde_json::json!({
                 "error": "Email already verified"
@@ -125,22 +570,67 @@ pub async fn resend_verification(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn login(
     auth_service: web::Data<Arc<AuthService>>,
     db: web::Data<DbContext>,
+    pow: web::Data<Arc<PowService>>,
+    privacy: web::Data<PrivacyConfig>,
+    mailer: web::Data<Arc<dyn Mailer>>,
+    notifier: web::Data<Arc<Notifier>>,
+    geoip: web::Data<Arc<GeoIp>>,
+    session_config: web::Data<SessionConfig>,
+    token_cache: web::Data<Arc<TokenCache>>,
+    meta: RequestMeta,
+    http_req: HttpRequest,
     req: web::Json<LoginRequest>,
 ) -> impl Responder {
+    let started = Instant::now();
+
+    if let Err(e) = pow
+        .verify(&db, http_req.headers(), PowAction::Login, meta.ip_address.as_deref())
+        .await
+    {
+        return e.error_response();
+    }
+
+    let email = if usernames::is_email(&req.login) {
+        req.login.trim().to_string()
+    } else {
+        match db.find_email_by_username(&usernames::normalize(&req.login)).await {
+            Ok(Some(email)) => email,
+            Ok(None) => {
+                record_login_failure(&db, &pow, &meta, &req.login, "unknown_username").await;
+                privacy.pad(started).await;
+                return invalid_login();
+            }
+            Err(e) => {
+                log::error!("Failed to look up username: {:?}", e);
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": "Login failed"
+                }));
+            }
+        }
+    };
+
     let login_result = match auth_service
-        .login_with_auth_type(&req.email, &req.password, AuthType::Email)
+        .login_with_auth_type(&email, &req.password, AuthType::Email)
         .await
     {
         Ok(result) => result,
         Err(IamError::InvalidCredentials) => {
-            return HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Invalid email or password"
-            }));
+            record_login_failure(&db, &pow, &meta, &email, "invalid_credentials").await;
+            privacy.pad(started).await;
+            return invalid_login();
+        }
+        // Only registered addresses can be unverified
+        Err(IamError::EmailNotVerified) if privacy.enabled => {
+            record_login_failure(&db, &pow, &meta, &email, "email_not_verified").await;
+            privacy.pad(started).await;
+            return invalid_login();
         }
         Err(IamError::EmailNotVerified) => {
+            record_login_failure(&db, &pow, &meta, &email, "email_not_verified").await;
             return HttpResponse::Unauthorized().json(serde_json::json!({
                 "error": "Email not verified. Please check your email for verification code."
             }));
@@ -169,16 +659,43 @@ pub async fn login(
         }
     };
 
-    // Create sign-in notification
-    let notification_message = format!("{} signed in", login_result.account.email);
-    if let Err(e) = db
-        .create_notification(account.id, "info", &notification_message)
-        .await
-    {
-        // Log error but don't fail the login
-        log::warn!("Failed to create sign-in notification: {:?}", e);
+    // A reported sign-in means the password may be known to someone else, so it
+    // isn't enough to sign in with until the owner resets it
+    if account.password_reset_required_at.is_some() {
+        if let Err(e) = auth_service
+            .logout(&login_result.tokens.access_token.to_string())
+            .await
+        {
+            log::warn!("Failed to revoke session of account awaiting reset: {:?}", e);
+        }
+        record_login_failure(&db, &pow, &meta, &email, "password_reset_required").await;
+        return password_reset_required();
     }
 
+    // Track the session and alert on sign-ins from unknown devices
+    devices::record_sign_in(
+        &db,
+        &mailer,
+        &notifier,
+        &geoip,
+        &account,
+        &login_result.account.email,
+        &meta,
+        &login_result.tokens.access_token.to_string(),
+        &login_result.tokens.refresh_token.to_string(),
+        login_result.tokens.access_token_expires_at,
+        &session_config.policy(req.remember_me),
+    )
+    .await;
+    sessions::enforce_session_limit(&db, &session_config, &token_cache, &account).await;
+
+    audit::record(
+        &db,
+        &meta,
+        AuditEntry::own(AuditEventType::LoginSucceeded, login_result.account.id),
+    )
+    .await;
+
     HttpResponse::Ok().json(AuthResponse {
         account: AccountInfo {
             id: account.id,
@@ -188,6 +705,8 @@ pub async fn login(
             avatar_url: account.avatar_url,
             username: account.username,
             auth_type: format!("{:?}", login_result.account.auth_type).to_lowercase(),
+            deletion_scheduled_for: account.deletion_scheduled_for,
+            password_reset_required: account.password_reset_required_at.is_some(),
         },
         access_token: login_result.tokens.access_token.to_string(),
         refresh_token: login_result.tokens.refresh_token.to_string(),
@@ -196,9 +715,17 @@ pub async fn login(
     })
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn google_login(
     auth_service: web::Data<Arc<AuthService>>,
     db: web::Data<DbContext>,
+    mailer: web::Data<Arc<dyn Mailer>>,
+    notifier: web::Data<Arc<Notifier>>,
+    geoip: web::Data<Arc<GeoIp>>,
+    registration: web::Data<RegistrationConfig>,
+    session_config: web::Data<SessionConfig>,
+    token_cache: web::Data<Arc<TokenCache>>,
+    meta: RequestMeta,
     req: web::Json<GoogleLoginRequest>,
 ) -> impl Responder {
     let login_result = match auth_service
@@ -239,6 +766,39 @@ pub async fn google_login(
         }
     };
 
+    // A first Google sign-in is a signup and goes through the same checks
+    let is_new = match db.get_account_by_iam_id(login_result.account.id).await {
+        Ok(existing) => existing.is_none(),
+        Err(e) => {
+            log::error!("Database error: {:?}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Database error"
+            }));
+        }
+    };
+    let mut invite_id = None;
+    if is_new {
+        let email = &login_result.account.email;
+        let admitted = match registration.check_email_domain(email) {
+            Ok(()) => admit_new_account(&db, &registration, email, req.invite_code.as_deref()).await,
+            Err(msg) => Err(HttpResponse::Forbidden().json(serde_json::json!({
+                "error": msg
+            }))),
+        };
+        match admitted {
+            Ok(id) => invite_id = id,
+            Err(response) => {
+                // Don't provision an app account; the IAM account stays, but its
+                // tokens are never handed out and the checks run again next time
+                let access_token = login_result.tokens.access_token.to_string();
+                if let Err(e) = auth_service.logout(&access_token).await {
+                    log::warn!("Failed to revoke rejected Google session: {:?}", e);
+                }
+                return response;
+            }
+        }
+    }
+
     // Get or create our Account record
     let account = match db.get_or_create_account_by_iam_id(
         login_result.account.id,
@@ -249,22 +809,46 @@ pub async fn google_login(
         Ok(acc) => acc,
         Err(e) => {
             log::error!("Database error: {:?}", e);
+            if let Some(invite_id) = invite_id {
+                if let Err(e) = db.release_invite_code(invite_id).await {
+                    log::warn!("Failed to release invite code: {:?}", e);
+                }
+            }
             return HttpResponse::InternalServerError().json(serde_json::json!({
                 "error": "Database error"
             }));
         }
     };
-
-    // Create sign-in notification
-    let notification_message = format!("{} signed in", login_result.account.email);
-    if let Err(e) = db
-        .create_notification(account.id, "info", &notification_message)
-        .await
-    {
-        // Log error but don't fail the login
-        log::warn!("Failed to create sign-in notification: {:?}", e);
+    if let Some(invite_id) = invite_id {
+        if let Err(e) = db.record_invite_redemption(invite_id, login_result.account.id).await {
+            log::warn!("Failed to record invite redemption: {:?}", e);
+        }
     }
 
+    // Track the session and alert on sign-ins from unknown devices
+    devices::record_sign_in(
+        &db,
+        &mailer,
+        &notifier,
+        &geoip,
+        &account,
+        &login_result.account.email,
+        &meta,
+        &login_result.tokens.access_token.to_string(),
+        &login_result.tokens.refresh_token.to_string(),
+        login_result.tokens.access_token_expires_at,
+        &session_config.policy(req.remember_me),
+    )
+    .await;
+    sessions::enforce_session_limit(&db, &session_config, &token_cache, &account).await;
+
+    audit::record(
+        &db,
+        &meta,
+        AuditEntry::own(AuditEventType::GoogleLogin, login_result.account.id),
+    )
+    .await;
+
     HttpResponse::Ok().json(AuthResponse {
         account: AccountInfo {
             id: account.id,
@@ -274,6 +858,8 @@ pub async fn google_login(
             avatar_url: account.avatar_url,
             username: account.username,
             auth_type: format!("{:?}", login_result.account.auth_type).to_lowercase(),
+            deletion_scheduled_for: account.deletion_scheduled_for,
+            password_reset_required: account.password_reset_required_at.is_some(),
         },
         access_token: login_result.tokens.access_token.to_string(),
         refresh_token: login_result.tokens.refresh_token.to_string(),
@@ -285,8 +871,44 @@ pub async fn google_login(
 pub async fn refresh_token(
     auth_service: web::Data<Arc<AuthService>>,
     db: web::Data<DbContext>,
+    session_config: web::Data<SessionConfig>,
+    token_cache: web::Data<Arc<TokenCache>>,
+    meta: RequestMeta,
     req: web::Json<RefreshTokenRequest>,
 ) -> impl Responder {
+    let session = match db
+        .get_session_by_refresh_token_hash(&sessions::hash_token(&req.refresh_token))
+        .await
+    {
+        Ok(session) => session,
+        Err(e) => {
+            log::error!("Failed to get session: {:?}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to refresh token"
+            }));
+        }
+    };
+    if session.as_ref().is_some_and(|s| s.revoked_at.is_some()) {
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Invalid or expired refresh token"
+        }));
+    }
+    if let Some(session) = session.as_ref().filter(|s| session_config.is_expired(s)) {
+        if let Err(e) = db.revoke_session(session.id).await {
+            log::error!("Failed to revoke expired session: {:?}", e);
+        }
+        // The session's access token may still be cached
+        match db.get_account_by_id(session.account_id).await {
+            Ok(Some(account)) => token_cache.invalidate_account(account.iam_account_id).await,
+            Ok(None) => {}
+            Err(e) => log::error!("Failed to get account of expired session: {:?}", e),
+        }
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Session expired, please sign in again",
+            "session_expired": true
+        }));
+    }
+
     let refresh_result = match auth_service.refresh(&req.refresh_token).await {
         Ok(result) => result,
         Err(IamError::TokenExpired) | Err(IamError::TokenNotFound) | Err(IamError::TokenRevoked) => {
@@ -295,6 +917,12 @@ pub async fn refresh_token(
             }));
         }
         Err(IamError::TokenReuseDetected) => {
+            audit::record(
+                &db,
+                &meta,
+                AuditEntry::anonymous(AuditEventType::RefreshTokenReuse, None),
+            )
+            .await;
             return HttpResponse::Unauthorized().json(serde_json::json!({
                 "error": "Refresh token has been compromised"
             }));
@@ -307,6 +935,20 @@ pub async fn refresh_token(
         }
     };
 
+    if let Some(session) = session {
+        if let Err(e) = db
+            .rotate_session_tokens(
+                session.id,
+                &sessions::hash_token(&refresh_result.tokens.access_token.to_string()),
+                &sessions::hash_token(&refresh_result.tokens.refresh_token.to_string()),
+                refresh_result.tokens.access_token_expires_at,
+            )
+            .await
+        {
+            log::error!("Failed to rotate session tokens: {:?}", e);
+        }
+    }
+
     // Get our Account record
     let account = match db.get_account_by_iam_id(refresh_result.account.id).await {
         Ok(Some(acc)) => acc,
@@ -332,6 +974,8 @@ pub async fn refresh_token(
             avatar_url: account.avatar_url,
             username: account.username,
             auth_type: format!("{:?}", refresh_result.account.auth_type).to_lowercase(),
+            deletion_scheduled_for: account.deletion_scheduled_for,
+            password_reset_required: account.password_reset_required_at.is_some(),
         },
         access_token: refresh_result.tokens.access_token.to_string(),
         refresh_token: refresh_result.tokens.refresh_token.to_string(),
@@ -342,6 +986,7 @@ pub async fn refresh_token(
 
 pub async fn logout(
     auth_service: web::Data<Arc<AuthService>>,
+    token_cache: web::Data<Arc<TokenCache>>,
     _user: AuthenticatedUser,
     req: web::Json<serde_json::Value>,
 ) -> impl Responder {
@@ -362,9 +1007,12 @@ pub async fn logout(
     };
 
     match auth_service.logout(token).await {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-            "message": "Logged out successfully"
-        })),
+        Ok(_) => {
+            token_cache.invalidate_token(token).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "Logged out successfully"
+            }))
+        }
         Err(e) => {
             log::error!("Logout error: {:?}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
@@ -374,488 +1022,1692 @@ pub async fn logout(
     }
 }
 
-pub async fn get_me(
-    db: web::Data<DbContext>,
+/// Handle the "this wasn't me" link of a new-device alert: sign every session
+/// out and, for email accounts, require a password reset through an emailed code
+pub async fn report_unrecognized_login(
     auth_service: web::Data<Arc<AuthService>>,
-    user: AuthenticatedUser,
+    db: web::Data<DbContext>,
+    token_cache: web::Data<Arc<TokenCache>>,
+    meta: RequestMeta,
+    req: web::Json<ReportLoginRequest>,
 ) -> impl Responder {
-    // Get IAM account info
-    let iam_account = match auth_service.get_account(user.account_id).await {
-        Ok(acc) => acc,
+    let session = match db
+        .revoke_session_by_alert_token(
+            &sessions::hash_token(&req.token),
+            Utc::now() - devices::ALERT_LINK_TTL,
+        )
+        .await
+    {
+        Ok(Some(session)) => session,
+        Ok(None) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid or expired link"
+            }));
+        }
         Err(e) => {
-            log::error!("Failed to get IAM account: {:?}", e);
+            log::error!("Failed to revoke reported session: {:?}", e);
             return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get account"
+                "error": "Failed to secure account"
             }));
         }
     };
 
-    // Get our Account record
-    let account = match db.get_account_by_iam_id(user.account_id).await {
-        Ok(Some(acc)) => acc,
+    if let Some(device_id) = session.device_id {
+        if let Err(e) = db.delete_known_device(device_id).await {
+            log::error!("Failed to forget reported device: {:?}", e);
+        }
+    }
+
+    let account = match db.get_account_by_id(session.account_id).await {
+        Ok(Some(account)) => account,
         Ok(None) => {
-            return HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Account not found"
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid or expired link"
             }));
         }
         Err(e) => {
-            log::error!("Database error: {:?}", e);
+            log::error!("Failed to get reported account: {:?}", e);
             return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Database error"
+                "error": "Failed to secure account"
             }));
         }
     };
 
-    HttpResponse::Ok().json(AccountInfo {
-        id: account.id,
-        iam_account_id: account.iam_account_id,
-        email: iam_account.email,
-        display_name: account.display_name,
-        avatar_url: account.avatar_url,
-        username: account.username,
-        auth_type: format!("{:?}", iam_account.auth_type).to_lowercase(),
-    })
-}
+    // Whoever signed in may hold other sessions, so sign every one of them out
+    if let Err(e) = db.revoke_all_sessions(account.id).await {
+        log::error!("Failed to secure reported account: {:?}", e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to secure account"
+        }));
+    }
+    token_cache.invalidate_account(account.iam_account_id).await;
 
-pub async fn change_password(
-    auth_service: web::Data<Arc<AuthService>>,
-    user: AuthenticatedUser,
-    req: web::Json<ChangePasswordRequest>,
-) -> impl Responder {
-    match auth_service
-        .change_password(user.account_id, &req.old_password, &req.new_password)
-        .await
-    {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-            "message": "Password changed successfully"
-        })),
-        Err(IamError::InvalidCredentials) => {
-            HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Invalid current password"
-            }))
+    // Whoever signed in may also know the password, so for email accounts the
+    // old password stops working here: a new one can only be set with the
+    // reset code emailed to the owner
+    let iam_account = match auth_service.get_account(account.iam_account_id).await {
+        Ok(iam_account) => Some(iam_account),
+        Err(e) => {
+            log::error!("Failed to get IAM account for reported login: {:?}", e);
+            None
         }
-        Err(IamError::WeakPassword(msg)) => {
-            HttpResponse::BadRequest().json(serde_json::json!({
-                "error": msg
-            }))
+    };
+    let reset_email = iam_account
+        .filter(|iam_account| matches!(iam_account.auth_type, AuthType::Email))
+        .map(|iam_account| iam_account.email);
+    if let Some(email) = &reset_email {
+        if let Err(e) = db.set_password_reset_required(account.id, true).await {
+            log::error!("Failed to require password reset: {:?}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to secure account"
+            }));
         }
-        Err(e) => {
-            log::error!("Change password error: {:?}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to change password"
-            }))
+        if let Err(e) = auth_service.request_password_reset(email).await {
+            log::error!("Failed to send password reset code: {:?}", e);
         }
     }
-}
 
-pub async fn delete_account(
+    audit::record(
+        &db,
+        &meta,
+        AuditEntry::own(AuditEventType::UnrecognizedLoginReported, account.iam_account_id)
+            .with_metadata(serde_json::json!({
+                "session_id": session.id,
+                "ip_address": session.ip_address,
+                "user_agent": session.user_agent,
+                "password_reset_required": reset_email.is_some(),
+            })),
+    )
+    .await;
+
+    let message = if reset_email.is_some() {
+        "All sessions have been signed out. We've emailed you a code to choose a new password; your old password no longer works."
+    } else {
+        "All sessions have been signed out. Review the security of your Google account before signing in again."
+    };
+    HttpResponse::Ok().json(serde_json::json!({
+        "message": message,
+        "password_reset_required": reset_email.is_some()
+    }))
+}
+
+/// Email a password reset code. Answers the same whether or not the address
+/// belongs to an email account.
+pub async fn request_password_reset(
     auth_service: web::Data<Arc<AuthService>>,
     db: web::Data<DbContext>,
-    user: AuthenticatedUser,
-    req: web::Json<DeleteAccountRequest>,
+    pow: web::Data<Arc<PowService>>,
+    privacy: web::Data<PrivacyConfig>,
+    meta: RequestMeta,
+    http_req: HttpRequest,
+    req: web::Json<RequestPasswordResetRequest>,
+) -> impl Responder {
+    let started = Instant::now();
+
+    if let Err(e) = pow
+        .verify(&db, http_req.headers(), PowAction::PasswordReset, meta.ip_address.as_deref())
+        .await
+    {
+        return e.error_response();
+    }
+
+    match auth_service.request_password_reset(&req.email).await {
+        Ok(_) | Err(IamError::AccountNotFound) | Err(IamError::AuthTypeMismatch) => {}
+        Err(e) => {
+            log::error!("Password reset request error: {:?}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to send password reset code"
+            }));
+        }
+    }
+
+    privacy.pad(started).await;
+    HttpResponse::Ok().json(serde_json::json!({
+        "message": PASSWORD_RESET_MESSAGE
+    }))
+}
+
+/// Choose a new password with the code from a password reset email. This is
+/// the only way out of a required password reset, since the old password may
+/// be known to someone else.
+#[allow(clippy::too_many_arguments)]
+pub async fn reset_password(
+    auth_service: web::Data<Arc<AuthService>>,
+    db: web::Data<DbContext>,
+    password_config: web::Data<PasswordCheckConfig>,
+    pow: web::Data<Arc<PowService>>,
+    token_cache: web::Data<Arc<TokenCache>>,
+    meta: RequestMeta,
+    http_req: HttpRequest,
+    req: web::Json<ResetPasswordRequest>,
 ) -> impl Responder {
-    // Delete IAM account (soft delete)
+    if let Err(e) = pow
+        .verify(&db, http_req.headers(), PowAction::PasswordReset, meta.ip_address.as_deref())
+        .await
+    {
+        return e.error_response();
+    }
+
+    if let Err(rejection) =
+        password_check::check_password(&password_config, &req.new_password, &[&req.email]).await
+    {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": rejection.message,
+            "details": rejection.details
+        }));
+    }
+
     match auth_service
-        .delete_account(user.account_id, &req.password)
+        .reset_password(&req.email, &req.code, &req.new_password)
         .await
     {
         Ok(_) => {}
-        Err(IamError::InvalidCredentials) => {
+        Err(IamError::InvalidVerificationCode)
+        | Err(IamError::VerificationCodeExpired)
+        | Err(IamError::AccountNotFound) => {
+            pow.record_rejection(meta.ip_address.as_deref());
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid or expired reset code"
+            }));
+        }
+        Err(IamError::WeakPassword(msg)) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": msg
+            }));
+        }
+        Err(e) => {
+            log::error!("Password reset error: {:?}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to reset password"
+            }));
+        }
+    }
+
+    let iam_account_id = match db.find_iam_account_id_by_email(&req.email).await {
+        Ok(id) => id,
+        Err(e) => {
+            log::error!("Failed to find account after password reset: {:?}", e);
+            None
+        }
+    };
+    if let Some(iam_account_id) = iam_account_id {
+        match db.get_account_by_iam_id(iam_account_id).await {
+            Ok(Some(account)) => {
+                if let Err(e) = db.revoke_all_sessions(account.id).await {
+                    log::error!("Failed to revoke sessions after password reset: {:?}", e);
+                }
+                if account.password_reset_required_at.is_some() {
+                    if let Err(e) = db.set_password_reset_required(account.id, false).await {
+                        log::error!("Failed to clear password reset requirement: {:?}", e);
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(e) => log::error!("Failed to get account after password reset: {:?}", e),
+        }
+        token_cache.invalidate_account(iam_account_id).await;
+        audit::record(
+            &db,
+            &meta,
+            AuditEntry::own(AuditEventType::PasswordChanged, iam_account_id)
+                .with_metadata(serde_json::json!({ "method": "reset_code" })),
+        )
+        .await;
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "message": "Your password has been reset. You can now sign in."
+    }))
+}
+
+/// Step-up re-authentication: confirm the user's identity again and mark the
+/// current session as elevated for a few minutes
+pub async fn reauthenticate(
+    auth_service: web::Data<Arc<AuthService>>,
+    db: web::Data<DbContext>,
+    reauth_config: web::Data<ReauthConfig>,
+    pow: web::Data<Arc<PowService>>,
+    meta: RequestMeta,
+    http_req: HttpRequest,
+    credentials: BearerAuth,
+    user: AuthenticatedUser,
+    req: web::Json<ReauthRequest>,
+) -> impl Responder {
+    // A stolen access token mustn't be enough to guess the password at leisure
+    if let Err(e) = pow
+        .verify(&db, http_req.headers(), PowAction::Reauth, meta.ip_address.as_deref())
+        .await
+    {
+        return e.error_response();
+    }
+
+    let session = match db
+        .get_session_by_access_token_hash(&sessions::hash_token(credentials.token()))
+        .await
+    {
+        Ok(Some(session)) => session,
+        Ok(None) => {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "This session can't be re-authenticated. Please sign in again."
+            }));
+        }
+        Err(e) => {
+            log::error!("Failed to get session: {:?}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to re-authenticate"
+            }));
+        }
+    };
+
+    let (method, result) = match (&req.password, &req.totp_code, &req.id_token) {
+        (Some(password), None, None) => (
+            "password",
+            reauth::confirm_password(&db, user.account_id, password).await,
+        ),
+        (None, None, Some(id_token)) => (
+            "google",
+            reauth::confirm_google(&auth_service, &reauth_config, user.account_id, id_token).await,
+        ),
+        (None, Some(_), None) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Two-factor authentication is not enabled for this account"
+            }));
+        }
+        _ => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Provide exactly one of password, totp_code or id_token"
+            }));
+        }
+    };
+
+    match result {
+        Ok(_) => {}
+        Err(ReauthError::Rejected) => {
+            pow.record_rejection(meta.ip_address.as_deref());
             return HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Invalid password"
+                "error": "Could not confirm your identity"
+            }));
+        }
+        Err(ReauthError::Failed(e)) => {
+            log::error!("Re-authentication error: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to re-authenticate"
+            }));
+        }
+    }
+
+    let elevated_until = Utc::now() + reauth_config.window;
+    if let Err(e) = db.elevate_session(session.id, elevated_until).await {
+        log::error!("Failed to elevate session: {:?}", e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to re-authenticate"
+        }));
+    }
+    audit::record(
+        &db,
+        &meta,
+        AuditEntry::own(AuditEventType::Reauthenticated, user.account_id)
+            .with_metadata(serde_json::json!({ "method": method })),
+    )
+    .await;
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "elevated_until": elevated_until
+    }))
+}
+
+pub async fn get_me(
+    auth_service: web::Data<Arc<AuthService>>,
+    user: AuthenticatedUser,
+    CurrentAccount(account): CurrentAccount,
+) -> impl Responder {
+    // Get IAM account info
+    let iam_account = match auth_service.get_account(user.account_id).await {
+        Ok(acc) => acc,
+        Err(e) => {
+            log::error!("Failed to get IAM account: {:?}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to get account"
+            }));
+        }
+    };
+
+    HttpResponse::Ok().json(AccountInfo {
+        id: account.id,
+        iam_account_id: account.iam_account_id,
+        email: iam_account.email,
+        display_name: account.display_name,
+        avatar_url: account.avatar_url,
+        username: account.username,
+        auth_type: format!("{:?}", iam_account.auth_type).to_lowercase(),
+        deletion_scheduled_for: account.deletion_scheduled_for,
+        password_reset_required: account.password_reset_required_at.is_some(),
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn change_password(
+    auth_service: web::Data<Arc<AuthService>>,
+    db: web::Data<DbContext>,
+    password_config: web::Data<PasswordCheckConfig>,
+    token_cache: web::Data<Arc<TokenCache>>,
+    meta: RequestMeta,
+    user: AuthenticatedUser,
+    CurrentAccount(account): CurrentAccount,
+    req: web::Json<ChangePasswordRequest>,
+) -> impl Responder {
+    // The old password may be known to whoever triggered the reset
+    if account.password_reset_required_at.is_some() {
+        return password_reset_required();
+    }
+
+    if let Err(rejection) =
+        password_check::check_password(&password_config, &req.new_password, &[&user.email]).await
+    {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": rejection.message,
+            "details": rejection.details
+        }));
+    }
+
+    match auth_service
+        .change_password(user.account_id, &req.old_password, &req.new_password)
+        .await
+    {
+        Ok(_) => {
+            token_cache.invalidate_account(user.account_id).await;
+            audit::record(
+                &db,
+                &meta,
+                AuditEntry::own(AuditEventType::PasswordChanged, user.account_id),
+            )
+            .await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "Password changed successfully"
+            }))
+        }
+        Err(IamError::InvalidCredentials) => {
+            HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Invalid current password"
+            }))
+        }
+        Err(IamError::WeakPassword(msg)) => {
+            HttpResponse::BadRequest().json(serde_json::json!({
+                "error": msg
+            }))
+        }
+        Err(e) => {
+            log::error!("Change password error: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to change password"
+            }))
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn delete_account(
+    auth_service: web::Data<Arc<AuthService>>,
+    db: web::Data<DbContext>,
+    mailer: web::Data<Arc<dyn Mailer>>,
+    deletion_config: web::Data<DeletionConfig>,
+    pow: web::Data<Arc<PowService>>,
+    token_cache: web::Data<Arc<TokenCache>>,
+    meta: RequestMeta,
+    http_req: HttpRequest,
+    credentials: BearerAuth,
+    user: AuthenticatedUser,
+    recent_auth: Option<RequireRecentAuth>,
+    req: web::Json<DeleteAccountRequest>,
+) -> impl Responder {
+    // Confirm the password without touching the IAM account, which must stay
+    // usable for signing in and restoring during the grace period. Sessions that
+    // re-authenticated recently (e.g. Google accounts) can skip it.
+    match (&req.password, recent_auth) {
+        (Some(password), _) => {
+            if let Err(e) = pow
+                .verify(&db, http_req.headers(), PowAction::Reauth, meta.ip_address.as_deref())
+                .await
+            {
+                return e.error_response();
+            }
+            match reauth::confirm_password(&db, user.account_id, password).await {
+                Ok(_) => {}
+                Err(ReauthError::Rejected) => {
+                    pow.record_rejection(meta.ip_address.as_deref());
+                    return HttpResponse::Unauthorized().json(serde_json::json!({
+                        "error": "Invalid password"
+                    }));
+                }
+                Err(ReauthError::Failed(e)) => {
+                    log::error!("Delete account error: {}", e);
+                    return HttpResponse::InternalServerError().json(serde_json::json!({
+                        "error": "Failed to delete account"
+                    }));
+                }
+            }
+        }
+        (None, Some(_)) => {}
+        (None, None) => {
+            return HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "Please confirm your identity to continue",
+                "reauth_required": true
+            }));
+        }
+    }
+
+    let scheduled_for = Utc::now() + deletion_config.grace_period;
+    let account = match db
+        .schedule_account_deletion(user.account_id, scheduled_for)
+        .await
+    {
+        Ok(acc) => acc,
+        Err(sqlx::Error::RowNotFound) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Account not found"
             }));
         }
         Err(e) => {
-            log::error!("Delete account error: {:?}", e);
+            log::error!("Failed to schedule account deletion: {:?}", e);
             return HttpResponse::InternalServerError().json(serde_json::json!({
                 "error": "Failed to delete account"
             }));
         }
     };
 
-    // Delete our Account record
-    match db.delete_account_by_iam_id(user.account_id).await {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-            "message": "Account deleted successfully"
+    // No session keeps working on an account that is about to be deleted
+    if let Err(e) = db.revoke_all_sessions(account.id).await {
+        log::error!("Failed to revoke sessions after deletion request: {:?}", e);
+    }
+    token_cache.invalidate_account(user.account_id).await;
+
+    audit::record(
+        &db,
+        &meta,
+        AuditEntry::own(AuditEventType::AccountDeletionRequested, user.account_id).with_metadata(
+            serde_json::json!({
+                "deletion_scheduled_for": scheduled_for,
+            }),
+        ),
+    )
+    .await;
+
+    deletion::send_deletion_email(mailer.get_ref(), &user.email, scheduled_for).await;
+
+    // Also sign out the current token in IAM; signing in again during the grace
+    // period allows the account to be restored
+    if let Err(e) = auth_service.logout(credentials.token()).await {
+        log::warn!("Failed to log out after deletion request: {:?}", e);
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "message": "Account scheduled for deletion",
+        "deletion_scheduled_for": account.deletion_scheduled_for
+    }))
+}
+
+pub async fn restore_account(
+    db: web::Data<DbContext>,
+    token_cache: web::Data<Arc<TokenCache>>,
+    meta: RequestMeta,
+    user: AuthenticatedUser,
+    CurrentAccount(account): CurrentAccount,
+) -> impl Responder {
+    if account.deletion_scheduled_for.is_none() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Account is not scheduled for deletion"
+        }));
+    }
+
+    match db.cancel_account_deletion(user.account_id).await {
+        Ok(_) => {
+            token_cache.invalidate_account(user.account_id).await;
+            audit::record(
+                &db,
+                &meta,
+                AuditEntry::own(AuditEventType::AccountRestored, user.account_id),
+            )
+            .await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "Account restored successfully"
+            }))
+        }
+        Err(e) => {
+            log::error!("Failed to restore account: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to restore account"
+            }))
+        }
+    }
+}
+
+// Notification handlers
+
+pub async fn create_notification(
+    notifier: web::Data<Arc<Notifier>>,
+    CurrentAccount(account): CurrentAccount,
+    req: web::Json<CreateNotificationRequest>,
+) -> impl Responder {
+    let req = req.into_inner();
+    // Kinds describing server-side events can only be produced by the server
+    if notifications::kind(&req.kind).is_some_and(|kind| !kind.public) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Kind '{}' can't be created through the API", req.kind)
+        }));
+    }
+    let dedupe_window = match req.dedupe_window.map(chrono::Duration::try_seconds) {
+        Some(None) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid dedupe_window"
+            }));
+        }
+        window => window.flatten(),
+    };
+    let notification = NewNotification {
+        kind: req.kind,
+        level: req.level,
+        title: req.title,
+        message: req.message,
+        data: req.data.unwrap_or_else(|| serde_json::json!({})),
+        action_url: req.action_url,
+        actions: req.actions,
+        source: Some(req.source.unwrap_or_else(|| "api".to_string())),
+        expires_at: req.expires_at,
+        group_key: req.group_key,
+        dedupe_window,
+    };
+
+    // Use authenticated user's account_id (ignore account_id from request for security)
+    match notifier.notify(&account, &notification).await {
+        Ok(Some(notification)) => HttpResponse::Created().json(notification),
+        // The account's preferences route this kind elsewhere
+        Ok(None) => HttpResponse::Accepted().json(serde_json::json!({
+            "message": "Notification delivered outside the notifications list"
         })),
+        Err(CreateError::Invalid(message)) => {
+            HttpResponse::BadRequest().json(serde_json::json!({ "error": message }))
+        }
+        Err(CreateError::Database(e)) => {
+            log::error!("Failed to create notification: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to create notification"
+            }))
+        }
+    }
+}
+
+pub async fn get_notifications(
+    db: web::Data<DbContext>,
+    CurrentAccount(account): CurrentAccount,
+    query: web::Query<NotificationsQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    if query.level.as_deref().is_some_and(|level| !notifications::is_valid_level(level)) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid level. Must be 'info', 'warning', or 'error'"
+        }));
+    }
+    let cursor = match query.cursor.as_deref().map(NotificationCursor::decode) {
+        None => None,
+        Some(Some(cursor)) => Some(cursor),
+        Some(None) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid cursor"
+            }));
+        }
+    };
+    let limit = query
+        .limit
+        .unwrap_or(NOTIFICATIONS_DEFAULT_LIMIT)
+        .clamp(1, NOTIFICATIONS_MAX_LIMIT);
+
+    let grouped = query.grouped.unwrap_or(false);
+    if grouped && query.group_key.is_some() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "grouped and group_key can't be combined"
+        }));
+    }
+
+    // Fetch one extra row to learn whether another page follows
+    let page = if grouped {
+        db.get_notification_groups_page(account.id, &query, cursor, limit + 1).await
+    } else {
+        db.get_notifications_page(account.id, &query, cursor, limit + 1).await
+    };
+    let counts = db.count_notifications(account.id, &query).await;
+    match (page, counts) {
+        (Ok(mut notifications), Ok((total, unread))) => {
+            let next_cursor = if notifications.len() as i64 > limit {
+                notifications.truncate(limit as usize);
+                notifications.last().map(|n| NotificationCursor::after(n).encode())
+            } else {
+                None
+            };
+            HttpResponse::Ok().json(NotificationPage {
+                notifications,
+                next_cursor,
+                total,
+                unread,
+            })
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            log::error!("Failed to get notifications: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to get notifications"
+            }))
+        }
+    }
+}
+
+/// Push notification changes to the client as Server-Sent Events
+pub async fn stream_notifications(
+    hub: web::Data<Arc<NotificationHub>>,
+    CurrentAccount(account): CurrentAccount,
+    http_req: HttpRequest,
+) -> impl Responder {
+    let last_event_id = http_req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<i64>().ok());
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // Keep nginx from buffering the stream
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(hub.get_ref().clone().stream(account.id, last_event_id))
+}
+
+pub async fn get_unread_count(
+    db: web::Data<DbContext>,
+    CurrentAccount(account): CurrentAccount,
+) -> impl Responder {
+    match db.get_unread_count(account.id).await {
+        Ok(count) => HttpResponse::Ok().json(serde_json::json!({
+            "count": count
+        })),
+        Err(e) => {
+            log::error!("Failed to get unread count: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to get unread count"
+            }))
+        }
+    }
+}
+
+pub async fn update_notification(
+    db: web::Data<DbContext>,
+    CurrentAccount(account): CurrentAccount,
+    notification_id: web::Path<uuid::Uuid>,
+    req: web::Json<UpdateNotificationRequest>,
+) -> impl Responder {
+    if req.read.is_none() && req.archived.is_none() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Set read, archived, or both"
+        }));
+    }
+
+    match db
+        .update_notification(*notification_id, account.id, req.read, req.archived)
+        .await
+    {
+        Ok(notification) => HttpResponse::Ok().json(notification),
+        Err(sqlx::Error::RowNotFound) => {
+            HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Notification not found"
+            }))
+        }
+        Err(e) => {
+            log::error!("Failed to update notification: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to update notification"
+            }))
+        }
+    }
+}
+
+pub async fn update_notifications_batch(
+    db: web::Data<DbContext>,
+    CurrentAccount(account): CurrentAccount,
+    req: web::Json<BatchUpdateNotificationsRequest>,
+) -> impl Responder {
+    if req.notification_ids.len() > NOTIFICATIONS_BATCH_MAX_IDS {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("At most {} notification_ids are allowed", NOTIFICATIONS_BATCH_MAX_IDS)
+        }));
+    }
+
+    match db
+        .update_notifications_read_batch(&req.notification_ids, account.id, req.read)
+        .await
+    {
+        Ok(notifications) => HttpResponse::Ok().json(notifications),
+        Err(e) => {
+            log::error!("Failed to update notifications: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to update notifications"
+            }))
+        }
+    }
+}
+
+/// Check a bulk operation's filter, so it only runs on what the client meant
+fn validate_notification_filter(filter: &NotificationFilter) -> Result<(), String> {
+    let narrowed = filter.kind.is_some()
+        || filter.level.is_some()
+        || filter.read.is_some()
+        || filter.before.is_some()
+        || filter.archived.is_some();
+    if !filter.all && !narrowed {
+        return Err("Set all to true or give at least one filter".to_string());
+    }
+    if filter.level.as_deref().is_some_and(|level| !notifications::is_valid_level(level)) {
+        return Err("Invalid level. Must be 'info', 'warning', or 'error'".to_string());
+    }
+    if let Some(kind) = &filter.kind {
+        if notifications::kind(kind).is_none() {
+            return Err(format!("Unknown kind '{}'", kind));
+        }
+    }
+    Ok(())
+}
+
+/// Mark every notification matching a filter read or unread, e.g. "mark all as read"
+pub async fn bulk_update_notifications(
+    db: web::Data<DbContext>,
+    CurrentAccount(account): CurrentAccount,
+    req: web::Json<BulkUpdateNotificationsRequest>,
+) -> impl Responder {
+    if let Err(message) = validate_notification_filter(&req.filter) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": message }));
+    }
+
+    match db
+        .update_notifications_read_matching(account.id, &req.filter, req.read, NOTIFICATIONS_BULK_BATCH_SIZE)
+        .await
+    {
+        Ok(count) => HttpResponse::Ok().json(serde_json::json!({
+            "updated_count": count
+        })),
+        Err(e) => {
+            log::error!("Failed to update notifications: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to update notifications"
+            }))
+        }
+    }
+}
+
+pub async fn delete_notification(
+    db: web::Data<DbContext>,
+    CurrentAccount(account): CurrentAccount,
+    notification_id: web::Path<uuid::Uuid>,
+) -> impl Responder {
+    match db.delete_notification(*notification_id, account.id).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Notification deleted successfully"
+        })),
+        Err(e) => {
+            log::error!("Failed to delete notification: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to delete notification"
+            }))
+        }
+    }
+}
+
+pub async fn delete_notifications_batch(
+    db: web::Data<DbContext>,
+    CurrentAccount(account): CurrentAccount,
+    req: web::Json<BatchDeleteNotificationsRequest>,
+) -> impl Responder {
+    if req.notification_ids.len() > NOTIFICATIONS_BATCH_MAX_IDS {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("At most {} notification_ids are allowed", NOTIFICATIONS_BATCH_MAX_IDS)
+        }));
+    }
+
+    match db
+        .delete_notifications_batch(&req.notification_ids, account.id)
+        .await
+    {
+        Ok(count) => HttpResponse::Ok().json(serde_json::json!({
+            "message": format!("{} notification(s) deleted successfully", count),
+            "deleted_count": count
+        })),
+        Err(e) => {
+            log::error!("Failed to delete notifications: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to delete notifications"
+            }))
+        }
+    }
+}
+
+/// Delete every notification matching a filter, e.g. "delete all read"
+pub async fn bulk_delete_notifications(
+    db: web::Data<DbContext>,
+    CurrentAccount(account): CurrentAccount,
+    req: web::Json<BulkDeleteNotificationsRequest>,
+) -> impl Responder {
+    if let Err(message) = validate_notification_filter(&req.filter) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": message }));
+    }
+
+    match db
+        .delete_notifications_matching(account.id, &req.filter, NOTIFICATIONS_BULK_BATCH_SIZE)
+        .await
+    {
+        Ok(count) => HttpResponse::Ok().json(serde_json::json!({
+            "message": format!("{} notification(s) deleted successfully", count),
+            "deleted_count": count
+        })),
+        Err(e) => {
+            log::error!("Failed to delete notifications: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to delete notifications"
+            }))
+        }
+    }
+}
+
+// Account settings handlers
+
+pub async fn get_account_settings(
+    CurrentAccount(account): CurrentAccount,
+) -> impl Responder {
+    HttpResponse::Ok().json(AccountSettings {
+        username: account.username,
+    })
+}
+
+pub async fn update_account_settings(
+    db: web::Data<DbContext>,
+    token_cache: web::Data<Arc<TokenCache>>,
+    meta: RequestMeta,
+    user: AuthenticatedUser,
+    CurrentAccount(account): CurrentAccount,
+    _recent_auth: RequireRecentAuth,
+    req: web::Json<UpdateAccountSettingsRequest>,
+) -> impl Responder {
+    // Validate username if provided
+    let username = match req.username.as_deref().map(usernames::validate).transpose() {
+        Ok(username) => username,
+        Err(message) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": message
+            }));
+        }
+    };
+
+    match db.update_account_settings(account.id, username).await {
+        Ok(updated_account) => {
+            token_cache.invalidate_account(user.account_id).await;
+            audit::record(
+                &db,
+                &meta,
+                AuditEntry::own(AuditEventType::SettingsChanged, user.account_id).with_metadata(
+                    serde_json::json!({
+                        "old_username_hash": account.username.as_deref().map(audit::identifier_hash),
+                        "new_username_hash": updated_account.username.as_deref().map(audit::identifier_hash),
+                    }),
+                ),
+            )
+            .await;
+            HttpResponse::Ok().json(AccountSettings {
+                username: updated_account.username,
+            })
+        }
+        Err(sqlx::Error::Database(db_err)) if db_err.constraint() == Some("idx_app_accounts_username") => {
+            HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Username is already taken"
+            }))
+        }
+        Err(e) => {
+            log::error!("Failed to update account settings: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to update account settings"
+            }))
+        }
+    }
+}
+
+pub async fn get_notification_preferences(
+    db: web::Data<DbContext>,
+    CurrentAccount(account): CurrentAccount,
+) -> impl Responder {
+    notification_preferences_response(&db, account.id).await
+}
+
+pub async fn update_notification_preferences(
+    db: web::Data<DbContext>,
+    meta: RequestMeta,
+    user: AuthenticatedUser,
+    CurrentAccount(account): CurrentAccount,
+    _recent_auth: RequireRecentAuth,
+    req: web::Json<UpdateNotificationPreferencesRequest>,
+) -> impl Responder {
+    let req = req.into_inner();
+    let webhook_url = req
+        .webhook_url
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty());
+    let validation = webhook_url
+        .as_deref()
+        .map(notifier::validate_webhook_url)
+        .transpose()
+        .and_then(|_| notifier::validate_rules(&req.rules, webhook_url.is_some()))
+        .and_then(|_| req.digest.as_ref().map(digest::validate_settings).transpose());
+    if let Err(message) = validation {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": message }));
+    }
+
+    let current = match db.get_notification_settings(account.id).await {
+        Ok(settings) => settings,
+        Err(e) => {
+            log::error!("Failed to get notification settings: {:?}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to update notification preferences"
+            }));
+        }
+    };
+    // Keep the signing secret unless the webhook moves somewhere else
+    let webhook_secret = match (&webhook_url, current) {
+        (None, _) => None,
+        (Some(url), Some(current)) if current.webhook_url.as_ref() == Some(url) => current.webhook_secret,
+        (Some(_), _) => Some(notifier::generate_webhook_secret()),
+    };
+
+    if let Err(e) = db
+        .replace_notification_preferences(
+            account.id,
+            &req.rules,
+            webhook_url.as_deref(),
+            webhook_secret.as_deref(),
+        )
+        .await
+    {
+        log::error!("Failed to update notification preferences: {:?}", e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to update notification preferences"
+        }));
+    }
+    if let Some(settings) = &req.digest {
+        let next_digest_at = digest::next_digest_at(
+            &settings.frequency,
+            &settings.time_zone,
+            settings.hour,
+            settings.weekday,
+            Utc::now(),
+        );
+        if let Err(e) = db.update_digest_settings(account.id, settings, next_digest_at).await {
+            log::error!("Failed to update digest settings: {:?}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to update notification preferences"
+            }));
+        }
+    }
+
+    audit::record(
+        &db,
+        &meta,
+        AuditEntry::own(AuditEventType::SettingsChanged, user.account_id).with_metadata(
+            serde_json::json!({
+                "notification_rules": req.rules.len(),
+                // Never the URL itself: the audit log can't be cleaned up if it holds a secret
+                "webhook_configured": webhook_url.is_some(),
+                "webhook_host": webhook_url.as_deref().and_then(notifier::webhook_host),
+                "webhook_url_hash": webhook_url.as_deref().map(audit::identifier_hash),
+                "digest_frequency": req.digest.as_ref().map(|d| &d.frequency),
+            }),
+        ),
+    )
+    .await;
+    notification_preferences_response(&db, account.id).await
+}
+
+/// An account's routing rules and webhook, along with every kind's defaults
+async fn notification_preferences_response(db: &DbContext, account_id: uuid::Uuid) -> HttpResponse {
+    let rules = db.get_notification_preferences(account_id).await;
+    let settings = db.get_notification_settings(account_id).await;
+    let (rules, settings) = match (rules, settings) {
+        (Ok(rules), Ok(settings)) => (rules, settings),
+        (Err(e), _) | (_, Err(e)) => {
+            log::error!("Failed to get notification preferences: {:?}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to get notification preferences"
+            }));
+        }
+    };
+
+    let channel_names = |channels: &[Channel]| -> Vec<&str> {
+        channels.iter().map(|channel| channel.as_str()).collect()
+    };
+    let kinds: Vec<_> = notifications::KINDS
+        .iter()
+        .map(|kind| {
+            serde_json::json!({
+                "name": kind.name,
+                "default_level": kind.default_level,
+                "default_channels": channel_names(kind.default_channels),
+                "mandatory_channels": channel_names(kind.mandatory_channels),
+            })
+        })
+        .collect();
+
+    let (webhook_url, webhook_secret, digest) = match settings {
+        Some(settings) => (
+            settings.webhook_url,
+            settings.webhook_secret,
+            serde_json::json!({
+                "frequency": settings.digest_frequency,
+                "time_zone": settings.digest_time_zone,
+                "hour": settings.digest_hour,
+                "weekday": settings.digest_weekday,
+                "last_digest_at": settings.last_digest_at,
+                "next_digest_at": settings.next_digest_at,
+            }),
+        ),
+        None => (None, None, serde_json::json!({ "frequency": "off" })),
+    };
+    HttpResponse::Ok().json(serde_json::json!({
+        "rules": rules,
+        "webhook_url": webhook_url,
+        "webhook_secret": webhook_secret,
+        "digest": digest,
+        "channels": channel_names(Channel::ALL),
+        "kinds": kinds,
+    }))
+}
+
+/// Turn off digest emails from the link at the bottom of each digest, without signing in
+pub async fn unsubscribe_digest(
+    db: web::Data<DbContext>,
+    config: web::Data<DigestConfig>,
+    req: web::Json<UnsubscribeDigestRequest>,
+) -> impl Responder {
+    let Some(account_id) = config.parse_unsubscribe_token(&req.token) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid unsubscribe link"
+        }));
+    };
+
+    match db.disable_digest(account_id).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "You will no longer receive notification digest emails"
+        })),
+        Err(e) => {
+            log::error!("Failed to unsubscribe from digest: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to unsubscribe"
+            }))
+        }
+    }
+}
+
+pub async fn request_data_export(
+    db: web::Data<DbContext>,
+    auth_service: web::Data<Arc<AuthService>>,
+    notifier: web::Data<Arc<Notifier>>,
+    export_config: web::Data<ExportConfig>,
+    meta: RequestMeta,
+    user: AuthenticatedUser,
+    CurrentAccount(account): CurrentAccount,
+) -> impl Responder {
+    // An export pending for too long was lost and shouldn't block a new one
+    let stale_before = Utc::now() - export_config.pending_timeout;
+    if let Err(e) = db.fail_stale_data_exports(Some(account.id), stale_before).await {
+        log::error!("Failed to fail stale data exports: {:?}", e);
+    }
+
+    match db.get_pending_data_export(account.id).await {
+        Ok(Some(pending)) => {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "A data export is already in progress",
+                "export_id": pending.id
+            }));
+        }
+        Ok(None) => {}
+        Err(e) => {
+            log::error!("Failed to check pending data exports: {:?}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to request data export"
+            }));
+        }
+    }
+
+    let data_export = match db.create_data_export(account.id).await {
+        Ok(data_export) => data_export,
+        // Another request started an export in the meantime
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "A data export is already in progress"
+            }));
+        }
+        Err(e) => {
+            log::error!("Failed to create data export: {:?}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to request data export"
+            }));
+        }
+    };
+
+    audit::record(
+        &db,
+        &meta,
+        AuditEntry::own(AuditEventType::DataExportRequested, user.account_id)
+            .with_metadata(serde_json::json!({ "export_id": data_export.id })),
+    )
+    .await;
+
+    actix_web::rt::spawn(export::run_export(
+        db.get_ref().clone(),
+        auth_service.get_ref().clone(),
+        notifier.get_ref().clone(),
+        export_config.get_ref().clone(),
+        data_export.id,
+        account,
+    ));
+
+    HttpResponse::Accepted().json(data_export)
+}
+
+pub async fn get_data_export(
+    db: web::Data<DbContext>,
+    CurrentAccount(account): CurrentAccount,
+    export_id: web::Path<uuid::Uuid>,
+) -> impl Responder {
+    match db.get_data_export(*export_id, account.id).await {
+        Ok(Some(data_export)) => {
+            let downloadable = data_export.status == "ready"
+                && data_export.expires_at.is_some_and(|t| t > Utc::now());
+            let download_url = downloadable
+                .then(|| format!("/api/account/export/{}/download", data_export.id));
+            HttpResponse::Ok().json(serde_json::json!({
+                "export": data_export,
+                "download_url": download_url
+            }))
+        }
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Data export not found"
+        })),
+        Err(e) => {
+            log::error!("Failed to get data export: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to get data export"
+            }))
+        }
+    }
+}
+
+pub async fn download_data_export(
+    db: web::Data<DbContext>,
+    meta: RequestMeta,
+    user: AuthenticatedUser,
+    CurrentAccount(account): CurrentAccount,
+    export_id: web::Path<uuid::Uuid>,
+) -> impl Responder {
+    match db.get_data_export_archive(*export_id, account.id).await {
+        Ok(Some(archive)) => {
+            audit::record(
+                &db,
+                &meta,
+                AuditEntry::own(AuditEventType::DataExportDownloaded, user.account_id)
+                    .with_metadata(serde_json::json!({ "export_id": *export_id })),
+            )
+            .await;
+            HttpResponse::Ok()
+                .content_type("application/zip")
+                .insert_header((
+                    actix_web::http::header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"data-export-{}.zip\"", export_id),
+                ))
+                .body(archive)
+        }
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Data export not found or expired"
+        })),
+        Err(e) => {
+            log::error!("Failed to get data export archive: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to download data export"
+            }))
+        }
+    }
+}
+
+pub async fn get_security_log(
+    db: web::Data<DbContext>,
+    user: AuthenticatedUser,
+    query: web::Query<SecurityLogQuery>,
+) -> impl Responder {
+    let limit = query
+        .limit
+        .unwrap_or(AUDIT_LOG_DEFAULT_LIMIT)
+        .clamp(1, AUDIT_LOG_MAX_LIMIT);
+
+    match db
+        .get_audit_events_for_account(user.account_id, query.before, limit)
+        .await
+    {
+        Ok(events) => HttpResponse::Ok().json(events),
         Err(e) => {
-            log::error!("Failed to delete account record: {:?}", e);
+            log::error!("Failed to get security log: {:?}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to delete account"
+                "error": "Failed to get security log"
             }))
         }
     }
 }
 
-// Notification handlers
+// Invite handlers
 
-pub async fn create_notification(
+pub async fn create_invite(
     db: web::Data<DbContext>,
+    registration: web::Data<RegistrationConfig>,
+    admin_config: web::Data<AdminConfig>,
     user: AuthenticatedUser,
-    req: web::Json<CreateNotificationRequest>,
+    CurrentAccount(account): CurrentAccount,
+    req: web::Json<CreateInviteRequest>,
 ) -> impl Responder {
-    // Validate level
-    if !["info", "warning", "error"].contains(&req.level.as_str()) {
+    let is_admin = admin_config.is_admin(&user.email);
+    if !is_admin && !registration.user_invites_enabled {
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Creating invites is not allowed"
+        }));
+    }
+
+    let max_uses = req.max_uses.unwrap_or(1);
+    if max_uses < 1 {
         return HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Invalid level. Must be 'info', 'warning', or 'error'"
+            "error": "max_uses must be at least 1"
+        }));
+    }
+    if !is_admin && max_uses > registration.user_invite_max_uses {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("max_uses must be {} or less", registration.user_invite_max_uses)
         }));
     }
 
-    // Get account by IAM ID
-    let account = match db.get_account_by_iam_id(user.account_id).await {
-        Ok(Some(acc)) => acc,
-        Ok(None) => {
-            return HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Account not found"
-            }));
-        }
-        Err(e) => {
-            log::error!("Failed to get account: {:?}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get account"
+    // Invites created by regular users always expire
+    let expires_in_days = match req.expires_in_days {
+        Some(days) if days < 1 => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "expires_in_days must be at least 1"
             }));
         }
+        Some(days) => Some(days),
+        None if is_admin => None,
+        None => Some(7),
     };
+    let expires_at = expires_in_days.map(|days| Utc::now() + chrono::Duration::days(days));
 
-    // Use authenticated user's account_id (ignore account_id from request for security)
     match db
-        .create_notification(account.id, &req.level, &req.message)
+        .create_invite_code(
+            &registration::generate_invite_code(),
+            Some(account.id),
+            req.email.as_deref(),
+            max_uses,
+            expires_at,
+        )
         .await
     {
-        Ok(notification) => HttpResponse::Created().json(notification),
+        Ok(invite) => HttpResponse::Created().json(invite),
         Err(e) => {
-            log::error!("Failed to create notification: {:?}", e);
+            log::error!("Failed to create invite code: {:?}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to create notification"
+                "error": "Failed to create invite"
             }))
         }
     }
 }
 
-pub async fn get_notifications(
+pub async fn get_invites(
     db: web::Data<DbContext>,
-    user: AuthenticatedUser,
+    CurrentAccount(account): CurrentAccount,
 ) -> impl Responder {
-    // Get account by IAM ID
-    let account = match db.get_account_by_iam_id(user.account_id).await {
-        Ok(Some(acc)) => acc,
-        Ok(None) => {
-            return HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Account not found"
-            }));
-        }
-        Err(e) => {
-            log::error!("Failed to get account: {:?}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get account"
-            }));
-        }
-    };
-
-    match db.get_notifications(account.id).await {
-        Ok(notifications) => HttpResponse::Ok().json(notifications),
+    match db.get_invite_codes_by_creator(account.id).await {
+        Ok(invites) => HttpResponse::Ok().json(invites),
         Err(e) => {
-            log::error!("Failed to get notifications: {:?}", e);
+            log::error!("Failed to get invite codes: {:?}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get notifications"
+                "error": "Failed to get invites"
             }))
         }
     }
 }
 
-pub async fn get_unread_count(
+pub async fn revoke_invite(
     db: web::Data<DbContext>,
+    admin_config: web::Data<AdminConfig>,
     user: AuthenticatedUser,
+    CurrentAccount(account): CurrentAccount,
+    invite_id: web::Path<uuid::Uuid>,
 ) -> impl Responder {
-    // Get account by IAM ID
-    let account = match db.get_account_by_iam_id(user.account_id).await {
-        Ok(Some(acc)) => acc,
-        Ok(None) => {
-            return HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Account not found"
-            }));
-        }
-        Err(e) => {
-            log::error!("Failed to get account: {:?}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get account"
-            }));
-        }
-    };
+    // Admins may revoke any invite, users only their own
+    let created_by = (!admin_config.is_admin(&user.email)).then_some(account.id);
 
-    match db.get_unread_count(account.id).await {
-        Ok(count) => HttpResponse::Ok().json(serde_json::json!({
-            "count": count
+    match db.revoke_invite_code(*invite_id, created_by).await {
+        Ok(0) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Invite not found"
+        })),
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Invite revoked successfully"
         })),
         Err(e) => {
-            log::error!("Failed to get unread count: {:?}", e);
+            log::error!("Failed to revoke invite code: {:?}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get unread count"
+                "error": "Failed to revoke invite"
             }))
         }
     }
 }
 
-pub async fn update_notification(
+// Admin handlers
+
+pub async fn admin_get_audit_events(
     db: web::Data<DbContext>,
-    user: AuthenticatedUser,
-    notification_id: web::Path<uuid::Uuid>,
-    req: web::Json<UpdateNotificationRequest>,
+    admin: AdminUser,
+    query: web::Query<AuditEventsQuery>,
 ) -> impl Responder {
-    // Get account by IAM ID
-    let account = match db.get_account_by_iam_id(user.account_id).await {
-        Ok(Some(acc)) => acc,
-        Ok(None) => {
-            return HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Account not found"
-            }));
-        }
-        Err(e) => {
-            log::error!("Failed to get account: {:?}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get account"
-            }));
-        }
-    };
+    log::info!("Admin {} queried audit events: {:?}", admin.0.email, query);
 
-    match db
-        .update_notification_read(*notification_id, account.id, req.read)
-        .await
-    {
-        Ok(notification) => HttpResponse::Ok().json(notification),
-        Err(sqlx::Error::RowNotFound) => {
-            HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Notification not found"
+    let limit = query
+        .limit
+        .unwrap_or(AUDIT_LOG_DEFAULT_LIMIT)
+        .clamp(1, AUDIT_LOG_MAX_LIMIT);
+
+    match db.search_audit_events(&query, limit).await {
+        Ok(events) => HttpResponse::Ok().json(events),
+        Err(e) => {
+            log::error!("Failed to search audit events: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to get audit events"
             }))
         }
+    }
+}
+
+pub async fn admin_get_waitlist(
+    db: web::Data<DbContext>,
+    _admin: AdminUser,
+    query: web::Query<WaitlistQuery>,
+) -> impl Responder {
+    match db.get_waitlist_entries(query.status.as_deref()).await {
+        Ok(entries) => HttpResponse::Ok().json(entries),
         Err(e) => {
-            log::error!("Failed to update notification: {:?}", e);
+            log::error!("Failed to get waitlist: {:?}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to update notification"
+                "error": "Failed to get waitlist"
             }))
         }
     }
 }
 
-pub async fn update_notifications_batch(
+pub async fn admin_approve_waitlist(
     db: web::Data<DbContext>,
-    user: AuthenticatedUser,
-    req: web::Json<serde_json::Value>,
+    registration: web::Data<RegistrationConfig>,
+    mailer: web::Data<Arc<dyn Mailer>>,
+    _admin: AdminUser,
+    CurrentAccount(admin_account): CurrentAccount,
+    req: web::Json<ApproveWaitlistRequest>,
 ) -> impl Responder {
-    // Get account by IAM ID
-    let account = match db.get_account_by_iam_id(user.account_id).await {
-        Ok(Some(acc)) => acc,
-        Ok(None) => {
-            return HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Account not found"
-            }));
-        }
+    if req.entry_ids.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "entry_ids must not be empty"
+        }));
+    }
+
+    let expires_at = Utc::now() + registration.waitlist_invite_ttl;
+    let approved = match db
+        .approve_waitlist_entries(
+            &req.entry_ids,
+            admin_account.id,
+            expires_at,
+            registration::generate_invite_code,
+        )
+        .await
+    {
+        Ok(approved) => approved,
         Err(e) => {
-            log::error!("Failed to get account: {:?}", e);
+            log::error!("Failed to approve waitlist entries: {:?}", e);
             return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get account"
+                "error": "Failed to approve waitlist entries"
             }));
         }
     };
 
-    let notification_ids: Vec<uuid::Uuid> = match req["notification_ids"].as_array() {
-        Some(arr) => {
-            arr.iter()
-                .filter_map(|v| v.as_str().and_then(|s| uuid::Uuid::parse_str(s).ok()))
-                .collect()
+    let frontend_url = mailer::frontend_url();
+    for (entry, invite) in &approved {
+        let body = registration::approval_email_body(&invite.code, &frontend_url);
+        if let Err(e) = mailer
+            .send_email(&entry.email, "Your signup has been approved", &body)
+            .await
+        {
+            log::error!("Failed to send approval email to {}: {:?}", entry.email, e);
         }
-        None => {
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "notification_ids array is required"
+    }
+
+    let entries: Vec<_> = approved.into_iter().map(|(entry, _)| entry).collect();
+    HttpResponse::Ok().json(serde_json::json!({
+        "approved_count": entries.len(),
+        "entries": entries
+    }))
+}
+
+pub async fn admin_get_token_cache_stats(
+    token_cache: web::Data<Arc<TokenCache>>,
+    _admin: AdminUser,
+) -> impl Responder {
+    HttpResponse::Ok().json(token_cache.stats())
+}
+
+/// Send a notification to every account in a segment, delivered in the background
+pub async fn admin_create_broadcast(
+    db: web::Data<DbContext>,
+    admin_config: web::Data<AdminConfig>,
+    AdminUser(admin): AdminUser,
+    req: web::Json<CreateBroadcastRequest>,
+) -> impl Responder {
+    let req = req.into_inner();
+    let notification = NewNotification {
+        kind: req.kind,
+        level: req.level,
+        title: req.title,
+        message: req.message,
+        data: serde_json::json!({}),
+        action_url: req.action_url,
+        actions: req.actions,
+        source: None,
+        expires_at: req.expires_at,
+        group_key: None,
+        dedupe_window: None,
+    };
+    if let Err(message) = notification
+        .validate()
+        .map(|_| ())
+        .and_then(|_| broadcasts::validate_segment(&req.segment))
+    {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": message }));
+    }
+
+    let total_recipients = match db
+        .count_broadcast_recipients(&req.segment, &admin_config.emails())
+        .await
+    {
+        Ok(count) => count,
+        Err(e) => {
+            log::error!("Failed to count broadcast recipients: {:?}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to create broadcast"
             }));
         }
     };
 
-    let read = req["read"]
-        .as_bool()
-        .ok_or_else(|| {
-            HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "read boolean is required"
-            }))
-        })
-        .unwrap();
-
     match db
-        .update_notifications_read_batch(&notification_ids, account.id, read)
+        .create_notification_broadcast(admin.account_id, &notification, &req.segment, total_recipients)
         .await
     {
-        Ok(notifications) => HttpResponse::Ok().json(notifications),
+        Ok(broadcast) => {
+            log::info!(
+                "Admin {} created notification broadcast {} for {} account(s)",
+                admin.account_id,
+                broadcast.id,
+                total_recipients
+            );
+            HttpResponse::Accepted().json(broadcast)
+        }
         Err(e) => {
-            log::error!("Failed to update notifications: {:?}", e);
+            log::error!("Failed to create broadcast: {:?}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to update notifications"
+                "error": "Failed to create broadcast"
             }))
         }
     }
 }
 
-pub async fn delete_notification(
+pub async fn admin_get_broadcasts(
     db: web::Data<DbContext>,
-    user: AuthenticatedUser,
-    notification_id: web::Path<uuid::Uuid>,
+    _admin: AdminUser,
 ) -> impl Responder {
-    // Get account by IAM ID
-    let account = match db.get_account_by_iam_id(user.account_id).await {
-        Ok(Some(acc)) => acc,
-        Ok(None) => {
-            return HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Account not found"
-            }));
-        }
+    match db.get_notification_broadcasts(BROADCASTS_LIST_LIMIT).await {
+        Ok(broadcasts) => HttpResponse::Ok().json(broadcasts),
         Err(e) => {
-            log::error!("Failed to get account: {:?}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get account"
-            }));
+            log::error!("Failed to get broadcasts: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to get broadcasts"
+            }))
         }
-    };
+    }
+}
 
-    match db.delete_notification(*notification_id, account.id).await {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-            "message": "Notification deleted successfully"
+/// A broadcast with its delivery progress
+pub async fn admin_get_broadcast(
+    db: web::Data<DbContext>,
+    _admin: AdminUser,
+    broadcast_id: web::Path<uuid::Uuid>,
+) -> impl Responder {
+    match db.get_notification_broadcast(broadcast_id.into_inner()).await {
+        Ok(Some(broadcast)) => HttpResponse::Ok().json(broadcast),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Broadcast not found"
         })),
         Err(e) => {
-            log::error!("Failed to delete notification: {:?}", e);
+            log::error!("Failed to get broadcast: {:?}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to delete notification"
+                "error": "Failed to get broadcast"
             }))
         }
     }
 }
 
-pub async fn delete_notifications_batch(
+/// Stop delivering a broadcast and remove the notifications it already created
+pub async fn admin_retract_broadcast(
     db: web::Data<DbContext>,
-    user: AuthenticatedUser,
-    req: web::Json<serde_json::Value>,
+    AdminUser(admin): AdminUser,
+    broadcast_id: web::Path<uuid::Uuid>,
 ) -> impl Responder {
-    // Get account by IAM ID
-    let account = match db.get_account_by_iam_id(user.account_id).await {
-        Ok(Some(acc)) => acc,
-        Ok(None) => {
-            return HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Account not found"
-            }));
-        }
-        Err(e) => {
-            log::error!("Failed to get account: {:?}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get account"
-            }));
-        }
-    };
-
-    let notification_ids: Vec<uuid::Uuid> = match req["notification_ids"].as_array() {
-        Some(arr) => {
-            arr.iter()
-                .filter_map(|v| v.as_str().and_then(|s| uuid::Uuid::parse_str(s).ok()))
-                .collect()
-        }
-        None => {
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "notification_ids array is required"
-            }));
-        }
-    };
-
-    match db
-        .delete_notifications_batch(&notification_ids, account.id)
-        .await
-    {
-        Ok(count) => HttpResponse::Ok().json(serde_json::json!({
-            "message": format!("{} notification(s) deleted successfully", count),
-            "deleted_count": count
+    match db.retract_notification_broadcast(broadcast_id.into_inner()).await {
+        Ok(Some(broadcast)) => {
+            log::info!(
+                "Admin {} retracted notification broadcast {}",
+                admin.account_id,
+                broadcast.id
+            );
+            HttpResponse::Accepted().json(broadcast)
+        }
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Broadcast not found or already retracted"
         })),
         Err(e) => {
-            log::error!("Failed to delete notifications: {:?}", e);
+            log::error!("Failed to retract broadcast: {:?}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to delete notifications"
+                "error": "Failed to retract broadcast"
             }))
         }
     }
 }
 
-// Account settings handlers
-
-pub async fn get_account_settings(
+/// Accounts with an open realtime connection
+pub async fn admin_get_presence(
     db: web::Data<DbContext>,
-    user: AuthenticatedUser,
+    config: web::Data<RealtimeConfig>,
+    _admin: AdminUser,
 ) -> impl Responder {
-    // Get account by IAM ID
-    let account = match db.get_account_by_iam_id(user.account_id).await {
-        Ok(Some(acc)) => acc,
-        Ok(None) => {
-            return HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Account not found"
-            }));
-        }
+    let seen_since = Utc::now() - config.presence_ttl();
+    match db.get_online_accounts(seen_since).await {
+        Ok(online) => HttpResponse::Ok().json(serde_json::json!({
+            "count": online.len(),
+            "online": online,
+        })),
         Err(e) => {
-            log::error!("Failed to get account: {:?}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get account"
-            }));
+            log::error!("Failed to get presence: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to get presence"
+            }))
         }
+    }
+}
+
+/// Record a failed password login, attributing it to the account if the email is
+/// known, and count it towards the proof-of-work difficulty
+async fn record_login_failure(
+    db: &DbContext,
+    pow: &PowService,
+    meta: &RequestMeta,
+    login: &str,
+    reason: &str,
+) {
+    pow.record_rejection(meta.ip_address.as_deref());
+
+    // Unknown usernames never reach nano-iam, so only emails can be attributed.
+    // What was typed is kept only as a hash when no account matches.
+    let target = if usernames::is_email(login) {
+        match db.find_iam_account_id_by_email(login).await {
+            Ok(id) => id,
+            Err(e) => {
+                log::warn!("Failed to look up account for audit event: {:?}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let metadata = match target {
+        Some(_) => serde_json::json!({ "reason": reason }),
+        None => serde_json::json!({ "login_hash": audit::identifier_hash(login), "reason": reason }),
     };
 
-    HttpResponse::Ok().json(AccountSettings {
-        username: account.username,
-    })
+    audit::record(
+        db,
+        meta,
+        AuditEntry::anonymous(AuditEventType::LoginFailed, target).with_metadata(metadata),
+    )
+    .await;
 }
 
-pub async fn update_account_settings(
-    db: web::Data<DbContext>,
-    user: AuthenticatedUser,
-    req: web::Json<UpdateAccountSettingsRequest>,
+pub async fn get_pow_challenge(
+    pow: web::Data<Arc<PowService>>,
+    ClientIp(client_ip): ClientIp,
+    query: web::Query<ChallengeQuery>,
 ) -> impl Responder {
-    // Get account by IAM ID
-    let account = match db.get_account_by_iam_id(user.account_id).await {
-        Ok(Some(acc)) => acc,
-        Ok(None) => {
-            return HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Account not found"
-            }));
-        }
-        Err(e) => {
-            log::error!("Failed to get account: {:?}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get account"
+    let action = match PowAction::parse(&query.action) {
+        Some(action) => action,
+        None => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid action"
             }));
         }
     };
 
-    // Validate username if provided
-    if let Some(ref username) = req.username {
-        let trimmed = username.trim();
-        if trimmed.is_empty() {
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Username cannot be empty"
-            }));
-        }
-        if trimmed.len() > 255 {
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Username must be 255 characters or less"
-            }));
-        }
+    if !pow.enabled() {
+        return HttpResponse::Ok().json(serde_json::json!({
+            "enabled": false
+        }));
     }
 
-    match db
-        .update_account_settings(account.id, req.username.clone())
-        .await
-    {
-        Ok(updated_account) => HttpResponse::Ok().json(AccountSettings {
-            username: updated_account.username,
-        }),
-        Err(e) => {
-            log::error!("Failed to update account settings: {:?}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to update account settings"
-            }))
-        }
-    }
+    HttpResponse::Ok().json(pow.issue(action, Some(&client_ip.to_string())))
 }
 
 pub async fn get_google_oauth_config() -> impl Responder {
@@ -0,0 +1,41 @@
+use nano_iam::LeaseLock;
+use std::future::Future;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Spawn a background job that runs every `interval`.
+///
+/// Each run is guarded by a `LeaseLock` lease named after the job, so only one
+/// replica executes it at a time. The lease outlives the interval so a slow run
+/// is not picked up again by another replica while still in progress.
+pub fn spawn_periodic<F, Fut>(name: &'static str, interval: Duration, lock: LeaseLock, job: F)
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let holder = Uuid::new_v4().to_string();
+    let lease_ttl = chrono::Duration::from_std(interval * 2)
+        .unwrap_or_else(|_| chrono::Duration::hours(1));
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+
+            match lock.try_acquire(name, &holder, lease_ttl).await {
+                Ok(true) => {
+                    job().await;
+                    if let Err(e) = lock.release(name, &holder).await {
+                        log::warn!("Failed to release lease for job {}: {:?}", name, e);
+                    }
+                }
+                Ok(false) => {
+                    log::debug!("Job {} is running on another instance, skipping", name);
+                }
+                Err(e) => {
+                    log::error!("Failed to acquire lease for job {}: {:?}", name, e);
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,33 @@
+/// Sender for application emails that nano-iam's `EmailSender` does not cover
+#[async_trait::async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send_email(
+        &self,
+        to: &str,
+        subject: &str,
+        body: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+}
+
+// Dummy mailer for development
+pub struct DummyMailer;
+
+#[async_trait::async_trait]
+impl Mailer for DummyMailer {
+    async fn send_email(
+        &self,
+        to: &str,
+        subject: &str,
+        body: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        log::info!("[DEV] Email to {}: {}\n{}", to, subject, body);
+        Ok(())
+    }
+}
+
+/// Base URL of the frontend, used for links in emails
+pub fn frontend_url() -> String {
+    std::env::var("FRONTEND_URL")
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| "http://localhost:3000".to_string())
+}
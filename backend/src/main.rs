@@ -1,7 +1,31 @@
+mod audit;
 mod auth;
+mod broadcasts;
+mod client_ip;
 mod dba;
+mod deletion;
+mod devices;
+mod digest;
+mod export;
+mod geoip;
 mod handlers;
+mod jobs;
+mod mailer;
 mod models;
+mod notification_stream;
+mod notifications;
+mod notifier;
+mod password_check;
+mod pow;
+mod privacy;
+mod reauth;
+mod realtime;
+mod registration;
+mod retention;
+mod sessions;
+mod token_cache;
+mod usernames;
+mod verification;
 
 use actix_cors::Cors;
 use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
@@ -42,7 +66,9 @@ async fn health_check() -> impl Responder {
 }
 
 // Dummy email sender for development
-struct DummyEmailSender;
+struct DummyEmailSender {
+    verification: verification::VerificationConfig,
+}
 
 #[async_trait::async_trait]
 impl EmailSender for DummyEmailSender {
@@ -52,7 +78,12 @@ impl EmailSender for DummyEmailSender {
         code: &str,
         _service_name: Option<&str>,
     ) -> Result<(), IamError> {
-        log::info!("[DEV] Verification email to {}: code = {}", to, code);
+        log::info!(
+            "[DEV] Verification email to {}: code = {}, link = {}",
+            to,
+            code,
+            self.verification.link(to, code)
+        );
         Ok(())
     }
 
@@ -83,7 +114,11 @@ async fn main() -> std::io::Result<()> {
         .await
         .expect("Failed to initialize database");
     
-    let email_sender: Arc<dyn EmailSender> = Arc::new(DummyEmailSender);
+    let verification_code_ttl = Duration::hours(1);
+    let verification_config = verification::VerificationConfig::from_env(verification_code_ttl);
+    let email_sender: Arc<dyn EmailSender> = Arc::new(DummyEmailSender {
+        verification: verification_config.clone(),
+    });
     let lock = LeaseLock::new(db_context.pool().clone());
     
     // Create IAM repository for auth service
@@ -95,7 +130,7 @@ async fn main() -> std::io::Result<()> {
             refresh_ttl: Duration::days(30),     // 30 days
         },
         email_verification: EmailVerificationConfig {
-            code_ttl: Duration::hours(1), // 1 hour
+            code_ttl: verification_code_ttl,
             code_length: 6,
         },
         password_policy: PasswordPolicy::default(),
@@ -109,6 +144,189 @@ async fn main() -> std::io::Result<()> {
         lock,
     ));
 
+    let admin_config = auth::AdminConfig::from_env();
+    let token_cache = Arc::new(token_cache::TokenCache::new(
+        db_context.clone(),
+        token_cache::TokenCacheConfig::from_env(),
+    ));
+    token_cache::spawn_listener(token_cache.clone());
+    let notification_stream_config = notification_stream::NotificationStreamConfig::from_env();
+    let notification_hub = Arc::new(notification_stream::NotificationHub::new(
+        db_context.clone(),
+        notification_stream_config.clone(),
+    ));
+    notification_stream::spawn_listener(notification_hub.clone());
+    let deletion_config = deletion::DeletionConfig::from_env();
+    let export_config = export::ExportConfig::from_env();
+    let registration_config = registration::RegistrationConfig::from_env();
+    let password_check_config = password_check::PasswordCheckConfig::from_env();
+    let privacy_config = privacy::PrivacyConfig::from_env();
+    let reauth_config = reauth::ReauthConfig::from_env();
+    let session_config = sessions::SessionConfig::from_env();
+    let realtime_config = realtime::RealtimeConfig::from_env();
+    let digest_config = digest::DigestConfig::from_env();
+    let broadcast_config = broadcasts::BroadcastConfig::from_env();
+    let retention_config = retention::RetentionConfig::from_env();
+    let pow_service = Arc::new(pow::PowService::new(pow::PowConfig::from_env()));
+    let mailer: Arc<dyn mailer::Mailer> = Arc::new(mailer::DummyMailer);
+    let notifier = Arc::new(notifier::Notifier::new(
+        db_context.clone(),
+        auth_service.clone(),
+        mailer.clone(),
+        &notifier::NotifierConfig::from_env(),
+    ));
+    let geoip = Arc::new(geoip::GeoIp::from_env());
+    let trusted_proxies = client_ip::TrustedProxies::from_env();
+
+    // Purge accounts whose deletion grace period has expired
+    {
+        let db = db_context.clone();
+        let token_cache = token_cache.clone();
+        jobs::spawn_periodic(
+            "purge_deleted_accounts",
+            deletion_config.purge_interval,
+            LeaseLock::new(db_context.pool().clone()),
+            move || {
+                let db = db.clone();
+                let token_cache = token_cache.clone();
+                async move { deletion::purge_expired_accounts(&db, &token_cache).await }
+            },
+        );
+    }
+
+    // Drop data export archives once their download window has passed, and
+    // fail exports lost while being built
+    {
+        let db = db_context.clone();
+        let config = export_config.clone();
+        jobs::spawn_periodic(
+            "clear_expired_data_exports",
+            export_config.cleanup_interval,
+            LeaseLock::new(db_context.pool().clone()),
+            move || {
+                let db = db.clone();
+                let config = config.clone();
+                async move { export::clear_expired_exports(&db, &config).await }
+            },
+        );
+    }
+
+    // Forget redeemed proof-of-work challenges once they have expired
+    {
+        let db = db_context.clone();
+        jobs::spawn_periodic(
+            "clear_expired_pow_redemptions",
+            std::time::Duration::from_secs(600),
+            LeaseLock::new(db_context.pool().clone()),
+            move || {
+                let db = db.clone();
+                async move { pow::clear_expired_redemptions(&db).await }
+            },
+        );
+    }
+
+    // Forget used verification links and attempt counters once their codes have expired
+    {
+        let db = db_context.clone();
+        let config = verification_config.clone();
+        jobs::spawn_periodic(
+            "clear_used_verification_links",
+            std::time::Duration::from_secs(3600),
+            LeaseLock::new(db_context.pool().clone()),
+            move || {
+                let db = db.clone();
+                let config = config.clone();
+                async move { verification::clear_expired_links(&db, &config).await }
+            },
+        );
+    }
+
+    // Forget notification events too old to resume a stream from
+    {
+        let db = db_context.clone();
+        let config = notification_stream_config.clone();
+        jobs::spawn_periodic(
+            "clear_old_notification_events",
+            std::time::Duration::from_secs(3600),
+            LeaseLock::new(db_context.pool().clone()),
+            move || {
+                let db = db.clone();
+                let config = config.clone();
+                async move { notification_stream::clear_old_events(&db, &config).await }
+            },
+        );
+    }
+
+    // Forget realtime connections left behind by replicas that stopped without cleaning up
+    {
+        let db = db_context.clone();
+        let config = realtime_config.clone();
+        jobs::spawn_periodic(
+            "clear_stale_realtime_connections",
+            std::time::Duration::from_secs(300),
+            LeaseLock::new(db_context.pool().clone()),
+            move || {
+                let db = db.clone();
+                let config = config.clone();
+                async move { realtime::clear_stale_connections(&db, &config).await }
+            },
+        );
+    }
+
+    // Email notification digests that are due
+    {
+        let db = db_context.clone();
+        let auth_service = auth_service.clone();
+        let mailer = mailer.clone();
+        let config = digest_config.clone();
+        jobs::spawn_periodic(
+            "send_notification_digests",
+            std::time::Duration::from_secs(300),
+            LeaseLock::new(db_context.pool().clone()),
+            move || {
+                let db = db.clone();
+                let auth_service = auth_service.clone();
+                let mailer = mailer.clone();
+                let config = config.clone();
+                async move { digest::send_due_digests(&db, &auth_service, &mailer, &config).await }
+            },
+        );
+    }
+
+    // Delete expired notifications and those past their retention period
+    {
+        let db = db_context.clone();
+        let config = retention_config.clone();
+        jobs::spawn_periodic(
+            "purge_notifications",
+            std::time::Duration::from_secs(3600),
+            LeaseLock::new(db_context.pool().clone()),
+            move || {
+                let db = db.clone();
+                let config = config.clone();
+                async move { retention::purge_notifications(&db, &config).await }
+            },
+        );
+    }
+
+    // Deliver and retract admin broadcasts in batches
+    {
+        let db = db_context.clone();
+        let admin_config = admin_config.clone();
+        let config = broadcast_config.clone();
+        jobs::spawn_periodic(
+            "process_notification_broadcasts",
+            std::time::Duration::from_secs(10),
+            LeaseLock::new(db_context.pool().clone()),
+            move || {
+                let db = db.clone();
+                let admin_config = admin_config.clone();
+                let config = config.clone();
+                async move { broadcasts::process_broadcasts(&db, &admin_config, &config).await }
+            },
+        );
+    }
+
     let host = env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
     let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
     let bind_address = format!("{}:{}", host, port);
@@ -127,8 +345,43 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .app_data(web::Data::new(db_context.clone()))
             .app_data(web::Data::new(auth_service.clone()))
+            .app_data(web::Data::new(admin_config.clone()))
+            .app_data(web::Data::new(deletion_config.clone()))
+            .app_data(web::Data::new(export_config.clone()))
+            .app_data(web::Data::new(registration_config.clone()))
+            .app_data(web::Data::new(pow_service.clone()))
+            .app_data(web::Data::new(password_check_config.clone()))
+            .app_data(web::Data::new(token_cache.clone()))
+            .app_data(web::Data::new(notification_hub.clone()))
+            .app_data(web::Data::new(mailer.clone()))
+            .app_data(web::Data::new(notifier.clone()))
+            .app_data(web::Data::new(geoip.clone()))
+            .app_data(web::Data::new(trusted_proxies.clone()))
+            .app_data(web::Data::new(verification_config.clone()))
+            .app_data(web::Data::new(privacy_config.clone()))
+            .app_data(web::Data::new(reauth_config.clone()))
+            .app_data(web::Data::new(session_config.clone()))
+            .app_data(web::Data::new(realtime_config.clone()))
+            .app_data(web::Data::new(digest_config.clone()))
+            // Report malformed JSON bodies in the same shape as other errors
+            .app_data(web::JsonConfig::default().error_handler(|err, _req| {
+                let response = HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": err.to_string()
+                }));
+                actix_web::error::InternalError::from_response(err, response).into()
+            }))
             .wrap(cors)
-            .wrap(actix_web::middleware::Logger::default())
+            // Same as the default format, with the client address resolved through trusted proxies
+            .wrap(
+                actix_web::middleware::Logger::new(
+                    r#"%{client_ip}xi "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#,
+                )
+                .custom_request_replace("client_ip", |req| {
+                    client_ip::resolve(req.request())
+                        .map(|ip| ip.to_string())
+                        .unwrap_or_else(|| "-".to_string())
+                }),
+            )
             // Public routes
             .service(get_status)
             .service(health_check)
@@ -148,6 +401,10 @@ async fn main() -> std::io::Result<()> {
                 "/api/auth/verify-email",
                 web::post().to(handlers::verify_email),
             )
+            .route(
+                "/api/auth/verify-email/link",
+                web::get().to(handlers::verify_email_link),
+            )
             .route(
                 "/api/auth/resend-verification",
                 web::post().to(handlers::resend_verification),
@@ -156,16 +413,39 @@ async fn main() -> std::io::Result<()> {
                 "/api/auth/refresh",
                 web::post().to(handlers::refresh_token),
             )
+            .route(
+                "/api/auth/report-login",
+                web::post().to(handlers::report_unrecognized_login),
+            )
+            .route(
+                "/api/auth/password-reset",
+                web::post().to(handlers::request_password_reset),
+            )
+            .route(
+                "/api/auth/password-reset/confirm",
+                web::post().to(handlers::reset_password),
+            )
+            .route(
+                "/api/auth/challenge",
+                web::get().to(handlers::get_pow_challenge),
+            )
             .route(
                 "/api/auth/google-oauth-config",
                 web::get().to(handlers::get_google_oauth_config),
             )
+            .route(
+                "/api/notification-digest/unsubscribe",
+                web::post().to(handlers::unsubscribe_digest),
+            )
+            // Authenticates itself, the token usually arrives after the upgrade
+            .route("/api/ws", web::get().to(realtime::connect))
             // Protected routes
             .service(
                 web::scope("/api/auth")
                     .wrap(auth.clone())
                     .route("/logout", web::post().to(handlers::logout))
                     .route("/me", web::get().to(handlers::get_me))
+                    .route("/reauth", web::post().to(handlers::reauthenticate))
                     .route("/change-password", web::post().to(handlers::change_password))
                     .route("/delete-account", web::post().to(handlers::delete_account)),
             )
@@ -176,14 +456,8 @@ async fn main() -> std::io::Result<()> {
                     .route("", web::get().to(handlers::get_notifications))
                     .route("", web::post().to(handlers::create_notification))
                     .route("/unread-count", web::get().to(handlers::get_unread_count))
-                    .route(
-                        "/{id}",
-                        web::put().to(handlers::update_notification),
-                    )
-                    .route(
-                        "/{id}",
-                        web::delete().to(handlers::delete_notification),
-                    )
+                    .route("/stream", web::get().to(handlers::stream_notifications))
+                    // Fixed paths go before /{id}, which would otherwise match them
                     .route(
                         "/batch",
                         web::put().to(handlers::update_notifications_batch),
@@ -191,6 +465,22 @@ async fn main() -> std::io::Result<()> {
                     .route(
                         "/batch",
                         web::delete().to(handlers::delete_notifications_batch),
+                    )
+                    .route(
+                        "/bulk",
+                        web::put().to(handlers::bulk_update_notifications),
+                    )
+                    .route(
+                        "/bulk",
+                        web::delete().to(handlers::bulk_delete_notifications),
+                    )
+                    .route(
+                        "/{id}",
+                        web::put().to(handlers::update_notification),
+                    )
+                    .route(
+                        "/{id}",
+                        web::delete().to(handlers::delete_notification),
                     ),
             )
             // Account settings routes
@@ -200,6 +490,57 @@ async fn main() -> std::io::Result<()> {
                     .route("", web::get().to(handlers::get_account_settings))
                     .route("", web::put().to(handlers::update_account_settings)),
             )
+            .service(
+                web::scope("/api/account")
+                    .wrap(auth.clone())
+                    .route("/security-log", web::get().to(handlers::get_security_log))
+                    .route("/restore", web::post().to(handlers::restore_account))
+                    .route(
+                        "/notification-preferences",
+                        web::get().to(handlers::get_notification_preferences),
+                    )
+                    .route(
+                        "/notification-preferences",
+                        web::put().to(handlers::update_notification_preferences),
+                    )
+                    .route("/export", web::post().to(handlers::request_data_export))
+                    .route("/export/{id}", web::get().to(handlers::get_data_export))
+                    .route(
+                        "/export/{id}/download",
+                        web::get().to(handlers::download_data_export),
+                    ),
+            )
+            // Invite routes (all protected)
+            .service(
+                web::scope("/api/invites")
+                    .wrap(auth.clone())
+                    .route("", web::get().to(handlers::get_invites))
+                    .route("", web::post().to(handlers::create_invite))
+                    .route("/{id}", web::delete().to(handlers::revoke_invite)),
+            )
+            // Admin routes (protected, admin allowlist checked per handler)
+            .service(
+                web::scope("/api/admin")
+                    .wrap(auth.clone())
+                    .route("/audit-events", web::get().to(handlers::admin_get_audit_events))
+                    .route(
+                        "/token-cache",
+                        web::get().to(handlers::admin_get_token_cache_stats),
+                    )
+                    .route("/presence", web::get().to(handlers::admin_get_presence))
+                    .route("/broadcasts", web::get().to(handlers::admin_get_broadcasts))
+                    .route("/broadcasts", web::post().to(handlers::admin_create_broadcast))
+                    .route("/broadcasts/{id}", web::get().to(handlers::admin_get_broadcast))
+                    .route(
+                        "/broadcasts/{id}/retract",
+                        web::post().to(handlers::admin_retract_broadcast),
+                    )
+                    .route("/waitlist", web::get().to(handlers::admin_get_waitlist))
+                    .route(
+                        "/waitlist/approve",
+                        web::post().to(handlers::admin_approve_waitlist),
+                    ),
+            )
     })
     .bind(&bind_address)?
     .run()
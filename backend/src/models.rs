@@ -10,6 +10,9 @@ pub struct Account {
     pub display_name: Option<String>,
     pub avatar_url: Option<String>,
     pub username: Option<String>,
+    pub deletion_requested_at: Option<DateTime<Utc>>,
+    pub deletion_scheduled_for: Option<DateTime<Utc>>,
+    pub password_reset_required_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -18,17 +21,27 @@ pub struct Account {
 pub struct SignupRequest {
     pub email: String,
     pub password: String,
+    pub invite_code: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct LoginRequest {
-    pub email: String,
+    /// Email address or username
+    #[serde(alias = "email", alias = "username")]
+    pub login: String,
     pub password: String,
+    /// Keep the session alive longer on this device
+    #[serde(default)]
+    pub remember_me: bool,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct GoogleLoginRequest {
     pub id_token: String,
+    #[serde(default)]
+    pub remember_me: bool,
+    /// Needed for a first sign-in when registration is invite-only
+    pub invite_code: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -42,7 +55,6 @@ pub struct AuthResponse {
 
 #[derive(Debug, Serialize)]
 pub struct SignupResponse {
-    pub account_id: Uuid,
     pub email: String,
     pub message: String,
 }
@@ -56,23 +68,53 @@ pub struct AccountInfo {
     pub avatar_url: Option<String>,
     pub username: Option<String>,
     pub auth_type: String,
+    pub deletion_scheduled_for: Option<DateTime<Utc>>,
+    pub password_reset_required: bool,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct VerifyEmailRequest {
-    pub account_id: Uuid,
+    pub email: String,
     pub code: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailLinkQuery {
+    pub token: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ChangePasswordRequest {
     pub old_password: String,
     pub new_password: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RequestPasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordRequest {
+    pub email: String,
+    /// Code from the password reset email
+    pub code: String,
+    pub new_password: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct DeleteAccountRequest {
-    pub password: String,
+    /// Not needed when the session re-authenticated recently
+    pub password: Option<String>,
+}
+
+/// Exactly one proof of identity for step-up re-authentication
+#[derive(Debug, Deserialize)]
+pub struct ReauthRequest {
+    pub password: Option<String>,
+    pub totp_code: Option<String>,
+    /// Fresh Google ID token for accounts that sign in with Google
+    pub id_token: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -84,24 +126,303 @@ pub struct RefreshTokenRequest {
 pub struct Notification {
     pub id: Uuid,
     pub account_id: Uuid,
+    /// Registered kind such as `security.login`, which defines the shape of `data`
+    pub kind: String,
     pub level: String,
+    pub title: Option<String>,
     pub message: String,
+    pub data: serde_json::Value,
+    /// Where clicking the notification leads
+    pub action_url: Option<String>,
+    /// Buttons shown with the notification, as `[{"label", "url"}]`
+    pub actions: serde_json::Value,
+    /// Subsystem or client that produced the notification
+    pub source: Option<String>,
+    /// Notifications sharing a key are listed together, and repeats may collapse into one
+    pub group_key: Option<String>,
+    /// How many times this happened, counting repeats collapsed into it
+    pub occurrences: i32,
+    /// When the latest repeat happened, absent until it repeats
+    pub last_occurred_at: Option<DateTime<Utc>>,
     pub read: bool,
+    /// Hidden and purged once this passes
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Archived notifications are only listed with `archived=true`
+    pub archived_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Notifications in this one's group, only set when listing with `grouped=true`
+    #[sqlx(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_size: Option<i64>,
+}
+
+/// A change to an account's notifications, as delivered by the notification stream
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct NotificationEvent {
+    pub id: i64,
+    pub account_id: Uuid,
+    pub event_type: String,
+    pub data: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A button shown with a notification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationAction {
+    pub label: String,
+    pub url: String,
+}
+
+/// Channels an account wants for one notification kind or level
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct NotificationPreference {
+    pub kind: Option<String>,
+    pub level: Option<String>,
+    /// Empty to receive nothing
+    pub channels: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct NotificationSettings {
+    pub account_id: Uuid,
+    pub webhook_url: Option<String>,
+    pub webhook_secret: Option<String>,
+    /// `off`, `daily` or `weekly`
+    pub digest_frequency: String,
+    /// IANA time zone the digest hour is in
+    pub digest_time_zone: String,
+    pub digest_hour: i16,
+    /// ISO weekday of weekly digests, Monday = 1
+    pub digest_weekday: i16,
+    pub last_digest_at: Option<DateTime<Utc>>,
+    pub next_digest_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// When and how often an account receives digest emails
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestSettings {
+    pub frequency: String,
+    #[serde(default = "default_digest_time_zone")]
+    pub time_zone: String,
+    #[serde(default = "default_digest_hour")]
+    pub hour: i16,
+    #[serde(default = "default_digest_weekday")]
+    pub weekday: i16,
+}
+
+fn default_digest_time_zone() -> String {
+    "UTC".to_string()
+}
+
+fn default_digest_hour() -> i16 {
+    8
+}
+
+fn default_digest_weekday() -> i16 {
+    1
+}
+
+/// An account whose digest email is due
+#[derive(Debug, Clone, FromRow)]
+pub struct DigestSchedule {
+    pub account_id: Uuid,
+    pub iam_account_id: Uuid,
+    pub digest_frequency: String,
+    pub digest_time_zone: String,
+    pub digest_hour: i16,
+    pub digest_weekday: i16,
+    pub last_digest_at: Option<DateTime<Utc>>,
+    pub next_digest_at: DateTime<Utc>,
+}
+
+/// A notification routed only to the digest, waiting for the next digest email
+#[derive(Debug, Clone, FromRow)]
+pub struct NotificationDigestItem {
+    pub id: Uuid,
+    pub title: Option<String>,
+    pub message: String,
+    pub action_url: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
-pub struct CreateNotificationRequest {
+pub struct UnsubscribeDigestRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateNotificationPreferencesRequest {
+    pub webhook_url: Option<String>,
+    /// Replaces every existing rule
+    pub rules: Vec<NotificationPreference>,
+    /// Left unchanged when absent
+    pub digest: Option<DigestSettings>,
+}
+
+/// Accounts a broadcast goes to; unset fields match everyone
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BroadcastSegment {
+    /// `email` or `google`
+    pub auth_type: Option<String>,
+    pub signed_up_after: Option<DateTime<Utc>>,
+    pub signed_up_before: Option<DateTime<Utc>>,
+    /// `admin` or `user`, by the admin allowlist
+    pub role: Option<String>,
+}
+
+/// A notification sent by an admin to every account in a segment
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct NotificationBroadcast {
+    pub id: Uuid,
+    pub created_by: Uuid,
+    pub kind: String,
     pub level: String,
+    pub title: Option<String>,
     pub message: String,
+    pub action_url: Option<String>,
+    pub actions: serde_json::Value,
+    pub segment: serde_json::Value,
+    /// `pending`, `running`, `completed`, `retracting` or `retracted`
+    pub status: String,
+    #[serde(skip_serializing)]
+    pub cursor: Option<Uuid>,
+    pub total_recipients: i32,
+    pub delivered_count: i32,
+    pub retracted_count: i32,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub retracted_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Deserialize)]
-pub struct UpdateNotificationRequest {
+pub struct CreateBroadcastRequest {
+    #[serde(default = "default_broadcast_kind")]
+    pub kind: String,
+    /// Defaults to the level registered for the kind
+    pub level: Option<String>,
+    pub title: Option<String>,
+    pub message: String,
+    pub action_url: Option<String>,
+    #[serde(default)]
+    pub actions: Vec<NotificationAction>,
+    #[serde(default)]
+    pub segment: BroadcastSegment,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn default_broadcast_kind() -> String {
+    "announcement".to_string()
+}
+
+/// An account with at least one open realtime connection
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct OnlineAccount {
+    /// nano-iam account ID
+    pub account_id: Uuid,
+    pub connections: i64,
+    pub online_since: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotificationsQuery {
+    pub limit: Option<i64>,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    pub kind: Option<String>,
+    pub level: Option<String>,
+    pub read: Option<bool>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// List archived notifications instead of the inbox
+    pub archived: Option<bool>,
+    /// List one entry per group, its latest notification, with the group's size
+    pub grouped: Option<bool>,
+    /// Only list the notifications in one group
+    pub group_key: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotificationPage {
+    pub notifications: Vec<Notification>,
+    /// Cursor for the next page, absent on the last page
+    pub next_cursor: Option<String>,
+    /// Notifications matching the filters, or groups when listing with `grouped=true`
+    pub total: i64,
+    /// Unread notifications matching the filters other than `read`
+    pub unread: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateNotificationRequest {
+    #[serde(default = "default_notification_kind")]
+    pub kind: String,
+    /// Defaults to the level registered for the kind
+    pub level: Option<String>,
+    pub title: Option<String>,
+    pub message: String,
+    #[serde(default)]
+    pub data: Option<serde_json::Value>,
+    pub action_url: Option<String>,
+    #[serde(default)]
+    pub actions: Vec<NotificationAction>,
+    pub source: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub group_key: Option<String>,
+    /// Seconds within which a repeat with the same `group_key` is collapsed
+    /// into the previous notification instead of creating a new one
+    pub dedupe_window: Option<i64>,
+}
+
+fn default_notification_kind() -> String {
+    "general".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchUpdateNotificationsRequest {
+    pub notification_ids: Vec<Uuid>,
     pub read: bool,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct BatchDeleteNotificationsRequest {
+    pub notification_ids: Vec<Uuid>,
+}
+
+/// Notifications a bulk operation applies to. `all` must be set when no other
+/// field is, so an empty filter can't touch everything by accident.
+#[derive(Debug, Default, Deserialize)]
+pub struct NotificationFilter {
+    #[serde(default)]
+    pub all: bool,
+    pub kind: Option<String>,
+    pub level: Option<String>,
+    pub read: Option<bool>,
+    /// Only notifications created before this
+    pub before: Option<DateTime<Utc>>,
+    /// Act on archived notifications instead of the inbox
+    pub archived: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkUpdateNotificationsRequest {
+    pub filter: NotificationFilter,
+    pub read: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkDeleteNotificationsRequest {
+    pub filter: NotificationFilter,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateNotificationRequest {
+    pub read: Option<bool>,
+    pub archived: Option<bool>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct AccountSettings {
     pub username: Option<String>,
@@ -111,3 +432,122 @@ pub struct AccountSettings {
 pub struct UpdateAccountSettingsRequest {
     pub username: Option<String>,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AuditEvent {
+    pub id: Uuid,
+    pub actor_account_id: Option<Uuid>,
+    pub target_account_id: Option<Uuid>,
+    pub event_type: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub metadata: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SecurityLogQuery {
+    pub limit: Option<i64>,
+    pub before: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditEventsQuery {
+    pub account_id: Option<Uuid>,
+    pub event_type: Option<String>,
+    pub ip_address: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DataExport {
+    pub id: Uuid,
+    pub account_id: Uuid,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct InviteCode {
+    pub id: Uuid,
+    pub code: String,
+    pub created_by: Option<Uuid>,
+    pub email: Option<String>,
+    pub max_uses: i32,
+    pub use_count: i32,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInviteRequest {
+    pub max_uses: Option<i32>,
+    pub expires_in_days: Option<i64>,
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct WaitlistEntry {
+    pub id: Uuid,
+    pub email: String,
+    pub status: String,
+    pub invite_code_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub approved_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WaitlistQuery {
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApproveWaitlistRequest {
+    pub entry_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChallengeQuery {
+    pub action: String,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct KnownDevice {
+    pub id: Uuid,
+    pub account_id: Uuid,
+    pub description: String,
+    pub ip_prefix: Option<String>,
+    pub location: Option<String>,
+    pub first_seen_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Session {
+    pub id: Uuid,
+    pub account_id: Uuid,
+    pub device_id: Option<Uuid>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub location: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub elevated_until: Option<DateTime<Utc>>,
+    /// Whether the long "remember me" timeouts apply
+    pub remember_me: bool,
+    /// Absolute end of the session, regardless of token rotation
+    pub expires_at: Option<DateTime<Utc>>,
+    /// When the session's current access token expires
+    pub access_token_expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportLoginRequest {
+    pub token: String,
+}
@@ -0,0 +1,282 @@
+use actix_web::web::Bytes;
+use futures_core::Stream;
+use sqlx::postgres::PgListener;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+use crate::dba::DbContext;
+use crate::models::NotificationEvent;
+
+/// Postgres channel announcing new notification events to all replicas
+pub const NOTIFICATION_EVENTS_CHANNEL: &str = "notification_events";
+
+/// Maximum events fetched per query when replaying missed events
+const REPLAY_BATCH_SIZE: i64 = 500;
+
+/// Event IDs are assigned before their transactions commit, so an event can
+/// become visible after one with a higher ID. Events written this many seconds
+/// around the last delivered one are checked again when replaying.
+const REPLAY_LOOKBACK_SECS: i64 = 30;
+
+/// Events buffered per replica before slow streams fall back to replaying
+const BROADCAST_CAPACITY: usize = 1024;
+
+/// Notification stream configuration
+#[derive(Clone)]
+pub struct NotificationStreamConfig {
+    /// How often an idle stream sends a comment to keep proxies from closing it
+    pub heartbeat: Duration,
+    /// How long events are kept for clients resuming with `Last-Event-ID`
+    pub event_retention: chrono::Duration,
+}
+
+impl NotificationStreamConfig {
+    /// Create notification stream configuration from environment or defaults
+    pub fn from_env() -> Self {
+        let heartbeat_secs = env::var("NOTIFICATION_STREAM_HEARTBEAT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(25);
+        let retention_hours = env::var("NOTIFICATION_EVENTS_RETENTION_HOURS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(24);
+        Self {
+            heartbeat: Duration::from_secs(heartbeat_secs),
+            event_retention: chrono::Duration::hours(retention_hours),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum HubMessage {
+    Event(Arc<NotificationEvent>),
+    /// Announcements may have been missed; every stream should replay from the database
+    Resync,
+}
+
+/// Fans notification events out to the streams open on this replica
+pub struct NotificationHub {
+    db: DbContext,
+    config: NotificationStreamConfig,
+    sender: broadcast::Sender<HubMessage>,
+}
+
+impl NotificationHub {
+    pub fn new(db: DbContext, config: NotificationStreamConfig) -> Self {
+        let (sender, _) = broadcast::channel(BROADCAST_CAPACITY);
+        Self { db, config, sender }
+    }
+
+    /// Receive every notification event announced to this replica
+    pub fn subscribe(&self) -> broadcast::Receiver<HubMessage> {
+        self.sender.subscribe()
+    }
+
+    /// Stream an account's notification events as Server-Sent Events.
+    ///
+    /// Without `last_event_id` the stream opens with an `unread_count` snapshot;
+    /// with it, events the client missed are replayed first.
+    pub fn stream(
+        self: Arc<Self>,
+        account_id: Uuid,
+        last_event_id: Option<i64>,
+    ) -> impl Stream<Item = Result<Bytes, Infallible>> {
+        // Subscribe before reading the database so nothing falls in between
+        let mut receiver = self.subscribe();
+
+        async_stream::stream! {
+            let mut last_id = match last_event_id {
+                Some(id) => id,
+                None => match self.snapshot(account_id).await {
+                    Ok((id, chunk)) => {
+                        yield Ok(chunk);
+                        id
+                    }
+                    Err(e) => {
+                        log::error!("Failed to open notification stream: {:?}", e);
+                        return;
+                    }
+                },
+            };
+            let mut replay = last_event_id.is_some();
+            // Recently delivered events, so ones seen again through the lookback are skipped
+            let mut delivered: HashMap<i64, chrono::DateTime<chrono::Utc>> = HashMap::new();
+            let lookback = chrono::Duration::seconds(REPLAY_LOOKBACK_SECS);
+
+            let mut heartbeat = tokio::time::interval(self.config.heartbeat);
+            heartbeat.tick().await;
+
+            loop {
+                if replay {
+                    replay = false;
+                    let late = match self
+                        .db
+                        .get_notification_events_near(
+                            account_id,
+                            last_id,
+                            REPLAY_LOOKBACK_SECS as f64,
+                            REPLAY_BATCH_SIZE,
+                        )
+                        .await
+                    {
+                        Ok(events) => events,
+                        Err(e) => {
+                            log::error!("Failed to replay notification events: {:?}", e);
+                            return;
+                        }
+                    };
+                    for event in late {
+                        if delivered.insert(event.id, event.created_at).is_none() {
+                            yield Ok(format_event(&event, last_id));
+                        }
+                    }
+                    loop {
+                        let events = match self
+                            .db
+                            .get_notification_events_after(account_id, last_id, REPLAY_BATCH_SIZE)
+                            .await
+                        {
+                            Ok(events) => events,
+                            Err(e) => {
+                                log::error!("Failed to replay notification events: {:?}", e);
+                                return;
+                            }
+                        };
+                        let done = (events.len() as i64) < REPLAY_BATCH_SIZE;
+                        for event in events {
+                            last_id = event.id;
+                            delivered.insert(event.id, event.created_at);
+                            yield Ok(format_event(&event, last_id));
+                        }
+                        if done {
+                            break;
+                        }
+                    }
+                    let cutoff = chrono::Utc::now() - lookback * 2;
+                    delivered.retain(|_, created_at| *created_at > cutoff);
+                }
+
+                tokio::select! {
+                    _ = heartbeat.tick() => yield Ok(Bytes::from_static(b": heartbeat\n\n")),
+                    received = receiver.recv() => match received {
+                        Ok(HubMessage::Event(event))
+                            if event.account_id == account_id
+                                && !delivered.contains_key(&event.id)
+                                && (event.id > last_id
+                                    || event.created_at > chrono::Utc::now() - lookback) =>
+                        {
+                            last_id = last_id.max(event.id);
+                            delivered.insert(event.id, event.created_at);
+                            let cutoff = chrono::Utc::now() - lookback * 2;
+                            delivered.retain(|_, created_at| *created_at > cutoff);
+                            yield Ok(format_event(&event, last_id));
+                        }
+                        Ok(HubMessage::Event(_)) => {}
+                        // This stream fell behind; catch up from the database
+                        Ok(HubMessage::Resync) | Err(RecvError::Lagged(_)) => replay = true,
+                        Err(RecvError::Closed) => return,
+                    },
+                }
+            }
+        }
+    }
+
+    /// Current unread count, tagged with the latest event ID so it can be resumed from
+    async fn snapshot(&self, account_id: Uuid) -> Result<(i64, Bytes), sqlx::Error> {
+        let last_id = self.db.get_latest_notification_event_id(account_id).await?;
+        let unread_count = self.db.get_unread_count(account_id).await?;
+        let chunk = format!(
+            "id: {}\nevent: unread_count\ndata: {}\n\n",
+            last_id,
+            serde_json::json!({ "unread_count": unread_count })
+        );
+        Ok((last_id, Bytes::from(chunk)))
+    }
+}
+
+/// Format an event for the stream. Its `id` is the highest ID delivered so far
+/// rather than the event's own, so a client's `Last-Event-ID` never goes back.
+fn format_event(event: &NotificationEvent, last_id: i64) -> Bytes {
+    Bytes::from(format!(
+        "id: {}\nevent: {}\ndata: {}\n\n",
+        last_id, event.event_type, event.data
+    ))
+}
+
+/// Forward events announced by any replica to the streams open on this one
+pub fn spawn_listener(hub: Arc<NotificationHub>) {
+    tokio::spawn(async move {
+        loop {
+            let mut listener = match PgListener::connect_with(hub.db.pool()).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    log::error!("Failed to connect notification event listener: {:?}", e);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    continue;
+                }
+            };
+            if let Err(e) = listener.listen(NOTIFICATION_EVENTS_CHANNEL).await {
+                log::error!("Failed to listen for notification events: {:?}", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+
+            // Anything announced while we were not listening was missed
+            let _ = hub.sender.send(HubMessage::Resync);
+
+            loop {
+                let notification = match listener.try_recv().await {
+                    Ok(Some(notification)) => notification,
+                    // Connection lost; announcements may have been missed
+                    Ok(None) => {
+                        let _ = hub.sender.send(HubMessage::Resync);
+                        continue;
+                    }
+                    Err(e) => {
+                        log::error!("Notification event listener error: {:?}", e);
+                        break;
+                    }
+                };
+
+                // Nobody on this replica is listening
+                if hub.sender.receiver_count() == 0 {
+                    continue;
+                }
+
+                let payload = serde_json::from_str::<serde_json::Value>(notification.payload()).ok();
+                let event_id = payload.and_then(|value| value.get("id").and_then(|id| id.as_i64()));
+                let Some(event_id) = event_id else {
+                    log::warn!(
+                        "Ignoring malformed notification event: {}",
+                        notification.payload()
+                    );
+                    continue;
+                };
+
+                match hub.db.get_notification_event(event_id).await {
+                    Ok(Some(event)) => {
+                        let _ = hub.sender.send(HubMessage::Event(Arc::new(event)));
+                    }
+                    Ok(None) => {}
+                    Err(e) => log::error!("Failed to load notification event: {:?}", e),
+                }
+            }
+        }
+    });
+}
+
+/// Remove events older than the resume window
+pub async fn clear_old_events(db: &DbContext, config: &NotificationStreamConfig) {
+    let cutoff = chrono::Utc::now() - config.event_retention;
+    match db.delete_notification_events_before(cutoff).await {
+        Ok(0) => {}
+        Ok(count) => log::info!("Cleared {} old notification event(s)", count),
+        Err(e) => log::error!("Failed to clear old notification events: {:?}", e),
+    }
+}
@@ -0,0 +1,482 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use uuid::Uuid;
+
+use crate::models::{Notification, NotificationAction};
+
+/// Notification levels accepted by the API
+pub const LEVELS: &[&str] = &["info", "warning", "error"];
+
+/// Longest title accepted
+const MAX_TITLE_LENGTH: usize = 200;
+
+/// Largest `data` payload accepted, in bytes of JSON
+const MAX_DATA_SIZE: usize = 16 * 1024;
+
+/// Most action buttons a notification may carry
+const MAX_ACTIONS: usize = 3;
+
+const MAX_ACTION_LABEL_LENGTH: usize = 40;
+
+const MAX_URL_LENGTH: usize = 2048;
+
+const MAX_SOURCE_LENGTH: usize = 100;
+
+const MAX_GROUP_KEY_LENGTH: usize = 200;
+
+/// Longest window repeats may be collapsed over
+const MAX_DEDUPE_WINDOW_DAYS: i64 = 30;
+
+/// Type of a field in a notification's `data`
+#[derive(Debug, Clone, Copy)]
+pub enum FieldType {
+    String,
+    Uuid,
+    /// RFC 3339 timestamp
+    Timestamp,
+}
+
+/// A field of a notification kind's `data`
+#[derive(Debug)]
+pub struct Field {
+    pub name: &'static str,
+    pub field_type: FieldType,
+    pub required: bool,
+}
+
+const fn required(name: &'static str, field_type: FieldType) -> Field {
+    Field {
+        name,
+        field_type,
+        required: true,
+    }
+}
+
+const fn optional(name: &'static str, field_type: FieldType) -> Field {
+    Field {
+        name,
+        field_type,
+        required: false,
+    }
+}
+
+/// Where a notification can be delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    /// The notifications list
+    InApp,
+    /// An email sent right away
+    Email,
+    /// The next digest email
+    Digest,
+    /// The account's webhook
+    Webhook,
+}
+
+impl Channel {
+    pub const ALL: &'static [Channel] = &[Channel::InApp, Channel::Email, Channel::Digest, Channel::Webhook];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Channel::InApp => "in_app",
+            Channel::Email => "email",
+            Channel::Digest => "digest",
+            Channel::Webhook => "webhook",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Channel::ALL.iter().copied().find(|channel| channel.as_str() == s)
+    }
+}
+
+/// A registered notification kind and the schema of its `data`
+#[derive(Debug)]
+pub struct Kind {
+    pub name: &'static str,
+    /// Level used when the producer doesn't pick one
+    pub default_level: &'static str,
+    /// Whether clients may create it through the API, rather than only the server
+    pub public: bool,
+    /// Channels used unless the account has a rule for the kind or its level
+    pub default_channels: &'static [Channel],
+    /// Channels always used, whatever the account's rules say
+    pub mandatory_channels: &'static [Channel],
+    /// Every key allowed in `data`; anything else is rejected
+    pub fields: &'static [Field],
+}
+
+/// Every notification kind producers may emit
+pub const KINDS: &[Kind] = &[
+    Kind {
+        name: "general",
+        default_level: "info",
+        public: true,
+        default_channels: &[Channel::InApp],
+        mandatory_channels: &[],
+        fields: &[],
+    },
+    Kind {
+        name: "security.login",
+        default_level: "info",
+        public: false,
+        default_channels: &[Channel::InApp],
+        mandatory_channels: &[Channel::InApp],
+        fields: &[
+            required("device", FieldType::String),
+            optional("location", FieldType::String),
+        ],
+    },
+    Kind {
+        name: "security.new_device",
+        default_level: "warning",
+        public: false,
+        default_channels: &[Channel::InApp],
+        mandatory_channels: &[Channel::InApp],
+        fields: &[
+            required("device", FieldType::String),
+            optional("location", FieldType::String),
+            optional("ip_address", FieldType::String),
+        ],
+    },
+    Kind {
+        name: "export.ready",
+        default_level: "info",
+        public: false,
+        default_channels: &[Channel::InApp, Channel::Email],
+        mandatory_channels: &[],
+        fields: &[
+            required("export_id", FieldType::Uuid),
+            required("expires_at", FieldType::Timestamp),
+        ],
+    },
+    // Sent by admins to many accounts at once, see `broadcasts`
+    Kind {
+        name: "announcement",
+        default_level: "info",
+        public: false,
+        default_channels: &[Channel::InApp],
+        mandatory_channels: &[],
+        fields: &[],
+    },
+    // Only delivered to accounts that opted in with a rule
+    Kind {
+        name: "marketing.product_update",
+        default_level: "info",
+        public: false,
+        default_channels: &[],
+        mandatory_channels: &[],
+        fields: &[],
+    },
+];
+
+pub fn kind(name: &str) -> Option<&'static Kind> {
+    KINDS.iter().find(|kind| kind.name == name)
+}
+
+/// A notification about to be created
+#[derive(Debug, Clone)]
+pub struct NewNotification {
+    pub kind: String,
+    pub level: Option<String>,
+    pub title: Option<String>,
+    pub message: String,
+    pub data: serde_json::Value,
+    pub action_url: Option<String>,
+    pub actions: Vec<NotificationAction>,
+    pub source: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub group_key: Option<String>,
+    /// Repeats with the same group key within this window of the group's first
+    /// notification collapse into it; later ones start a new notification
+    pub dedupe_window: Option<Duration>,
+}
+
+impl NewNotification {
+    pub fn new(kind: &str, message: impl Into<String>) -> Self {
+        Self {
+            kind: kind.to_string(),
+            level: None,
+            title: None,
+            message: message.into(),
+            data: serde_json::json!({}),
+            action_url: None,
+            actions: Vec::new(),
+            source: None,
+            expires_at: None,
+            group_key: None,
+            dedupe_window: None,
+        }
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn with_data(mut self, data: serde_json::Value) -> Self {
+        self.data = data;
+        self
+    }
+
+    pub fn with_action(mut self, label: impl Into<String>, url: impl Into<String>) -> Self {
+        self.actions.push(NotificationAction {
+            label: label.into(),
+            url: url.into(),
+        });
+        self
+    }
+
+    pub fn with_source(mut self, source: &str) -> Self {
+        self.source = Some(source.to_string());
+        self
+    }
+
+    pub fn with_expiry(mut self, expires_at: DateTime<Utc>) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    pub fn with_group(mut self, group_key: impl Into<String>, dedupe_window: Duration) -> Self {
+        self.group_key = Some(group_key.into());
+        self.dedupe_window = Some(dedupe_window);
+        self
+    }
+
+    /// Level the notification is stored with
+    pub fn level(&self) -> &str {
+        match &self.level {
+            Some(level) => level,
+            None => kind(&self.kind).map_or("info", |kind| kind.default_level),
+        }
+    }
+
+    /// Check the notification against its kind's schema
+    pub fn validate(&self) -> Result<&'static Kind, String> {
+        let kind = kind(&self.kind).ok_or_else(|| format!("Unknown kind '{}'", self.kind))?;
+        if !is_valid_level(self.level()) {
+            return Err("Invalid level. Must be 'info', 'warning', or 'error'".to_string());
+        }
+        if self.title.as_ref().is_some_and(|t| t.chars().count() > MAX_TITLE_LENGTH) {
+            return Err(format!("Title must be at most {} characters", MAX_TITLE_LENGTH));
+        }
+        validate_data(kind, &self.data)?;
+        if let Some(url) = &self.action_url {
+            validate_url(url)?;
+        }
+        if self.actions.len() > MAX_ACTIONS {
+            return Err(format!("At most {} actions are allowed", MAX_ACTIONS));
+        }
+        for action in &self.actions {
+            let length = action.label.trim().chars().count();
+            if length == 0 || length > MAX_ACTION_LABEL_LENGTH {
+                return Err(format!(
+                    "Action labels must be 1 to {} characters",
+                    MAX_ACTION_LABEL_LENGTH
+                ));
+            }
+            validate_url(&action.url)?;
+        }
+        if let Some(source) = &self.source {
+            let valid = !source.is_empty()
+                && source.len() <= MAX_SOURCE_LENGTH
+                && source
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "._-".contains(c));
+            if !valid {
+                return Err("Invalid source".to_string());
+            }
+        }
+        if self.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+            return Err("expires_at must be in the future".to_string());
+        }
+        if let Some(group_key) = &self.group_key {
+            let valid = !group_key.is_empty()
+                && group_key.len() <= MAX_GROUP_KEY_LENGTH
+                && group_key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "._:-".contains(c));
+            if !valid {
+                return Err("Invalid group_key".to_string());
+            }
+        }
+        if let Some(window) = self.dedupe_window {
+            if self.group_key.is_none() {
+                return Err("dedupe_window requires a group_key".to_string());
+            }
+            if window <= Duration::zero() || window > Duration::days(MAX_DEDUPE_WINDOW_DAYS) {
+                return Err(format!(
+                    "dedupe_window must be between 1 second and {} days",
+                    MAX_DEDUPE_WINDOW_DAYS
+                ));
+            }
+        }
+        Ok(kind)
+    }
+}
+
+#[derive(Debug)]
+pub enum CreateError {
+    Invalid(String),
+    Database(sqlx::Error),
+}
+
+fn validate_data(kind: &Kind, data: &serde_json::Value) -> Result<(), String> {
+    let object = data
+        .as_object()
+        .ok_or_else(|| "Data must be a JSON object".to_string())?;
+    if data.to_string().len() > MAX_DATA_SIZE {
+        return Err(format!("Data must be at most {} bytes", MAX_DATA_SIZE));
+    }
+
+    for key in object.keys() {
+        if !kind.fields.iter().any(|field| field.name == key) {
+            return Err(format!("Unexpected field '{}' for kind '{}'", key, kind.name));
+        }
+    }
+    for field in kind.fields {
+        let value = match object.get(field.name) {
+            Some(serde_json::Value::Null) | None if field.required => {
+                return Err(format!("Missing field '{}' for kind '{}'", field.name, kind.name));
+            }
+            Some(serde_json::Value::Null) | None => continue,
+            Some(value) => value,
+        };
+        let valid = match field.field_type {
+            FieldType::String => value.is_string(),
+            FieldType::Uuid => value.as_str().is_some_and(|v| Uuid::parse_str(v).is_ok()),
+            FieldType::Timestamp => value
+                .as_str()
+                .is_some_and(|v| DateTime::parse_from_rfc3339(v).is_ok()),
+        };
+        if !valid {
+            return Err(format!(
+                "Field '{}' for kind '{}' must be a {:?}",
+                field.name, kind.name, field.field_type
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Only web links and paths within the app, so links can't run script
+fn validate_url(url: &str) -> Result<(), String> {
+    let valid = url.len() <= MAX_URL_LENGTH
+        && (url.starts_with("https://")
+            || url.starts_with("http://")
+            || (url.starts_with('/') && !url.starts_with("//")))
+        && !url.chars().any(|c| c.is_whitespace() || c.is_control());
+    if valid {
+        Ok(())
+    } else {
+        Err("Action URLs must be http(s) links or paths starting with '/'".to_string())
+    }
+}
+
+/// Position in the notifications list, ordered by `(occurred_at, id)` most recent
+/// first, where a notification last occurred when its latest repeat was collapsed
+/// into it
+#[derive(Debug, Clone, Copy)]
+pub struct NotificationCursor {
+    pub occurred_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl NotificationCursor {
+    /// Cursor pointing just past a notification
+    pub fn after(notification: &Notification) -> Self {
+        Self {
+            occurred_at: notification.last_occurred_at.unwrap_or(notification.created_at),
+            id: notification.id,
+        }
+    }
+
+    /// Opaque string form handed to clients as `next_cursor`
+    pub fn encode(&self) -> String {
+        format!("{}_{}", self.occurred_at.timestamp_micros(), self.id.simple())
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let (micros, id) = cursor.split_once('_')?;
+        Some(Self {
+            occurred_at: Utc.timestamp_micros(micros.parse().ok()?).single()?,
+            id: Uuid::parse_str(id).ok()?,
+        })
+    }
+}
+
+pub fn is_valid_level(level: &str) -> bool {
+    LEVELS.contains(&level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_round_trips() {
+        let cursor = NotificationCursor {
+            occurred_at: Utc.timestamp_micros(1_700_000_000_123_456).unwrap(),
+            id: Uuid::new_v4(),
+        };
+        let decoded = NotificationCursor::decode(&cursor.encode()).unwrap();
+        assert_eq!(decoded.occurred_at, cursor.occurred_at);
+        assert_eq!(decoded.id, cursor.id);
+    }
+
+    #[test]
+    fn cursor_follows_latest_occurrence() {
+        let created_at = Utc.timestamp_micros(1_700_000_000_000_000).unwrap();
+        let mut notification = Notification {
+            id: Uuid::new_v4(),
+            account_id: Uuid::new_v4(),
+            kind: "general".to_string(),
+            level: "info".to_string(),
+            title: None,
+            message: "Hello".to_string(),
+            data: serde_json::json!({}),
+            action_url: None,
+            actions: serde_json::json!([]),
+            source: None,
+            group_key: None,
+            occurrences: 1,
+            last_occurred_at: None,
+            read: false,
+            expires_at: None,
+            archived_at: None,
+            created_at,
+            updated_at: created_at,
+            group_size: None,
+        };
+        assert_eq!(NotificationCursor::after(&notification).occurred_at, created_at);
+
+        let repeated_at = created_at + Duration::minutes(5);
+        notification.occurrences = 2;
+        notification.last_occurred_at = Some(repeated_at);
+        assert_eq!(NotificationCursor::after(&notification).occurred_at, repeated_at);
+    }
+
+    #[test]
+    fn cursor_keeps_microseconds_only() {
+        let occurred_at = Utc.timestamp_nanos(1_700_000_000_123_456_789);
+        let cursor = NotificationCursor { occurred_at, id: Uuid::nil() };
+        let decoded = NotificationCursor::decode(&cursor.encode()).unwrap();
+        assert_eq!(decoded.occurred_at.timestamp_micros(), occurred_at.timestamp_micros());
+    }
+
+    #[test]
+    fn rejects_malformed_cursors() {
+        let id = Uuid::new_v4().simple().to_string();
+        for cursor in [
+            String::new(),
+            "1700000000123456".to_string(),
+            format!("1700000000123456-{}", id),
+            format!("abc_{}", id),
+            format!("_{}", id),
+            "1700000000123456_".to_string(),
+            "1700000000123456_not-a-uuid".to_string(),
+            format!("{}_{}", i64::MAX, id),
+        ] {
+            assert!(NotificationCursor::decode(&cursor).is_none(), "accepted {:?}", cursor);
+        }
+    }
+}
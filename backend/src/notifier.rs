@@ -0,0 +1,362 @@
+use hmac::{Hmac, Mac};
+use nano_iam::AuthService;
+use rand::RngCore;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::Url;
+use sha2::Sha256;
+use std::collections::HashSet;
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::dba::DbContext;
+use crate::mailer::{self, Mailer};
+use crate::models::{Account, Notification, NotificationPreference};
+use crate::notifications::{self, Channel, CreateError, Kind, NewNotification};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Most routing rules an account may have
+const MAX_RULES: usize = 100;
+
+const MAX_WEBHOOK_URL_LENGTH: usize = 2048;
+
+/// Header carrying the hex HMAC-SHA256 of the webhook body, keyed with the account's secret
+const WEBHOOK_SIGNATURE_HEADER: &str = "X-Notification-Signature";
+
+/// Notification delivery configuration
+#[derive(Clone)]
+pub struct NotifierConfig {
+    /// How long a webhook endpoint has to answer
+    pub webhook_timeout: Duration,
+}
+
+impl NotifierConfig {
+    /// Create notification delivery configuration from environment or defaults
+    pub fn from_env() -> Self {
+        let webhook_timeout_secs = env::var("NOTIFICATION_WEBHOOK_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(10);
+        Self {
+            webhook_timeout: Duration::from_secs(webhook_timeout_secs),
+        }
+    }
+}
+
+/// Routes notifications to the channels each account has chosen
+pub struct Notifier {
+    db: DbContext,
+    auth_service: Arc<AuthService>,
+    mailer: Arc<dyn Mailer>,
+    http: reqwest::Client,
+}
+
+impl Notifier {
+    pub fn new(
+        db: DbContext,
+        auth_service: Arc<AuthService>,
+        mailer: Arc<dyn Mailer>,
+        config: &NotifierConfig,
+    ) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(config.webhook_timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .dns_resolver(Arc::new(PublicOnlyResolver))
+            .no_proxy()
+            .build()
+            .expect("Failed to build webhook HTTP client");
+        Self {
+            db,
+            auth_service,
+            mailer,
+            http,
+        }
+    }
+
+    /// Validate a notification and deliver it to an account.
+    ///
+    /// Returns the stored notification, or `None` when the account doesn't
+    /// receive this kind in-app. Emails and webhooks are sent in the background.
+    pub async fn notify(
+        &self,
+        account: &Account,
+        notification: &NewNotification,
+    ) -> Result<Option<Notification>, CreateError> {
+        let kind = notification.validate().map_err(CreateError::Invalid)?;
+        let rules = self
+            .db
+            .get_notification_preferences(account.id)
+            .await
+            .map_err(CreateError::Database)?;
+
+        let channels = resolve_channels(kind, notification.level(), &rules);
+        let mut stored = None;
+        for &channel in &channels {
+            match channel {
+                Channel::InApp => {
+                    stored = Some(
+                        self.db
+                            .create_notification(account.id, notification)
+                            .await
+                            .map_err(CreateError::Database)?,
+                    );
+                }
+                // Unread in-app notifications are part of the digest anyway
+                Channel::Digest if channels.contains(&Channel::InApp) => {}
+                Channel::Digest => {
+                    if let Err(e) = self.db.add_notification_digest_item(account.id, notification).await {
+                        log::error!("Failed to queue notification for digest: {:?}", e);
+                    }
+                }
+                Channel::Email => {
+                    tokio::spawn(send_email(
+                        self.auth_service.clone(),
+                        self.mailer.clone(),
+                        account.iam_account_id,
+                        notification.clone(),
+                    ));
+                }
+                Channel::Webhook => {
+                    tokio::spawn(send_webhook(
+                        self.db.clone(),
+                        self.http.clone(),
+                        account.id,
+                        notification.clone(),
+                    ));
+                }
+            }
+        }
+        Ok(stored)
+    }
+}
+
+/// Channels a notification goes to: the account's rule for its kind, else for
+/// its level, else the kind's defaults, plus the kind's mandatory channels
+pub fn resolve_channels(kind: &Kind, level: &str, rules: &[NotificationPreference]) -> Vec<Channel> {
+    let chosen: Vec<Channel> = rules
+        .iter()
+        .find(|rule| rule.kind.as_deref() == Some(kind.name))
+        .or_else(|| rules.iter().find(|rule| rule.level.as_deref() == Some(level)))
+        .map(|rule| rule.channels.iter().filter_map(|c| Channel::parse(c)).collect())
+        .unwrap_or_else(|| kind.default_channels.to_vec());
+
+    Channel::ALL
+        .iter()
+        .copied()
+        .filter(|channel| chosen.contains(channel) || kind.mandatory_channels.contains(channel))
+        .collect()
+}
+
+/// Check routing rules submitted by an account
+pub fn validate_rules(rules: &[NotificationPreference], has_webhook: bool) -> Result<(), String> {
+    if rules.len() > MAX_RULES {
+        return Err(format!("At most {} rules are allowed", MAX_RULES));
+    }
+
+    let mut seen = HashSet::new();
+    for rule in rules {
+        let key = match (&rule.kind, &rule.level) {
+            (Some(kind), None) => {
+                if notifications::kind(kind).is_none() {
+                    return Err(format!("Unknown kind '{}'", kind));
+                }
+                format!("kind:{}", kind)
+            }
+            (None, Some(level)) => {
+                if !notifications::is_valid_level(level) {
+                    return Err("Invalid level. Must be 'info', 'warning', or 'error'".to_string());
+                }
+                format!("level:{}", level)
+            }
+            _ => return Err("Each rule must set exactly one of 'kind' or 'level'".to_string()),
+        };
+        if !seen.insert(key) {
+            return Err("Each kind and level may only have one rule".to_string());
+        }
+
+        for channel in &rule.channels {
+            match Channel::parse(channel) {
+                Some(Channel::Webhook) if !has_webhook => {
+                    return Err("Set a webhook URL before routing notifications to it".to_string());
+                }
+                Some(_) => {}
+                None => return Err(format!("Unknown channel '{}'", channel)),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Only public HTTPS endpoints, so webhooks can't be pointed at internal services
+pub fn validate_webhook_url(url: &str) -> Result<(), String> {
+    let invalid = || "Webhook URL must be a public https:// URL".to_string();
+    if url.len() > MAX_WEBHOOK_URL_LENGTH {
+        return Err(invalid());
+    }
+    let parsed = Url::parse(url).map_err(|_| invalid())?;
+    if parsed.scheme() != "https" || !parsed.username().is_empty() || parsed.password().is_some() {
+        return Err(invalid());
+    }
+
+    let host = parsed.host_str().ok_or_else(invalid)?;
+    let public = match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) => is_public_ip(ip),
+        Err(_) => {
+            let domain = host.trim_end_matches('.').to_ascii_lowercase();
+            domain != "localhost" && !domain.ends_with(".localhost") && !domain.ends_with(".internal")
+        }
+    };
+    if public {
+        Ok(())
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Host of a webhook URL, the only part of it safe to log: the path and query
+/// often carry the credentials (e.g. Slack and Discord webhooks)
+pub fn webhook_host(url: &str) -> Option<String> {
+    Url::parse(url).ok()?.host_str().map(str::to_string)
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                // Carrier-grade NAT
+                || (ip.octets()[0] == 100 && (ip.octets()[1] & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || ip.to_ipv4_mapped().is_some_and(|v4| !is_public_ip(IpAddr::V4(v4))))
+        }
+    }
+}
+
+/// Resolves webhook hosts, dropping addresses that aren't public, so a host
+/// that passed validation can't later point deliveries at internal services
+struct PublicOnlyResolver;
+
+impl Resolve for PublicOnlyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Secret for signing webhook deliveries
+pub fn generate_webhook_secret() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Links in emails must be absolute
+fn absolute_url(url: &str) -> String {
+    if url.starts_with('/') {
+        format!("{}{}", mailer::frontend_url(), url)
+    } else {
+        url.to_string()
+    }
+}
+
+async fn send_email(
+    auth_service: Arc<AuthService>,
+    mailer: Arc<dyn Mailer>,
+    iam_account_id: Uuid,
+    notification: NewNotification,
+) {
+    let email = match auth_service.get_account(iam_account_id).await {
+        Ok(iam_account) => iam_account.email,
+        Err(e) => {
+            log::warn!("Failed to get IAM account for notification email: {:?}", e);
+            return;
+        }
+    };
+
+    let mut body = notification.message.clone();
+    if let Some(url) = &notification.action_url {
+        body.push_str(&format!("\n\n{}", absolute_url(url)));
+    }
+    for action in &notification.actions {
+        body.push_str(&format!("\n\n{}: {}", action.label, absolute_url(&action.url)));
+    }
+    let subject = notification.title.as_deref().unwrap_or("New notification");
+
+    if let Err(e) = mailer.send_email(&email, subject, &body).await {
+        log::error!("Failed to send notification email: {:?}", e);
+    }
+}
+
+async fn send_webhook(
+    db: DbContext,
+    http: reqwest::Client,
+    account_id: Uuid,
+    notification: NewNotification,
+) {
+    let settings = match db.get_notification_settings(account_id).await {
+        Ok(Some(settings)) => settings,
+        Ok(None) => return,
+        Err(e) => {
+            log::error!("Failed to get notification settings: {:?}", e);
+            return;
+        }
+    };
+    let (Some(url), Some(secret)) = (settings.webhook_url, settings.webhook_secret) else {
+        return;
+    };
+    // Check again at send time; IP literals never reach the resolver
+    if let Err(e) = validate_webhook_url(&url) {
+        log::warn!("Skipping webhook for account {}: {}", account_id, e);
+        return;
+    }
+
+    let body = serde_json::json!({
+        "kind": notification.kind,
+        "level": notification.level(),
+        "title": notification.title,
+        "message": notification.message,
+        "data": notification.data,
+        "action_url": notification.action_url,
+        "actions": notification.actions,
+        "source": notification.source,
+        "created_at": chrono::Utc::now(),
+    })
+    .to_string();
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    let signature = hex::encode(mac.finalize().into_bytes());
+
+    let result = http
+        .post(&url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(WEBHOOK_SIGNATURE_HEADER, format!("sha256={}", signature))
+        .body(body)
+        .send()
+        .await;
+    match result {
+        Ok(response) if response.status().is_success() => {}
+        Ok(response) => log::warn!("Notification webhook answered {}", response.status()),
+        Err(e) => log::warn!("Failed to deliver notification webhook: {}", e),
+    }
+}
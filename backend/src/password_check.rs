@@ -0,0 +1,445 @@
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::collections::HashSet;
+use std::env;
+use std::path::PathBuf;
+
+/// Length of the SHA-1 hex prefix used to name corpus files
+const PREFIX_LENGTH: usize = 5;
+
+/// Frequently used password fragments that add little entropy
+const COMMON_WORDS: &[&str] = &[
+    "password", "passwort", "qwerty", "letmein", "welcome", "admin", "login", "dragon",
+    "monkey", "master", "iloveyou", "sunshine", "princess", "football", "baseball",
+    "shadow", "secret", "trustno1", "hello", "freedom", "whatever", "superman",
+];
+
+/// Keyboard rows checked for runs such as "qwert" or "asdf"
+const KEYBOARD_ROWS: &[&str] = &["qwertyuiop", "asdfghjkl", "zxcvbnm", "1234567890"];
+
+/// Shortest email fragment treated as personal information, so that short
+/// pieces like "co" or "net" don't flag ordinary words
+const MIN_PERSONAL_FRAGMENT: usize = 4;
+
+/// Default minimum entropy, roughly a random 8 character mixed-case password
+const DEFAULT_MIN_ENTROPY_BITS: f64 = 40.0;
+
+/// Password screening configuration
+#[derive(Clone)]
+pub struct PasswordCheckConfig {
+    /// Directory of k-anonymity range files named by SHA-1 prefix (e.g. `21BD1`),
+    /// each line holding `SUFFIX:COUNT` as published by Have I Been Pwned
+    pub breach_corpus_dir: Option<PathBuf>,
+    /// Minimum occurrences in the corpus for a password to be rejected
+    pub breach_min_count: u64,
+    /// Minimum estimated entropy in bits, 0 to disable
+    pub min_entropy_bits: f64,
+}
+
+impl PasswordCheckConfig {
+    /// Create password screening configuration from environment or defaults
+    pub fn from_env() -> Self {
+        let breach_corpus_dir = env::var("BREACHED_PASSWORDS_DIR")
+            .ok()
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from);
+        if let Some(dir) = &breach_corpus_dir {
+            if !dir.is_dir() {
+                log::error!("BREACHED_PASSWORDS_DIR {} is not a directory", dir.display());
+            }
+        }
+
+        Self {
+            breach_corpus_dir,
+            breach_min_count: env::var("BREACHED_PASSWORDS_MIN_COUNT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1),
+            min_entropy_bits: env::var("PASSWORD_MIN_ENTROPY_BITS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MIN_ENTROPY_BITS),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PasswordStrength {
+    pub entropy_bits: f64,
+    /// 0 (very weak) to 4 (very strong)
+    pub score: u8,
+    pub feedback: Vec<String>,
+}
+
+/// Reason a password was rejected, returned to the client as `error` and `details`
+#[derive(Debug)]
+pub struct PasswordRejection {
+    pub message: &'static str,
+    pub details: serde_json::Value,
+}
+
+/// Screen a new password against the breach corpus and the strength estimator.
+///
+/// `user_inputs` are values such as the email address that make a password
+/// easier to guess when contained in it.
+pub async fn check_password(
+    config: &PasswordCheckConfig,
+    password: &str,
+    user_inputs: &[&str],
+) -> Result<(), PasswordRejection> {
+    if let Some(dir) = &config.breach_corpus_dir {
+        match breach_count(dir, password).await {
+            Ok(count) if count >= config.breach_min_count => {
+                return Err(PasswordRejection {
+                    message: "This password has appeared in a data breach. Please choose a different password.",
+                    details: serde_json::json!({
+                        "reason": "breached",
+                        "feedback": ["Choose a password you have not used on other sites"],
+                    }),
+                });
+            }
+            Ok(_) => {}
+            // Don't block signups because the corpus is unreadable
+            Err(e) => log::error!("Failed to read breached password corpus: {}", e),
+        }
+    }
+
+    if config.min_entropy_bits > 0.0 {
+        let strength = estimate_strength(password, user_inputs);
+        if strength.entropy_bits < config.min_entropy_bits {
+            return Err(PasswordRejection {
+                message: "Password is too weak",
+                details: serde_json::json!({
+                    "reason": "weak",
+                    "entropy_bits": strength.entropy_bits,
+                    "min_entropy_bits": config.min_entropy_bits,
+                    "score": strength.score,
+                    "feedback": strength.feedback,
+                }),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Look up how often a password occurs in the local corpus
+async fn breach_count(dir: &std::path::Path, password: &str) -> std::io::Result<u64> {
+    let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+    let (prefix, suffix) = hash.split_at(PREFIX_LENGTH);
+
+    let contents = match tokio::fs::read_to_string(dir.join(prefix)).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            match tokio::fs::read_to_string(dir.join(format!("{}.txt", prefix))).await {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+                Err(e) => return Err(e),
+            }
+        }
+        Err(e) => return Err(e),
+    };
+
+    for line in contents.lines() {
+        let (line_suffix, count) = line.trim().split_once(':').unwrap_or((line.trim(), "1"));
+        if line_suffix.eq_ignore_ascii_case(suffix) {
+            return Ok(count.trim().parse().unwrap_or(1));
+        }
+    }
+    Ok(0)
+}
+
+/// Estimate password entropy from its character pool, penalising predictable patterns
+pub fn estimate_strength(password: &str, user_inputs: &[&str]) -> PasswordStrength {
+    let chars: Vec<char> = password.chars().collect();
+    let lower = password.to_lowercase();
+    let mut feedback = Vec::new();
+
+    let mut pool = 0u32;
+    if chars.iter().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if chars.iter().any(|c| c.is_ascii_punctuation() || c.is_ascii_whitespace()) {
+        pool += 33;
+    }
+    if chars.iter().any(|c| !c.is_ascii()) {
+        pool += 100;
+    }
+    if pool < 40 {
+        feedback.push("Mix upper and lower case letters, digits and symbols".to_string());
+    }
+
+    // Characters that repeat or continue a sequence contribute little
+    let mut effective_length = 0.0;
+    let mut has_repeats = false;
+    let mut has_sequences = false;
+    for (i, c) in chars.iter().enumerate() {
+        if i > 0 && chars[i - 1] == *c {
+            has_repeats = true;
+            effective_length += 0.25;
+        } else if i > 0 && (*c as i64 - chars[i - 1] as i64).abs() == 1 {
+            has_sequences = true;
+            effective_length += 0.5;
+        } else {
+            effective_length += 1.0;
+        }
+    }
+    if has_repeats {
+        feedback.push("Avoid repeated characters like \"aaa\"".to_string());
+    }
+    if has_sequences {
+        feedback.push("Avoid sequences like \"abc\" or \"123\"".to_string());
+    }
+
+    let mut penalty = 0.0;
+    let keyboard_run = KEYBOARD_ROWS.iter().any(|row| {
+        row.as_bytes()
+            .windows(4)
+            .filter_map(|w| std::str::from_utf8(w).ok())
+            .any(|run| lower.contains(run))
+    });
+    if keyboard_run {
+        penalty += 10.0;
+        feedback.push("Avoid keyboard patterns like \"qwerty\"".to_string());
+    }
+
+    let common: HashSet<&str> = COMMON_WORDS.iter().copied().filter(|w| lower.contains(*w)).collect();
+    if !common.is_empty() {
+        penalty += 8.0 * common.len() as f64 + 10.0;
+        feedback.push("Avoid common words and passwords".to_string());
+    }
+
+    if contains_year(&lower) {
+        penalty += 6.0;
+        feedback.push("Avoid years and dates".to_string());
+    }
+
+    let personal = user_inputs
+        .iter()
+        .flat_map(|input| personal_fragments(input))
+        .any(|part| lower.contains(&part));
+    if personal {
+        penalty += 15.0;
+        feedback.push("Avoid using your name or email address".to_string());
+    }
+
+    if chars.len() < 12 {
+        feedback.push("Add more characters; longer passwords are stronger".to_string());
+    }
+
+    let entropy_bits = (effective_length * (pool.max(1) as f64).log2() - penalty).max(0.0);
+    let entropy_bits = (entropy_bits * 10.0).round() / 10.0;
+    let score = match entropy_bits {
+        b if b < 28.0 => 0,
+        b if b < 36.0 => 1,
+        b if b < 60.0 => 2,
+        b if b < 80.0 => 3,
+        _ => 4,
+    };
+
+    PasswordStrength {
+        entropy_bits,
+        score,
+        feedback,
+    }
+}
+
+/// Split a user input such as an email address into fragments worth checking.
+///
+/// For emails, the domain's last label (the TLD) is dropped, so `user@example.com`
+/// yields `user` and `example` but not `com`. Fragments shorter than
+/// `MIN_PERSONAL_FRAGMENT` are skipped, which also drops suffixes like `co` in `co.uk`.
+fn personal_fragments(input: &str) -> Vec<String> {
+    let input = input.trim().to_lowercase();
+    let (local, domain) = match input.rsplit_once('@') {
+        Some((local, domain)) => (local, domain.rsplit_once('.').map_or(domain, |(d, _)| d)),
+        None => (input.as_str(), ""),
+    };
+
+    local
+        .split(['.', '_', '-', '+'])
+        .chain(domain.split(['.', '-']))
+        .filter(|part| part.chars().count() >= MIN_PERSONAL_FRAGMENT)
+        .map(str::to_string)
+        .collect()
+}
+
+fn contains_year(s: &str) -> bool {
+    s.as_bytes().windows(4).any(|w| {
+        std::str::from_utf8(w)
+            .ok()
+            .and_then(|y| y.parse::<u32>().ok())
+            .is_some_and(|y| (1900..=2099).contains(&y))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRONG_PASSWORD: &str = "Tr0ub4dor&Gl1mmer!Vx";
+
+    fn config(breach_corpus_dir: Option<PathBuf>, min_entropy_bits: f64) -> PasswordCheckConfig {
+        PasswordCheckConfig {
+            breach_corpus_dir,
+            breach_min_count: 1,
+            min_entropy_bits,
+        }
+    }
+
+    /// Write a corpus holding `password` with the given count into a fresh directory
+    fn corpus_with(name: &str, password: &str, count: u64, extension: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("password-check-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        let (prefix, suffix) = hash.split_at(PREFIX_LENGTH);
+        std::fs::write(
+            dir.join(format!("{}{}", prefix, extension)),
+            format!("0000000000000000000000000000000000A:3\n{}:{}\n", suffix, count),
+        )
+        .unwrap();
+        dir
+    }
+
+    fn has_feedback(password: &str, user_inputs: &[&str], text: &str) -> bool {
+        estimate_strength(password, user_inputs)
+            .feedback
+            .iter()
+            .any(|f| f.contains(text))
+    }
+
+    #[tokio::test]
+    async fn rejects_breached_password() {
+        let dir = corpus_with("breached", STRONG_PASSWORD, 42, "");
+        let rejection = check_password(&config(Some(dir), 0.0), STRONG_PASSWORD, &[])
+            .await
+            .unwrap_err();
+        assert_eq!(rejection.details["reason"], "breached");
+    }
+
+    #[tokio::test]
+    async fn reads_corpus_files_with_txt_extension() {
+        let dir = corpus_with("breached-txt", STRONG_PASSWORD, 1, ".txt");
+        let rejection = check_password(&config(Some(dir), 0.0), STRONG_PASSWORD, &[])
+            .await
+            .unwrap_err();
+        assert_eq!(rejection.details["reason"], "breached");
+    }
+
+    #[tokio::test]
+    async fn accepts_breached_password_below_min_count() {
+        let dir = corpus_with("below-min", STRONG_PASSWORD, 2, "");
+        let config = PasswordCheckConfig {
+            breach_min_count: 3,
+            ..config(Some(dir), 0.0)
+        };
+        assert!(check_password(&config, STRONG_PASSWORD, &[]).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn accepts_password_missing_from_corpus() {
+        let dir = corpus_with("missing", "some other password", 10, "");
+        assert!(check_password(&config(Some(dir), 0.0), STRONG_PASSWORD, &[]).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_weak_password() {
+        let rejection = check_password(&config(None, DEFAULT_MIN_ENTROPY_BITS), "password1", &[])
+            .await
+            .unwrap_err();
+        assert_eq!(rejection.details["reason"], "weak");
+        assert_eq!(rejection.details["min_entropy_bits"], DEFAULT_MIN_ENTROPY_BITS);
+    }
+
+    #[tokio::test]
+    async fn accepts_strong_password() {
+        let result = check_password(&config(None, DEFAULT_MIN_ENTROPY_BITS), STRONG_PASSWORD, &[]).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn zero_min_entropy_disables_strength_check() {
+        assert!(check_password(&config(None, 0.0), "password1", &[]).await.is_ok());
+    }
+
+    #[test]
+    fn flags_small_character_pool() {
+        assert!(has_feedback("onlylowercaseletters", &[], "Mix upper and lower case"));
+        assert!(!has_feedback(STRONG_PASSWORD, &[], "Mix upper and lower case"));
+    }
+
+    #[test]
+    fn flags_repeated_characters() {
+        assert!(has_feedback("Xaaaa-Kq9!zP", &[], "repeated characters"));
+    }
+
+    #[test]
+    fn flags_sequences() {
+        assert!(has_feedback("Kq9!abcd-zP", &[], "sequences"));
+    }
+
+    #[test]
+    fn flags_keyboard_runs() {
+        assert!(has_feedback("Kq9!asdf-zP", &[], "keyboard patterns"));
+    }
+
+    #[test]
+    fn flags_common_words() {
+        assert!(has_feedback("Kq9!dragon-zP", &[], "common words"));
+    }
+
+    #[test]
+    fn flags_years() {
+        assert!(has_feedback("Kq9!1987-zP", &[], "years and dates"));
+    }
+
+    #[test]
+    fn flags_short_passwords() {
+        assert!(has_feedback("Kq9!zP", &[], "Add more characters"));
+        assert!(!has_feedback(STRONG_PASSWORD, &[], "Add more characters"));
+    }
+
+    #[test]
+    fn flags_email_local_part_and_domain() {
+        assert!(has_feedback("Kq9!jsmith-zP", &["jsmith@example.com"], "name or email"));
+        assert!(has_feedback("Kq9!example-zP", &["jsmith@example.com"], "name or email"));
+    }
+
+    #[test]
+    fn ignores_tld_and_short_email_fragments() {
+        assert!(!has_feedback("comfortable", &["user@example.com"], "name or email"));
+        assert!(!has_feedback("Kq9!couk-zP", &["jo@example.co.uk"], "name or email"));
+    }
+
+    #[test]
+    fn personal_fragments_skip_tld() {
+        assert_eq!(personal_fragments("First.Last@mail.example.com"), ["first", "last", "mail", "example"]);
+        assert_eq!(personal_fragments("user@example.com"), ["user", "example"]);
+    }
+
+    #[test]
+    fn personal_info_lowers_entropy() {
+        let plain = estimate_strength("Kq9!jsmith-zP", &[]);
+        let personal = estimate_strength("Kq9!jsmith-zP", &["jsmith@example.com"]);
+        assert!(personal.entropy_bits < plain.entropy_bits);
+    }
+
+    #[test]
+    fn config_defaults() {
+        env::remove_var("BREACHED_PASSWORDS_DIR");
+        env::remove_var("BREACHED_PASSWORDS_MIN_COUNT");
+        env::remove_var("PASSWORD_MIN_ENTROPY_BITS");
+
+        let config = PasswordCheckConfig::from_env();
+        assert!(config.breach_corpus_dir.is_none());
+        assert_eq!(config.breach_min_count, 1);
+        assert_eq!(config.min_entropy_bits, DEFAULT_MIN_ENTROPY_BITS);
+        assert!(config.min_entropy_bits > 0.0);
+    }
+}
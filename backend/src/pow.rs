@@ -0,0 +1,548 @@
+use actix_web::http::header::HeaderMap;
+use actix_web::HttpResponse;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::future::Future;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
+
+use crate::dba::DbContext;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Header carrying the challenge string issued by `/api/auth/challenge`
+pub const CHALLENGE_HEADER: &str = "X-PoW-Challenge";
+/// Header carrying the client's solution to the challenge
+pub const SOLUTION_HEADER: &str = "X-PoW-Solution";
+
+/// Maximum accepted length of a solution
+const MAX_SOLUTION_LENGTH: usize = 64;
+
+/// Length of the window used to count rejected attempts
+const RATE_WINDOW_SECS: u64 = 60;
+
+/// Actions that can be protected by a proof-of-work challenge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowAction {
+    Signup,
+    Login,
+    ResendVerification,
+    PasswordReset,
+    Reauth,
+}
+
+impl PowAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PowAction::Signup => "signup",
+            PowAction::Login => "login",
+            PowAction::ResendVerification => "resend_verification",
+            PowAction::PasswordReset => "password_reset",
+            PowAction::Reauth => "reauth",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "signup" => Some(PowAction::Signup),
+            "login" => Some(PowAction::Login),
+            "resend_verification" => Some(PowAction::ResendVerification),
+            "password_reset" => Some(PowAction::PasswordReset),
+            "reauth" => Some(PowAction::Reauth),
+            _ => None,
+        }
+    }
+}
+
+/// Proof-of-work configuration
+pub struct PowConfig {
+    pub enabled: bool,
+    /// HMAC key for signing challenges; must be shared by all replicas
+    pub secret: Vec<u8>,
+    /// Leading zero bits required under normal load
+    pub base_difficulty: u32,
+    pub max_difficulty: u32,
+    pub challenge_ttl: Duration,
+    /// Rejected attempts per minute from one IP before difficulty is raised for it
+    pub ip_failure_limit: u32,
+}
+
+impl PowConfig {
+    /// Create proof-of-work configuration from environment or defaults
+    pub fn from_env() -> Self {
+        let enabled = env::var("POW_ENABLED")
+            .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+
+        let secret = match env::var("POW_SECRET") {
+            Ok(secret) if !secret.is_empty() => secret.into_bytes(),
+            _ => {
+                if enabled {
+                    log::warn!("POW_SECRET not set, using a random key; challenges will not be valid across replicas or restarts");
+                }
+                let mut key = vec![0u8; 32];
+                rand::thread_rng().fill_bytes(&mut key);
+                key
+            }
+        };
+
+        let challenge_ttl_secs = env::var("POW_CHALLENGE_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(300);
+
+        Self {
+            enabled,
+            secret,
+            base_difficulty: env::var("POW_BASE_DIFFICULTY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(18),
+            max_difficulty: env::var("POW_MAX_DIFFICULTY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(24),
+            challenge_ttl: Duration::seconds(challenge_ttl_secs),
+            ip_failure_limit: env::var("POW_IP_FAILURE_LIMIT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(5),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Challenge {
+    pub enabled: bool,
+    pub action: &'static str,
+    pub challenge: String,
+    pub difficulty: u32,
+    pub algorithm: &'static str,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum PowError {
+    Missing,
+    Invalid,
+    Expired,
+    Replayed,
+    Db(sqlx::Error),
+}
+
+impl PowError {
+    pub fn error_response(&self) -> HttpResponse {
+        let message = match self {
+            PowError::Missing => "Proof-of-work challenge required",
+            PowError::Invalid => "Invalid proof-of-work solution",
+            PowError::Expired => "Proof-of-work challenge expired",
+            PowError::Replayed => "Proof-of-work challenge already used",
+            PowError::Db(e) => {
+                log::error!("Failed to redeem proof-of-work challenge: {:?}", e);
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": "Failed to verify proof-of-work"
+                }));
+            }
+        };
+        HttpResponse::BadRequest().json(serde_json::json!({
+            "error": message,
+            "challenge_required": true
+        }))
+    }
+}
+
+/// Rejected attempts per IP in the current window
+struct RateWindow {
+    started: Instant,
+    per_ip: HashMap<String, u32>,
+}
+
+/// Issues and verifies signed proof-of-work challenges
+pub struct PowService {
+    config: PowConfig,
+    window: Mutex<RateWindow>,
+}
+
+impl PowService {
+    pub fn new(config: PowConfig) -> Self {
+        Self {
+            config,
+            window: Mutex::new(RateWindow {
+                started: Instant::now(),
+                per_ip: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Issue a challenge bound to the client's IP, raising difficulty when
+    /// rejected attempts from that IP exceed their limit
+    pub fn issue(&self, action: PowAction, ip: Option<&str>) -> Challenge {
+        let difficulty = self.current_difficulty(ip);
+        let expires_at = Utc::now() + self.config.challenge_ttl;
+
+        let mut nonce = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let payload = format!(
+            "{}.{}.{}.{}.{}",
+            action.as_str(),
+            difficulty,
+            expires_at.timestamp(),
+            hex::encode(nonce),
+            self.ip_tag(ip)
+        );
+        let signature = hex::encode(self.sign(&payload));
+
+        Challenge {
+            enabled: self.config.enabled,
+            action: action.as_str(),
+            challenge: format!("{}.{}", payload, signature),
+            difficulty,
+            algorithm: "sha256",
+            expires_at,
+        }
+    }
+
+    /// Verify the challenge and solution headers of a request for an action.
+    ///
+    /// A solution is valid when SHA-256 of `"{challenge}:{solution}"` starts with
+    /// at least `difficulty` zero bits. Each challenge can be redeemed once, and
+    /// only from the IP it was issued to, so an easy challenge can't be carried
+    /// over from another address. Rejected requests count towards raising the
+    /// difficulty for `ip`.
+    pub async fn verify(
+        &self,
+        db: &DbContext,
+        headers: &HeaderMap,
+        action: PowAction,
+        ip: Option<&str>,
+    ) -> Result<(), PowError> {
+        self.verify_with(headers, action, ip, |nonce, expires_at| async move {
+            db.redeem_pow_nonce(&nonce, expires_at).await
+        })
+        .await
+    }
+
+    /// `verify`, redeeming the challenge's nonce with `redeem`, which returns
+    /// false if it was redeemed before
+    async fn verify_with<F, Fut>(
+        &self,
+        headers: &HeaderMap,
+        action: PowAction,
+        ip: Option<&str>,
+        redeem: F,
+    ) -> Result<(), PowError>
+    where
+        F: FnOnce(String, DateTime<Utc>) -> Fut,
+        Fut: Future<Output = Result<bool, sqlx::Error>>,
+    {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        let result = match self.check(headers, action, ip) {
+            Ok((nonce, expires_at)) => match redeem(nonce, expires_at).await {
+                Ok(true) => Ok(()),
+                Ok(false) => Err(PowError::Replayed),
+                Err(e) => Err(PowError::Db(e)),
+            },
+            Err(e) => Err(e),
+        };
+        if let Err(e) = &result {
+            if !matches!(e, PowError::Db(_)) {
+                self.record_rejection(ip);
+            }
+        }
+        result
+    }
+
+    /// Check the signature, action, client IP, expiry and solution of a
+    /// request's challenge, returning its nonce and expiry
+    fn check(
+        &self,
+        headers: &HeaderMap,
+        action: PowAction,
+        ip: Option<&str>,
+    ) -> Result<(String, DateTime<Utc>), PowError> {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        let (challenge, solution) = match (header(CHALLENGE_HEADER), header(SOLUTION_HEADER)) {
+            (Some(c), Some(s)) if !s.is_empty() && s.len() <= MAX_SOLUTION_LENGTH => (c, s),
+            _ => return Err(PowError::Missing),
+        };
+
+        let (payload, signature) = challenge.rsplit_once('.').ok_or(PowError::Invalid)?;
+        let signature = hex::decode(signature).map_err(|_| PowError::Invalid)?;
+        let mut mac = HmacSha256::new_from_slice(&self.config.secret).expect("HMAC accepts any key length");
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature).map_err(|_| PowError::Invalid)?;
+
+        let parts: Vec<&str> = payload.split('.').collect();
+        let [challenge_action, difficulty, expires_at, nonce, ip_tag] = parts[..] else {
+            return Err(PowError::Invalid);
+        };
+        if challenge_action != action.as_str() || ip_tag != self.ip_tag(ip) {
+            return Err(PowError::Invalid);
+        }
+        let difficulty: u32 = difficulty.parse().map_err(|_| PowError::Invalid)?;
+        let expires_at = expires_at
+            .parse::<i64>()
+            .ok()
+            .and_then(|ts| Utc.timestamp_opt(ts, 0).single())
+            .ok_or(PowError::Invalid)?;
+        if expires_at <= Utc::now() {
+            return Err(PowError::Expired);
+        }
+
+        let hash = Sha256::digest(format!("{}:{}", challenge, solution).as_bytes());
+        if leading_zero_bits(&hash) < difficulty {
+            return Err(PowError::Invalid);
+        }
+
+        Ok((nonce.to_string(), expires_at))
+    }
+
+    /// Count a rejected attempt at a protected action, such as a wrong password
+    /// or a missing or invalid solution
+    pub fn record_rejection(&self, ip: Option<&str>) {
+        if let Some(ip) = ip {
+            *self.window().per_ip.entry(ip.to_string()).or_insert(0) += 1;
+        }
+    }
+
+    fn sign(&self, payload: &str) -> Vec<u8> {
+        let mut mac = HmacSha256::new_from_slice(&self.config.secret).expect("HMAC accepts any key length");
+        mac.update(payload.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    /// Keyed tag identifying the IP a challenge was issued to, without putting
+    /// the address itself in the challenge
+    fn ip_tag(&self, ip: Option<&str>) -> String {
+        match ip {
+            Some(ip) => hex::encode(&self.sign(&format!("ip:{}", ip))[..8]),
+            None => "-".to_string(),
+        }
+    }
+
+    /// Derive the difficulty from the IP's rejected attempts in the current
+    /// window. Other clients' failures don't count, so no one can raise the
+    /// difficulty for everybody else.
+    fn current_difficulty(&self, ip: Option<&str>) -> u32 {
+        let window = self.window();
+        let ip_count = ip.and_then(|ip| window.per_ip.get(ip)).copied().unwrap_or(0);
+        let extra = extra_bits(ip_count, self.config.ip_failure_limit);
+        (self.config.base_difficulty + extra).min(self.config.max_difficulty)
+    }
+
+    /// The current window, starting a new one once it has run its length
+    fn window(&self) -> MutexGuard<'_, RateWindow> {
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        if window.started.elapsed().as_secs() >= RATE_WINDOW_SECS {
+            window.started = Instant::now();
+            window.per_ip.clear();
+        }
+        window
+    }
+}
+
+/// One extra bit (doubling the work) for each doubling of the count over its limit
+fn extra_bits(count: u32, limit: u32) -> u32 {
+    let mut bits = 0;
+    let mut threshold = limit.max(1);
+    while count > threshold && bits < 16 {
+        bits += 1;
+        threshold = threshold.saturating_mul(2);
+    }
+    bits
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        if *byte == 0 {
+            bits += 8;
+        } else {
+            bits += byte.leading_zeros();
+            break;
+        }
+    }
+    bits
+}
+
+/// Remove redemption records of challenges that can no longer be replayed
+pub async fn clear_expired_redemptions(db: &DbContext) {
+    if let Err(e) = db.delete_expired_pow_redemptions().await {
+        log::error!("Failed to clear expired proof-of-work redemptions: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+    use std::collections::HashSet;
+
+    const TEST_IP: &str = "192.0.2.1";
+
+    fn service(challenge_ttl: Duration) -> PowService {
+        PowService::new(PowConfig {
+            enabled: true,
+            secret: b"test-secret".to_vec(),
+            base_difficulty: 4,
+            max_difficulty: 8,
+            challenge_ttl,
+            ip_failure_limit: 2,
+        })
+    }
+
+    fn solve(challenge: &Challenge) -> HeaderMap {
+        let solution = (0u64..)
+            .map(|n| n.to_string())
+            .find(|s| {
+                let hash = Sha256::digest(format!("{}:{}", challenge.challenge, s).as_bytes());
+                leading_zero_bits(&hash) >= challenge.difficulty
+            })
+            .unwrap();
+        headers(&challenge.challenge, &solution)
+    }
+
+    fn headers(challenge: &str, solution: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("x-pow-challenge"),
+            HeaderValue::from_str(challenge).unwrap(),
+        );
+        headers.insert(
+            HeaderName::from_static("x-pow-solution"),
+            HeaderValue::from_str(solution).unwrap(),
+        );
+        headers
+    }
+
+    async fn verify(
+        pow: &PowService,
+        redeemed: &Mutex<HashSet<String>>,
+        headers: &HeaderMap,
+        action: PowAction,
+    ) -> Result<(), PowError> {
+        pow.verify_with(headers, action, Some(TEST_IP), |nonce, _| {
+            let fresh = redeemed.lock().unwrap().insert(nonce);
+            async move { Ok(fresh) }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn accepts_valid_solution() {
+        let pow = service(Duration::minutes(5));
+        let challenge = pow.issue(PowAction::Login, Some(TEST_IP));
+        let redeemed = Mutex::new(HashSet::new());
+
+        assert!(verify(&pow, &redeemed, &solve(&challenge), PowAction::Login).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_headers() {
+        let pow = service(Duration::minutes(5));
+        let redeemed = Mutex::new(HashSet::new());
+
+        let result = verify(&pow, &redeemed, &HeaderMap::new(), PowAction::Login).await;
+        assert!(matches!(result, Err(PowError::Missing)));
+    }
+
+    #[tokio::test]
+    async fn rejects_tampered_signature() {
+        let pow = service(Duration::minutes(5));
+        let challenge = pow.issue(PowAction::Login, Some(TEST_IP));
+        let mut tampered = solve(&challenge);
+        let last = challenge.challenge.chars().last().unwrap();
+        let replacement = if last == '0' { '1' } else { '0' };
+        let forged = format!(
+            "{}{}",
+            &challenge.challenge[..challenge.challenge.len() - 1],
+            replacement
+        );
+        tampered.insert(
+            HeaderName::from_static("x-pow-challenge"),
+            HeaderValue::from_str(&forged).unwrap(),
+        );
+        let redeemed = Mutex::new(HashSet::new());
+
+        let result = verify(&pow, &redeemed, &tampered, PowAction::Login).await;
+        assert!(matches!(result, Err(PowError::Invalid)));
+    }
+
+    #[tokio::test]
+    async fn rejects_challenge_for_another_action() {
+        let pow = service(Duration::minutes(5));
+        let challenge = pow.issue(PowAction::Login, Some(TEST_IP));
+        let redeemed = Mutex::new(HashSet::new());
+
+        let result = verify(&pow, &redeemed, &solve(&challenge), PowAction::Signup).await;
+        assert!(matches!(result, Err(PowError::Invalid)));
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_challenge() {
+        let pow = service(Duration::seconds(-1));
+        let challenge = pow.issue(PowAction::Login, Some(TEST_IP));
+        let redeemed = Mutex::new(HashSet::new());
+
+        let result = verify(&pow, &redeemed, &solve(&challenge), PowAction::Login).await;
+        assert!(matches!(result, Err(PowError::Expired)));
+    }
+
+    #[tokio::test]
+    async fn rejects_replayed_challenge() {
+        let pow = service(Duration::minutes(5));
+        let challenge = pow.issue(PowAction::Login, Some(TEST_IP));
+        let headers = solve(&challenge);
+        let redeemed = Mutex::new(HashSet::new());
+
+        assert!(verify(&pow, &redeemed, &headers, PowAction::Login).await.is_ok());
+        let result = verify(&pow, &redeemed, &headers, PowAction::Login).await;
+        assert!(matches!(result, Err(PowError::Replayed)));
+    }
+
+    #[tokio::test]
+    async fn raises_difficulty_after_rejections() {
+        let pow = service(Duration::minutes(5));
+        let redeemed = Mutex::new(HashSet::new());
+        for _ in 0..3 {
+            let _ = verify(&pow, &redeemed, &HeaderMap::new(), PowAction::Login).await;
+        }
+
+        assert_eq!(pow.issue(PowAction::Login, Some(TEST_IP)).difficulty, 5);
+        assert_eq!(pow.issue(PowAction::Login, Some("192.0.2.2")).difficulty, 4);
+    }
+
+    #[tokio::test]
+    async fn rejections_from_other_ips_do_not_raise_difficulty() {
+        let pow = service(Duration::minutes(5));
+        for i in 0..200 {
+            pow.record_rejection(Some(&format!("198.51.100.{}", i % 250)));
+            pow.record_rejection(None);
+        }
+
+        assert_eq!(pow.issue(PowAction::Login, Some(TEST_IP)).difficulty, 4);
+    }
+
+    #[tokio::test]
+    async fn rejects_challenge_issued_to_another_ip() {
+        let pow = service(Duration::minutes(5));
+        let challenge = pow.issue(PowAction::Login, Some("192.0.2.2"));
+        let redeemed = Mutex::new(HashSet::new());
+
+        let result = verify(&pow, &redeemed, &solve(&challenge), PowAction::Login).await;
+        assert!(matches!(result, Err(PowError::Invalid)));
+    }
+}
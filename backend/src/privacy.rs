@@ -0,0 +1,70 @@
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::mailer::{self, Mailer};
+
+/// Account enumeration protection configuration
+#[derive(Clone)]
+pub struct PrivacyConfig {
+    /// Give the same response whether or not an email is registered
+    pub enabled: bool,
+    /// Minimum duration of responses that could otherwise be told apart by timing
+    pub min_response_time: Duration,
+}
+
+impl PrivacyConfig {
+    /// Create privacy configuration from environment or defaults
+    pub fn from_env() -> Self {
+        Self {
+            enabled: env::var("AUTH_PRIVACY_MODE")
+                .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+                .unwrap_or(false),
+            min_response_time: Duration::from_millis(
+                env::var("AUTH_MIN_RESPONSE_MS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(500),
+            ),
+        }
+    }
+
+    /// Delay a response until `min_response_time` has passed since `started`
+    pub async fn pad(&self, started: Instant) {
+        if !self.enabled {
+            return;
+        }
+        if let Some(remaining) = self.min_response_time.checked_sub(started.elapsed()) {
+            tokio::time::sleep(remaining).await;
+        }
+    }
+}
+
+/// Email the owner of an address in the background, so the response time
+/// doesn't depend on whether the address is registered
+pub fn notify_owner(mailer: &Arc<dyn Mailer>, to: &str, subject: &'static str, body: String) {
+    let mailer = mailer.clone();
+    let to = to.to_string();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = mailer.send_email(&to, subject, &body).await {
+            log::error!("Failed to send account notice to {}: {:?}", to, e);
+        }
+    });
+}
+
+pub fn signup_attempt_body() -> String {
+    format!(
+        "Someone tried to create an account with this email address, which already has one.\n\n\
+         If this was you, sign in instead: {}/signin\n\
+         If it wasn't, you can ignore this email; no changes were made to your account.",
+        mailer::frontend_url()
+    )
+}
+
+pub fn already_verified_body() -> String {
+    format!(
+        "A new verification code was requested for this email address, but it is already verified.\n\n\
+         You can sign in at {}/signin",
+        mailer::frontend_url()
+    )
+}
@@ -0,0 +1,575 @@
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::http::StatusCode;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_ws::{AggregatedMessage, AggregatedMessageStream, CloseCode, CloseReason, Session};
+use serde::Deserialize;
+use std::collections::{HashSet, VecDeque};
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+use crate::auth;
+use crate::dba::DbContext;
+use crate::models::Account;
+use crate::notification_stream::{HubMessage, NotificationHub};
+use crate::token_cache::Principal;
+
+/// Path checked against the password-reset allowlist
+const WS_PATH: &str = "/api/ws";
+
+/// Largest client message accepted, in bytes
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// Maximum notifications marked read by one message
+const MAX_MARK_READ_IDS: usize = 500;
+
+/// Realtime WebSocket configuration
+#[derive(Clone)]
+pub struct RealtimeConfig {
+    /// How long a client has to send its token after connecting
+    pub auth_timeout: Duration,
+    /// How often the access token is checked again and presence refreshed
+    pub revalidate_interval: Duration,
+    /// How often the server pings; clients silent for two intervals are dropped
+    pub heartbeat_interval: Duration,
+    /// Events a client may leave unacknowledged before delivery pauses
+    pub max_unacked: u64,
+    /// Events held back while paused before the connection is dropped
+    pub max_queued: usize,
+    /// How long a send may wait on a slow socket before the connection is dropped
+    pub send_timeout: Duration,
+}
+
+impl RealtimeConfig {
+    /// Create realtime configuration from environment or defaults
+    pub fn from_env() -> Self {
+        let auth_timeout_secs = env::var("WS_AUTH_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(10);
+        let revalidate_secs = env::var("WS_REVALIDATE_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(60);
+        let heartbeat_secs = env::var("WS_HEARTBEAT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(30);
+        let send_timeout_secs = env::var("WS_SEND_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(10);
+        Self {
+            auth_timeout: Duration::from_secs(auth_timeout_secs),
+            revalidate_interval: Duration::from_secs(revalidate_secs),
+            heartbeat_interval: Duration::from_secs(heartbeat_secs),
+            max_unacked: env::var("WS_MAX_UNACKED")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(100),
+            max_queued: env::var("WS_MAX_QUEUED")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1000),
+            send_timeout: Duration::from_secs(send_timeout_secs),
+        }
+    }
+
+    /// Connections not refreshed within this window are considered gone
+    pub fn presence_ttl(&self) -> chrono::Duration {
+        chrono::Duration::from_std(self.revalidate_interval * 2).unwrap_or(chrono::Duration::minutes(2))
+    }
+}
+
+/// Topics a connection can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Topic {
+    /// Changes to the account's own notifications
+    Notifications,
+}
+
+impl Topic {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Topic::Notifications => "notifications",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "notifications" => Some(Topic::Notifications),
+            _ => None,
+        }
+    }
+}
+
+/// Messages sent by clients. Any message may carry an `id`, echoed back in
+/// the `reply` or `error` it produces.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    /// Authenticate, or swap in a refreshed access token
+    Auth { token: String },
+    Subscribe { topic: String },
+    Unsubscribe { topic: String },
+    /// Acknowledge every event up to and including `message_id`
+    Ack { message_id: u64 },
+    MarkRead {
+        notification_ids: Vec<Uuid>,
+        #[serde(default = "default_read")]
+        read: bool,
+    },
+}
+
+fn default_read() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientEnvelope {
+    id: Option<String>,
+    #[serde(flatten)]
+    message: ClientMessage,
+}
+
+/// Why a connection ended; `None` when the socket is already gone
+struct Disconnect(Option<CloseReason>);
+
+impl Disconnect {
+    fn gone() -> Self {
+        Disconnect(None)
+    }
+
+    fn close(code: CloseCode, description: &str) -> Self {
+        Disconnect(Some(CloseReason {
+            code,
+            description: Some(description.to_string()),
+        }))
+    }
+}
+
+/// Open the realtime WebSocket.
+///
+/// Browsers can't set headers on a WebSocket, so the token is normally sent
+/// as the first message (`{"type":"auth","token":"..."}`); other clients may
+/// use the usual `Authorization: Bearer` header instead.
+pub async fn connect(
+    req: HttpRequest,
+    body: web::Payload,
+    db: web::Data<DbContext>,
+    hub: web::Data<Arc<NotificationHub>>,
+    config: web::Data<RealtimeConfig>,
+) -> Result<HttpResponse, Error> {
+    let header_token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string());
+    let pre_auth = match header_token {
+        Some(token) => {
+            let principal = auth::authenticate(&req, &token).await?;
+            auth::check_password_reset(WS_PATH, &principal)?;
+            Some((token, principal))
+        }
+        None => None,
+    };
+
+    let (response, session, stream) = actix_ws::handle(&req, body)?;
+    let stream = stream
+        .aggregate_continuations()
+        .max_continuation_size(MAX_MESSAGE_SIZE);
+
+    let events = hub.subscribe();
+    actix_web::rt::spawn(run(
+        req,
+        db.get_ref().clone(),
+        config.get_ref().clone(),
+        session,
+        stream,
+        events,
+        pre_auth,
+    ));
+    Ok(response)
+}
+
+async fn run(
+    req: HttpRequest,
+    db: DbContext,
+    config: RealtimeConfig,
+    mut session: Session,
+    mut stream: AggregatedMessageStream,
+    events: broadcast::Receiver<HubMessage>,
+    pre_auth: Option<(String, Principal)>,
+) {
+    let (token, principal) = match pre_auth {
+        Some(auth) => auth,
+        None => match wait_for_auth(&req, &config, &mut session, &mut stream).await {
+            Ok(auth) => auth,
+            Err(Disconnect(reason)) => {
+                let _ = session.close(reason).await;
+                return;
+            }
+        },
+    };
+
+    let account = match principal.account.clone() {
+        Some(account) => account,
+        None => match db
+            .get_or_create_account_by_iam_id(principal.user.account_id, principal.user.email.clone())
+            .await
+        {
+            Ok(account) => account,
+            Err(e) => {
+                log::error!("Failed to load account for realtime connection: {:?}", e);
+                let _ = session
+                    .close(Disconnect::close(CloseCode::Error, "Internal error").0)
+                    .await;
+                return;
+            }
+        },
+    };
+
+    let connection_id = Uuid::new_v4();
+    if let Err(e) = db.add_realtime_connection(connection_id, account.id).await {
+        log::error!("Failed to record realtime connection: {:?}", e);
+    }
+
+    let mut connection = Connection {
+        id: connection_id,
+        req,
+        db: db.clone(),
+        config,
+        session,
+        token,
+        principal,
+        account,
+        topics: HashSet::new(),
+        last_message_id: 0,
+        last_sent_id: 0,
+        acked: 0,
+        queue: VecDeque::new(),
+    };
+    let Disconnect(reason) = connection.serve(&mut stream, events).await;
+
+    if let Err(e) = db.remove_realtime_connection(connection_id).await {
+        log::error!("Failed to remove realtime connection: {:?}", e);
+    }
+    let _ = connection.session.close(reason).await;
+}
+
+/// Wait for the client's `auth` message
+async fn wait_for_auth(
+    req: &HttpRequest,
+    config: &RealtimeConfig,
+    session: &mut Session,
+    stream: &mut AggregatedMessageStream,
+) -> Result<(String, Principal), Disconnect> {
+    let deadline = tokio::time::sleep(config.auth_timeout);
+    tokio::pin!(deadline);
+
+    loop {
+        let message = tokio::select! {
+            _ = &mut deadline => {
+                return Err(Disconnect::close(CloseCode::Policy, "Authentication timed out"));
+            }
+            message = stream.recv() => message,
+        };
+
+        let text = match message {
+            Some(Ok(AggregatedMessage::Text(text))) => text,
+            Some(Ok(AggregatedMessage::Ping(bytes))) => {
+                session.pong(&bytes).await.map_err(|_| Disconnect::gone())?;
+                continue;
+            }
+            Some(Ok(AggregatedMessage::Pong(_))) => continue,
+            Some(Ok(AggregatedMessage::Close(_))) | None => return Err(Disconnect::gone()),
+            Some(Ok(AggregatedMessage::Binary(_))) | Some(Err(_)) => {
+                return Err(Disconnect::close(CloseCode::Unsupported, "Expected a JSON text message"));
+            }
+        };
+
+        let token = match serde_json::from_str::<ClientEnvelope>(&text) {
+            Ok(ClientEnvelope {
+                message: ClientMessage::Auth { token },
+                ..
+            }) => token,
+            _ => return Err(Disconnect::close(CloseCode::Policy, "Authentication required")),
+        };
+        let principal = auth::authenticate(req, &token)
+            .await
+            .map_err(|_| Disconnect::close(CloseCode::Policy, "Invalid or expired token"))?;
+        auth::check_password_reset(WS_PATH, &principal)
+            .map_err(|_| Disconnect::close(CloseCode::Policy, "Password reset required"))?;
+        return Ok((token, principal));
+    }
+}
+
+/// An authenticated realtime connection
+struct Connection {
+    id: Uuid,
+    req: HttpRequest,
+    db: DbContext,
+    config: RealtimeConfig,
+    session: Session,
+    token: String,
+    principal: Principal,
+    account: Account,
+    topics: HashSet<Topic>,
+    /// ID of the last event queued for this client
+    last_message_id: u64,
+    /// ID of the last event actually sent to the client
+    last_sent_id: u64,
+    /// Highest event ID the client has acknowledged
+    acked: u64,
+    /// Events waiting for the client to acknowledge earlier ones
+    queue: VecDeque<(u64, String)>,
+}
+
+impl Connection {
+    async fn serve(
+        &mut self,
+        stream: &mut AggregatedMessageStream,
+        mut events: broadcast::Receiver<HubMessage>,
+    ) -> Disconnect {
+        match self.serve_inner(stream, &mut events).await {
+            Ok(()) => Disconnect::close(CloseCode::Normal, "Closing"),
+            Err(disconnect) => disconnect,
+        }
+    }
+
+    async fn serve_inner(
+        &mut self,
+        stream: &mut AggregatedMessageStream,
+        events: &mut broadcast::Receiver<HubMessage>,
+    ) -> Result<(), Disconnect> {
+        self.send(serde_json::json!({
+            "type": "ready",
+            "connection_id": self.id,
+        }))
+        .await?;
+
+        let start = tokio::time::Instant::now();
+        let mut heartbeat =
+            tokio::time::interval_at(start + self.config.heartbeat_interval, self.config.heartbeat_interval);
+        let mut revalidate = tokio::time::interval_at(
+            start + self.config.revalidate_interval,
+            self.config.revalidate_interval,
+        );
+        let mut last_heard = Instant::now();
+
+        loop {
+            tokio::select! {
+                message = stream.recv() => {
+                    last_heard = Instant::now();
+                    match message {
+                        Some(Ok(AggregatedMessage::Text(text))) => self.handle_text(&text).await?,
+                        Some(Ok(AggregatedMessage::Binary(_))) => {
+                            self.send_error(None, "Binary messages are not supported").await?
+                        }
+                        Some(Ok(AggregatedMessage::Ping(bytes))) => {
+                            self.session.pong(&bytes).await.map_err(|_| Disconnect::gone())?
+                        }
+                        Some(Ok(AggregatedMessage::Pong(_))) => {}
+                        Some(Ok(AggregatedMessage::Close(_))) | None => return Ok(()),
+                        Some(Err(e)) => {
+                            log::debug!("Realtime protocol error: {:?}", e);
+                            return Err(Disconnect::close(CloseCode::Protocol, "Protocol error"));
+                        }
+                    }
+                }
+                received = events.recv() => match received {
+                    Ok(HubMessage::Event(event)) => {
+                        if event.account_id == self.account.id
+                            && self.topics.contains(&Topic::Notifications)
+                        {
+                            self.enqueue(Topic::Notifications, &event.event_type, &event.data).await?;
+                        }
+                    }
+                    // Events were missed; subscribers should refetch
+                    Ok(HubMessage::Resync) | Err(RecvError::Lagged(_)) => {
+                        if self.topics.contains(&Topic::Notifications) {
+                            self.send(serde_json::json!({
+                                "type": "resync",
+                                "topic": Topic::Notifications.as_str(),
+                            }))
+                            .await?;
+                        }
+                    }
+                    Err(RecvError::Closed) => {
+                        return Err(Disconnect::close(CloseCode::Restart, "Server shutting down"));
+                    }
+                },
+                _ = heartbeat.tick() => {
+                    if last_heard.elapsed() > self.config.heartbeat_interval * 2 {
+                        return Err(Disconnect::close(CloseCode::Policy, "Heartbeat timed out"));
+                    }
+                    self.session.ping(b"").await.map_err(|_| Disconnect::gone())?;
+                }
+                _ = revalidate.tick() => self.revalidate().await?,
+            }
+        }
+    }
+
+    async fn handle_text(&mut self, text: &str) -> Result<(), Disconnect> {
+        let envelope = match serde_json::from_str::<ClientEnvelope>(text) {
+            Ok(envelope) => envelope,
+            Err(e) => return self.send_error(None, &format!("Invalid message: {}", e)).await,
+        };
+        let id = envelope.id;
+
+        match envelope.message {
+            ClientMessage::Auth { token } => self.swap_token(id, token).await,
+            ClientMessage::Subscribe { topic } => match Topic::parse(&topic) {
+                Some(topic) => {
+                    self.topics.insert(topic);
+                    self.reply(id, serde_json::json!({ "subscribed": topic.as_str() })).await
+                }
+                None => self.send_error(id, "Unknown topic").await,
+            },
+            ClientMessage::Unsubscribe { topic } => match Topic::parse(&topic) {
+                Some(topic) => {
+                    self.topics.remove(&topic);
+                    self.reply(id, serde_json::json!({ "unsubscribed": topic.as_str() })).await
+                }
+                None => self.send_error(id, "Unknown topic").await,
+            },
+            ClientMessage::Ack { message_id } => {
+                // Events still in the queue haven't been seen, so they can't be acknowledged
+                if message_id > self.acked && message_id <= self.last_sent_id {
+                    self.acked = message_id;
+                }
+                self.flush().await
+            }
+            ClientMessage::MarkRead {
+                notification_ids,
+                read,
+            } => {
+                if notification_ids.len() > MAX_MARK_READ_IDS {
+                    return self.send_error(id, "Too many notification IDs").await;
+                }
+                match self
+                    .db
+                    .update_notifications_read_batch(&notification_ids, self.account.id, read)
+                    .await
+                {
+                    Ok(updated) => {
+                        self.reply(id, serde_json::json!({ "updated": updated.len() })).await
+                    }
+                    Err(e) => {
+                        log::error!("Failed to update notifications over realtime: {:?}", e);
+                        self.send_error(id, "Failed to update notifications").await
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replace the token of this connection with a refreshed one for the same account
+    async fn swap_token(&mut self, id: Option<String>, token: String) -> Result<(), Disconnect> {
+        let principal = match auth::authenticate(&self.req, &token).await {
+            Ok(principal) => principal,
+            Err(_) => return self.send_error(id, "Invalid or expired token").await,
+        };
+        if principal.user.account_id != self.principal.user.account_id {
+            return self.send_error(id, "Token belongs to a different account").await;
+        }
+
+        self.token = token;
+        self.principal = principal;
+        self.reply(id, serde_json::json!({ "authenticated": true })).await
+    }
+
+    /// Close the connection once its token has expired or been revoked
+    async fn revalidate(&mut self) -> Result<(), Disconnect> {
+        match auth::authenticate(&self.req, &self.token).await {
+            Ok(principal) => {
+                if auth::check_password_reset(WS_PATH, &principal).is_err() {
+                    return Err(Disconnect::close(CloseCode::Policy, "Password reset required"));
+                }
+                self.principal = principal;
+            }
+            Err(e) if e.as_response_error().status_code() == StatusCode::UNAUTHORIZED => {
+                return Err(Disconnect::close(CloseCode::Policy, "Token expired"));
+            }
+            // Keep the connection through transient failures
+            Err(e) => log::warn!("Failed to revalidate realtime connection: {}", e),
+        }
+
+        if let Err(e) = self.db.touch_realtime_connection(self.id).await {
+            log::error!("Failed to refresh realtime presence: {:?}", e);
+        }
+        Ok(())
+    }
+
+    /// Queue an event, delivering it once the client has acknowledged enough earlier ones
+    async fn enqueue(
+        &mut self,
+        topic: Topic,
+        event: &str,
+        data: &serde_json::Value,
+    ) -> Result<(), Disconnect> {
+        if self.queue.len() >= self.config.max_queued {
+            return Err(Disconnect::close(CloseCode::Policy, "Client is not keeping up"));
+        }
+
+        self.last_message_id += 1;
+        let message = serde_json::json!({
+            "type": "event",
+            "message_id": self.last_message_id,
+            "topic": topic.as_str(),
+            "event": event,
+            "data": data,
+        });
+        self.queue.push_back((self.last_message_id, message.to_string()));
+        self.flush().await
+    }
+
+    /// Send queued events that fit in the acknowledgement window
+    async fn flush(&mut self) -> Result<(), Disconnect> {
+        while let Some((message_id, _)) = self.queue.front() {
+            if message_id.saturating_sub(self.acked) > self.config.max_unacked {
+                break;
+            }
+            if let Some((message_id, text)) = self.queue.pop_front() {
+                self.send_text(text).await?;
+                self.last_sent_id = message_id;
+            }
+        }
+        Ok(())
+    }
+
+    async fn reply(&mut self, id: Option<String>, data: serde_json::Value) -> Result<(), Disconnect> {
+        self.send(serde_json::json!({ "type": "reply", "id": id, "data": data }))
+            .await
+    }
+
+    async fn send_error(&mut self, id: Option<String>, message: &str) -> Result<(), Disconnect> {
+        self.send(serde_json::json!({ "type": "error", "id": id, "message": message }))
+            .await
+    }
+
+    async fn send(&mut self, message: serde_json::Value) -> Result<(), Disconnect> {
+        self.send_text(message.to_string()).await
+    }
+
+    async fn send_text(&mut self, text: String) -> Result<(), Disconnect> {
+        match tokio::time::timeout(self.config.send_timeout, self.session.text(text)).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(_)) => Err(Disconnect::gone()),
+            Err(_) => Err(Disconnect::close(CloseCode::Policy, "Client is not keeping up")),
+        }
+    }
+}
+
+/// Remove presence records of connections whose replica stopped refreshing them
+pub async fn clear_stale_connections(db: &DbContext, config: &RealtimeConfig) {
+    let cutoff = chrono::Utc::now() - config.presence_ttl();
+    match db.delete_stale_realtime_connections(cutoff).await {
+        Ok(0) => {}
+        Ok(count) => log::info!("Cleared {} stale realtime connection(s)", count),
+        Err(e) => log::error!("Failed to clear stale realtime connections: {:?}", e),
+    }
+}
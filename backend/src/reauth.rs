@@ -0,0 +1,205 @@
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{web, Error, FromRequest, HttpRequest, HttpResponse};
+use argon2::password_hash::{PasswordHash, PasswordVerifier};
+use argon2::Argon2;
+use chrono::{DateTime, Duration, Utc};
+use nano_iam::{AccountId, AuthService, AuthType, IamError};
+use serde::Deserialize;
+use std::env;
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::dba::DbContext;
+use crate::sessions;
+
+const GOOGLE_TOKEN_INFO_URL: &str = "https://oauth2.googleapis.com/tokeninfo";
+
+/// Step-up re-authentication configuration
+#[derive(Clone)]
+pub struct ReauthConfig {
+    /// How long a session stays elevated after re-authenticating; Google ID
+    /// tokens issued longer ago than this are refused as well
+    pub window: Duration,
+    /// OAuth client Google ID tokens must be issued for
+    pub google_client_id: Option<String>,
+    http: reqwest::Client,
+}
+
+impl ReauthConfig {
+    /// Create re-authentication configuration from environment or defaults
+    pub fn from_env() -> Self {
+        let window_secs = env::var("REAUTH_WINDOW_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(300);
+        let http = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .expect("Failed to build Google token HTTP client");
+        Self {
+            window: Duration::seconds(window_secs),
+            google_client_id: env::var("GOOGLE_OAUTH_CLIENT_ID").ok().filter(|id| !id.is_empty()),
+            http,
+        }
+    }
+}
+
+/// Why an identity could not be confirmed
+#[derive(Debug)]
+pub enum ReauthError {
+    /// The proof doesn't belong to the account, or is too old
+    Rejected,
+    /// The proof couldn't be checked
+    Failed(String),
+}
+
+impl From<IamError> for ReauthError {
+    fn from(e: IamError) -> Self {
+        match e {
+            IamError::InvalidCredentials | IamError::AuthTypeMismatch | IamError::InvalidOAuthToken => {
+                ReauthError::Rejected
+            }
+            e => ReauthError::Failed(format!("{:?}", e)),
+        }
+    }
+}
+
+/// Confirm an account's password against the hash nano-iam stores. Nothing is
+/// signed in, so no tokens are issued and the IAM account is left untouched.
+pub async fn confirm_password(
+    db: &DbContext,
+    account_id: AccountId,
+    password: &str,
+) -> Result<(), ReauthError> {
+    let hash = db
+        .get_password_hash(account_id)
+        .await
+        .map_err(|e| ReauthError::Failed(format!("failed to load password hash: {:?}", e)))?
+        .ok_or(ReauthError::Rejected)?;
+
+    // Argon2 is deliberately slow, keep it off the async workers
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || {
+        let hash = PasswordHash::new(&hash)
+            .map_err(|e| ReauthError::Failed(format!("unreadable password hash: {:?}", e)))?;
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .map_err(|_| ReauthError::Rejected)
+    })
+    .await
+    .map_err(|e| ReauthError::Failed(format!("password check panicked: {:?}", e)))?
+}
+
+/// Claims returned by Google's token info endpoint, which checks the ID token's
+/// signature and expiry
+#[derive(Debug, Deserialize)]
+struct GoogleTokenInfo {
+    aud: String,
+    email: Option<String>,
+    email_verified: Option<String>,
+    iat: String,
+}
+
+/// Confirm that a fresh Google ID token belongs to the given account. The token
+/// is checked with Google directly so nothing is signed in or created in IAM.
+pub async fn confirm_google(
+    auth_service: &AuthService,
+    config: &ReauthConfig,
+    account_id: AccountId,
+    id_token: &str,
+) -> Result<(), ReauthError> {
+    let client_id = config
+        .google_client_id
+        .as_deref()
+        .ok_or_else(|| ReauthError::Failed("Google OAuth is not configured".to_string()))?;
+
+    let response = config
+        .http
+        .get(GOOGLE_TOKEN_INFO_URL)
+        .query(&[("id_token", id_token)])
+        .send()
+        .await
+        .map_err(|e| ReauthError::Failed(format!("Google token info request failed: {:?}", e)))?;
+    if response.status().is_client_error() {
+        return Err(ReauthError::Rejected);
+    }
+    let body = response
+        .error_for_status()
+        .map_err(|e| ReauthError::Failed(format!("Google token info request failed: {:?}", e)))?
+        .text()
+        .await
+        .map_err(|e| ReauthError::Failed(format!("Google token info request failed: {:?}", e)))?;
+    let info: GoogleTokenInfo = serde_json::from_str(&body)
+        .map_err(|e| ReauthError::Failed(format!("Invalid Google token info response: {:?}", e)))?;
+
+    let issued_at = info
+        .iat
+        .parse::<i64>()
+        .ok()
+        .and_then(|iat| DateTime::<Utc>::from_timestamp(iat, 0))
+        .ok_or(ReauthError::Rejected)?;
+    if info.aud.as_str() != client_id || Utc::now() - issued_at > config.window {
+        return Err(ReauthError::Rejected);
+    }
+
+    let account = auth_service.get_account(account_id).await?;
+    let same_account = matches!(account.auth_type, AuthType::Google)
+        && info.email_verified.as_deref() == Some("true")
+        && info
+            .email
+            .as_deref()
+            .is_some_and(|email| email.eq_ignore_ascii_case(&account.email));
+    if !same_account {
+        return Err(ReauthError::Rejected);
+    }
+    Ok(())
+}
+
+/// Guard for sensitive routes: the current session must have re-authenticated
+/// through `POST /api/auth/reauth` within the last few minutes
+pub struct RequireRecentAuth;
+
+impl FromRequest for RequireRecentAuth {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
+        let token = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(sessions::hash_token);
+        let db = req.app_data::<web::Data<DbContext>>().cloned();
+
+        Box::pin(async move {
+            let token_hash = token.ok_or_else(|| actix_web::error::ErrorUnauthorized("User not authenticated"))?;
+            let db = db.ok_or_else(|| actix_web::error::ErrorInternalServerError("Database not configured"))?;
+
+            let session = db
+                .get_session_by_access_token_hash(&token_hash)
+                .await
+                .map_err(|e| {
+                    log::error!("Failed to load session for re-authentication check: {:?}", e);
+                    actix_web::error::ErrorInternalServerError("Failed to check authentication")
+                })?;
+
+            match session {
+                Some(session)
+                    if session.revoked_at.is_none()
+                        && session.elevated_until.is_some_and(|until| until > Utc::now()) =>
+                {
+                    Ok(RequireRecentAuth)
+                }
+                _ => Err(actix_web::error::InternalError::from_response(
+                    "Recent authentication required",
+                    HttpResponse::Forbidden().json(serde_json::json!({
+                        "error": "Please confirm your identity to continue",
+                        "reauth_required": true
+                    })),
+                )
+                .into()),
+            }
+        })
+    }
+}
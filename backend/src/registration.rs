@@ -0,0 +1,173 @@
+use chrono::Duration;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+
+/// Well-known disposable email providers, extended via `DISPOSABLE_DOMAINS_FILE`
+const DISPOSABLE_DOMAINS: &[&str] = &[
+    "10minutemail.com",
+    "dispostable.com",
+    "fakeinbox.com",
+    "getnada.com",
+    "guerrillamail.com",
+    "mailinator.com",
+    "maildrop.cc",
+    "sharklasers.com",
+    "temp-mail.org",
+    "tempmail.com",
+    "throwawaymail.com",
+    "trashmail.com",
+    "yopmail.com",
+];
+
+/// Length of generated invite codes
+const INVITE_CODE_LENGTH: usize = 12;
+
+/// Who may create an account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationMode {
+    /// Anyone can sign up
+    Open,
+    /// Signup requires a valid invite code
+    InviteOnly,
+    /// Signup without an invite code joins a waitlist for admin approval
+    Waitlist,
+}
+
+/// Registration configuration
+#[derive(Clone)]
+pub struct RegistrationConfig {
+    pub mode: RegistrationMode,
+    /// If non-empty, only these email domains may register
+    pub allowed_domains: HashSet<String>,
+    pub denied_domains: HashSet<String>,
+    /// Disposable domains to reject, empty when blocking is disabled
+    pub disposable_domains: HashSet<String>,
+    /// Whether non-admin users may create invite codes
+    pub user_invites_enabled: bool,
+    /// Maximum uses of an invite code created by a non-admin user
+    pub user_invite_max_uses: i32,
+    /// Validity of approval invites sent to waitlisted addresses
+    pub waitlist_invite_ttl: Duration,
+}
+
+impl RegistrationConfig {
+    /// Create registration configuration from environment or defaults
+    pub fn from_env() -> Self {
+        let mode = match env::var("REGISTRATION_MODE").unwrap_or_default().as_str() {
+            "invite" | "invite_only" => RegistrationMode::InviteOnly,
+            "waitlist" => RegistrationMode::Waitlist,
+            "" | "open" => RegistrationMode::Open,
+            other => {
+                log::warn!("Unknown REGISTRATION_MODE '{}', using open registration", other);
+                RegistrationMode::Open
+            }
+        };
+
+        let mut disposable_domains = HashSet::new();
+        if env_flag("REGISTRATION_BLOCK_DISPOSABLE") {
+            disposable_domains.extend(DISPOSABLE_DOMAINS.iter().map(|d| d.to_string()));
+            if let Ok(path) = env::var("DISPOSABLE_DOMAINS_FILE") {
+                match fs::read_to_string(&path) {
+                    Ok(contents) => disposable_domains.extend(
+                        contents
+                            .lines()
+                            .map(|l| l.trim().to_lowercase())
+                            .filter(|l| !l.is_empty() && !l.starts_with('#')),
+                    ),
+                    Err(e) => log::error!("Failed to read disposable domains file {}: {}", path, e),
+                }
+            }
+        }
+
+        Self {
+            mode,
+            allowed_domains: domain_list("REGISTRATION_ALLOWED_DOMAINS"),
+            denied_domains: domain_list("REGISTRATION_DENIED_DOMAINS"),
+            disposable_domains,
+            user_invites_enabled: env::var("USER_INVITES_ENABLED")
+                .map(|v| v != "0" && v != "false")
+                .unwrap_or(true),
+            user_invite_max_uses: env::var("USER_INVITE_MAX_USES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1),
+            waitlist_invite_ttl: Duration::days(
+                env::var("WAITLIST_INVITE_TTL_DAYS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(14),
+            ),
+        }
+    }
+
+    /// Check the email's domain against the allow, deny and disposable lists
+    pub fn check_email_domain(&self, email: &str) -> Result<(), &'static str> {
+        let domain = match email.rsplit_once('@') {
+            Some((_, domain)) if !domain.is_empty() => domain.trim().to_lowercase(),
+            _ => return Err("Invalid email address"),
+        };
+
+        if !self.allowed_domains.is_empty() && !matches_domain(&self.allowed_domains, &domain) {
+            return Err("Registration is not available for this email domain");
+        }
+        if matches_domain(&self.denied_domains, &domain) {
+            return Err("Registration is not available for this email domain");
+        }
+        if matches_domain(&self.disposable_domains, &domain) {
+            return Err("Disposable email addresses are not allowed");
+        }
+        Ok(())
+    }
+}
+
+/// Generate a random invite code
+pub fn generate_invite_code() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(INVITE_CODE_LENGTH)
+        .map(char::from)
+        .collect::<String>()
+        .to_uppercase()
+}
+
+/// Body of the email sent when a waitlisted address is approved
+pub fn approval_email_body(code: &str, frontend_url: &str) -> String {
+    format!(
+        "Good news! Your spot on the waitlist has been approved.\n\n\
+         Create your account at {}/signup?invite_code={}\n\
+         or enter this invite code when signing up: {}",
+        frontend_url, code, code
+    )
+}
+
+/// Match a domain or any of its subdomains against a set
+fn matches_domain(domains: &HashSet<String>, domain: &str) -> bool {
+    let mut candidate = domain;
+    loop {
+        if domains.contains(candidate) {
+            return true;
+        }
+        match candidate.split_once('.') {
+            Some((_, parent)) if parent.contains('.') => candidate = parent,
+            _ => return false,
+        }
+    }
+}
+
+fn domain_list(var: &str) -> HashSet<String> {
+    env::var(var)
+        .unwrap_or_default()
+        .split(',')
+        .map(|d| d.trim().to_lowercase())
+        .filter(|d| !d.is_empty())
+        .collect()
+}
+
+fn env_flag(var: &str) -> bool {
+    env::var(var)
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
@@ -0,0 +1,152 @@
+use chrono::{Duration, Utc};
+use std::env;
+
+use crate::dba::DbContext;
+use crate::notifications;
+
+/// Rules applied when `NOTIFICATION_RETENTION_RULES` is unset: read info
+/// notifications go after 30 days, archived ones after 180, any read one after
+/// a year. Unread notifications are kept until they expire.
+const DEFAULT_RULES: &str = "level=info,read=true,days=30;archived=true,days=180;read=true,days=365";
+
+/// Notifications a retention rule deletes once they are older than `max_age`.
+/// Unset fields match any notification.
+#[derive(Debug, Clone)]
+pub struct RetentionRule {
+    pub kind: Option<String>,
+    pub level: Option<String>,
+    pub read: Option<bool>,
+    pub archived: Option<bool>,
+    pub max_age: Duration,
+}
+
+impl RetentionRule {
+    /// Parse a rule written as `key=value` pairs separated by commas, e.g.
+    /// `level=info,read=true,days=30`
+    fn parse(spec: &str) -> Result<Self, String> {
+        let mut rule = Self {
+            kind: None,
+            level: None,
+            read: None,
+            archived: None,
+            max_age: Duration::zero(),
+        };
+        let mut days = None;
+
+        for pair in spec.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got '{}'", pair))?;
+            let value = value.trim();
+            match key.trim() {
+                "kind" if notifications::kind(value).is_some() => rule.kind = Some(value.to_string()),
+                "level" if notifications::is_valid_level(value) => rule.level = Some(value.to_string()),
+                "read" => rule.read = Some(parse_bool(value)?),
+                "archived" => rule.archived = Some(parse_bool(value)?),
+                "days" => {
+                    days = Some(
+                        value
+                            .parse::<i64>()
+                            .ok()
+                            .filter(|days| *days > 0)
+                            .ok_or_else(|| format!("invalid days '{}'", value))?,
+                    )
+                }
+                key => return Err(format!("invalid {} '{}'", key, value)),
+            }
+        }
+
+        rule.max_age = Duration::days(days.ok_or("missing days")?);
+        Ok(rule)
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    value.parse().map_err(|_| format!("expected true or false, got '{}'", value))
+}
+
+/// Notification retention configuration
+#[derive(Clone)]
+pub struct RetentionConfig {
+    /// A notification is deleted once it is older than the shortest
+    /// `max_age` among the rules it matches
+    pub rules: Vec<RetentionRule>,
+    /// Notifications deleted per statement
+    pub batch_size: i64,
+}
+
+impl RetentionConfig {
+    /// Create retention configuration from environment or defaults.
+    ///
+    /// `NOTIFICATION_RETENTION_RULES` holds rules separated by semicolons; set it
+    /// to an empty string to keep notifications until they expire.
+    pub fn from_env() -> Self {
+        let value = env::var("NOTIFICATION_RETENTION_RULES").unwrap_or_else(|_| DEFAULT_RULES.to_string());
+        let rules = value
+            .split(';')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| match RetentionRule::parse(s) {
+                Ok(rule) => Some(rule),
+                Err(e) => {
+                    log::error!("Ignoring invalid NOTIFICATION_RETENTION_RULES entry '{}': {}", s, e);
+                    None
+                }
+            })
+            .collect();
+
+        Self {
+            rules,
+            batch_size: env::var("NOTIFICATION_RETENTION_BATCH_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1000),
+        }
+    }
+}
+
+/// Delete expired notifications and those past their retention period
+pub async fn purge_notifications(db: &DbContext, config: &RetentionConfig) {
+    let now = Utc::now();
+
+    let expired = purge(db, config, "expired", None, now).await;
+    let mut retained = 0;
+    for rule in &config.rules {
+        retained += purge(db, config, "past retention", Some(rule), now - rule.max_age).await;
+    }
+
+    if expired + retained > 0 {
+        log::info!(
+            "Purged {} notification(s): {} expired, {} past retention",
+            expired + retained,
+            expired,
+            retained
+        );
+    }
+}
+
+/// Delete in batches until a batch comes back short, so one statement never
+/// holds locks on too many rows
+async fn purge(
+    db: &DbContext,
+    config: &RetentionConfig,
+    reason: &str,
+    rule: Option<&RetentionRule>,
+    before: chrono::DateTime<Utc>,
+) -> u64 {
+    let mut total = 0;
+    loop {
+        match db.purge_notifications_batch(rule, before, config.batch_size).await {
+            Ok(count) => {
+                total += count;
+                if count < config.batch_size as u64 {
+                    return total;
+                }
+            }
+            Err(e) => {
+                log::error!("Failed to purge {} notifications: {:?}", reason, e);
+                return total;
+            }
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+use std::env;
+
+use crate::dba::DbContext;
+use crate::models::{Account, Session};
+use crate::token_cache::TokenCache;
+
+/// Timeouts applied to one session
+#[derive(Debug, Clone, Copy)]
+pub struct SessionPolicy {
+    pub remember_me: bool,
+    /// How long the session may go without a request or refresh before it ends
+    pub idle_timeout: Duration,
+    /// How long the session lasts in total, however often it is refreshed
+    pub lifetime: Duration,
+}
+
+/// Session lifetime configuration
+#[derive(Clone)]
+pub struct SessionConfig {
+    /// Policy for ordinary sign-ins
+    pub default_policy: SessionPolicy,
+    /// Policy for sign-ins with "remember me" checked
+    pub remember_policy: SessionPolicy,
+    /// Maximum live sessions per account, 0 for no limit
+    pub max_sessions: i64,
+    /// How stale `last_seen_at` may get before an authenticated request updates it
+    pub touch_interval: Duration,
+}
+
+impl SessionConfig {
+    /// Create session configuration from environment or defaults
+    pub fn from_env() -> Self {
+        let idle_timeout_secs = env::var("SESSION_IDLE_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(12 * 3600);
+        let lifetime_secs = env::var("SESSION_LIFETIME_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(24 * 3600);
+        let remember_idle_timeout_secs = env::var("SESSION_REMEMBER_IDLE_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(14 * 24 * 3600);
+        let remember_lifetime_secs = env::var("SESSION_REMEMBER_LIFETIME_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(90 * 24 * 3600);
+        Self {
+            default_policy: SessionPolicy {
+                remember_me: false,
+                idle_timeout: Duration::seconds(idle_timeout_secs),
+                lifetime: Duration::seconds(lifetime_secs),
+            },
+            remember_policy: SessionPolicy {
+                remember_me: true,
+                idle_timeout: Duration::seconds(remember_idle_timeout_secs),
+                lifetime: Duration::seconds(remember_lifetime_secs),
+            },
+            max_sessions: env::var("MAX_SESSIONS_PER_ACCOUNT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(10),
+            touch_interval: Duration::seconds(
+                env::var("SESSION_TOUCH_INTERVAL_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(60),
+            ),
+        }
+    }
+
+    pub fn policy(&self, remember_me: bool) -> SessionPolicy {
+        if remember_me {
+            self.remember_policy
+        } else {
+            self.default_policy
+        }
+    }
+
+    /// When a session ends unless it is used again: the earlier of its idle
+    /// deadline and its absolute expiry
+    pub fn valid_until(&self, session: &Session) -> DateTime<Utc> {
+        let idle_deadline = session.last_seen_at + self.policy(session.remember_me).idle_timeout;
+        match session.expires_at {
+            Some(expires_at) => expires_at.min(idle_deadline),
+            None => idle_deadline,
+        }
+    }
+
+    /// Whether a session has been idle too long or outlived its lifetime.
+    ///
+    /// Checked by `auth::authenticate` on every request that reaches the
+    /// database and by the refresh endpoint; cached tokens are only cached
+    /// until `valid_until`, so a cache hit never outlives the session either.
+    pub fn is_expired(&self, session: &Session) -> bool {
+        self.valid_until(session) <= Utc::now()
+    }
+
+    /// Whether `last_seen_at` is stale enough to be worth writing again
+    pub fn needs_touch(&self, session: &Session) -> bool {
+        session.last_seen_at + self.touch_interval <= Utc::now()
+    }
+}
+
+/// Hex SHA-256 of a token, the form in which tokens are stored in `sessions`
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Sign out the oldest sessions of an account beyond the concurrent session cap
+pub async fn enforce_session_limit(
+    db: &DbContext,
+    config: &SessionConfig,
+    token_cache: &TokenCache,
+    account: &Account,
+) {
+    if config.max_sessions <= 0 {
+        return;
+    }
+
+    match db.revoke_oldest_sessions(account.id, config.max_sessions).await {
+        Ok(evicted) if evicted.is_empty() => {}
+        Ok(evicted) => {
+            log::info!(
+                "Evicted {} session(s) of account {} over the limit of {}",
+                evicted.len(),
+                account.iam_account_id,
+                config.max_sessions
+            );
+            token_cache.invalidate_account(account.iam_account_id).await;
+        }
+        Err(e) => log::error!("Failed to enforce session limit: {:?}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn config() -> SessionConfig {
+        SessionConfig {
+            default_policy: SessionPolicy {
+                remember_me: false,
+                idle_timeout: Duration::hours(1),
+                lifetime: Duration::hours(24),
+            },
+            remember_policy: SessionPolicy {
+                remember_me: true,
+                idle_timeout: Duration::days(14),
+                lifetime: Duration::days(90),
+            },
+            max_sessions: 10,
+            touch_interval: Duration::seconds(60),
+        }
+    }
+
+    fn session(last_seen_ago: Duration, expires_in: Option<Duration>, remember_me: bool) -> Session {
+        let now = Utc::now();
+        Session {
+            id: Uuid::new_v4(),
+            account_id: Uuid::new_v4(),
+            device_id: None,
+            ip_address: None,
+            user_agent: None,
+            location: None,
+            created_at: now - Duration::hours(2),
+            last_seen_at: now - last_seen_ago,
+            revoked_at: None,
+            elevated_until: None,
+            remember_me,
+            expires_at: expires_in.map(|d| now + d),
+            access_token_expires_at: None,
+        }
+    }
+
+    #[test]
+    fn active_session_is_not_expired() {
+        assert!(!config().is_expired(&session(Duration::minutes(5), Some(Duration::hours(20)), false)));
+    }
+
+    #[test]
+    fn idle_session_expires() {
+        assert!(config().is_expired(&session(Duration::minutes(61), Some(Duration::hours(20)), false)));
+    }
+
+    #[test]
+    fn remember_me_uses_longer_idle_timeout() {
+        assert!(!config().is_expired(&session(Duration::minutes(61), Some(Duration::days(60)), true)));
+    }
+
+    #[test]
+    fn session_expires_at_lifetime_even_when_active() {
+        assert!(config().is_expired(&session(Duration::seconds(1), Some(Duration::seconds(-1)), false)));
+    }
+
+    #[test]
+    fn valid_until_is_earlier_of_idle_deadline_and_lifetime() {
+        let config = config();
+        let idle_first = session(Duration::minutes(30), Some(Duration::hours(20)), false);
+        assert_eq!(config.valid_until(&idle_first), idle_first.last_seen_at + Duration::hours(1));
+
+        let lifetime_first = session(Duration::zero(), Some(Duration::minutes(10)), false);
+        assert_eq!(config.valid_until(&lifetime_first), lifetime_first.expires_at.unwrap());
+    }
+
+    #[test]
+    fn touch_is_throttled() {
+        let config = config();
+        assert!(!config.needs_touch(&session(Duration::seconds(10), None, false)));
+        assert!(config.needs_touch(&session(Duration::seconds(61), None, false)));
+    }
+}
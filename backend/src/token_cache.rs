@@ -0,0 +1,296 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::postgres::PgListener;
+use std::collections::HashMap;
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::auth::AuthenticatedUser;
+use crate::dba::DbContext;
+use crate::models::Account;
+
+/// Postgres channel used to broadcast invalidations to all replicas
+pub const INVALIDATION_CHANNEL: &str = "auth_invalidation";
+
+type TokenHash = [u8; 32];
+
+/// Token cache configuration
+pub struct TokenCacheConfig {
+    /// How long a validated token is trusted without asking nano-iam again, 0 to disable
+    pub ttl: Duration,
+    pub max_entries: usize,
+}
+
+impl TokenCacheConfig {
+    /// Create token cache configuration from environment or defaults
+    pub fn from_env() -> Self {
+        let ttl_secs = env::var("TOKEN_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(60);
+        let max_entries = env::var("TOKEN_CACHE_MAX_ENTRIES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(10_000);
+        Self {
+            ttl: Duration::from_secs(ttl_secs),
+            max_entries,
+        }
+    }
+}
+
+/// The result of authenticating an access token
+#[derive(Clone)]
+pub struct Principal {
+    pub user: AuthenticatedUser,
+    /// The app account, if one exists yet
+    pub account: Option<Account>,
+}
+
+struct CacheEntry {
+    principal: Principal,
+    expires_at: Instant,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenCacheStats {
+    pub enabled: bool,
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub invalidations: u64,
+    pub hit_ratio: f64,
+}
+
+/// In-process cache of validated access tokens
+pub struct TokenCache {
+    db: DbContext,
+    config: TokenCacheConfig,
+    entries: RwLock<HashMap<TokenHash, CacheEntry>>,
+    /// Bumped on every invalidation so lookups that raced with one are not cached
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+impl TokenCache {
+    pub fn new(db: DbContext, config: TokenCacheConfig) -> Self {
+        Self {
+            db,
+            config,
+            entries: RwLock::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        !self.config.ttl.is_zero()
+    }
+
+    /// Current generation, to be passed back to `insert`
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    pub fn get(&self, token: &str) -> Option<Principal> {
+        if !self.enabled() {
+            return None;
+        }
+
+        let entries = self.entries.read().unwrap_or_else(|e| e.into_inner());
+        match entries.get(&hash_token(token)) {
+            Some(entry) if entry.expires_at > Instant::now() => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(entry.principal.clone())
+            }
+            _ => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Cache a principal, unless an invalidation happened since `generation` was read.
+    /// The entry never outlives `valid_until`, e.g. the token's own expiry.
+    pub fn insert(
+        &self,
+        token: &str,
+        principal: Principal,
+        generation: u64,
+        valid_until: Option<DateTime<Utc>>,
+    ) {
+        if !self.enabled() {
+            return;
+        }
+
+        let ttl = match valid_until {
+            Some(valid_until) => match (valid_until - Utc::now()).to_std() {
+                Ok(remaining) => remaining.min(self.config.ttl),
+                Err(_) => return,
+            },
+            None => self.config.ttl,
+        };
+
+        let mut entries = self.entries.write().unwrap_or_else(|e| e.into_inner());
+        if self.generation() != generation {
+            return;
+        }
+
+        if entries.len() >= self.config.max_entries {
+            let now = Instant::now();
+            entries.retain(|_, entry| entry.expires_at > now);
+            if entries.len() >= self.config.max_entries {
+                entries.clear();
+            }
+        }
+
+        entries.insert(
+            hash_token(token),
+            CacheEntry {
+                principal,
+                expires_at: Instant::now() + ttl,
+            },
+        );
+    }
+
+    /// Drop a single token on every replica
+    pub async fn invalidate_token(&self, token: &str) {
+        let token_hash = hex::encode(hash_token(token));
+        self.apply(&Invalidation::Token(token_hash.clone()));
+        self.publish(serde_json::json!({ "token_hash": token_hash })).await;
+    }
+
+    /// Drop every token of an account on every replica
+    pub async fn invalidate_account(&self, iam_account_id: Uuid) {
+        self.apply(&Invalidation::Account(iam_account_id));
+        self.publish(serde_json::json!({ "account_id": iam_account_id })).await;
+    }
+
+    pub fn stats(&self) -> TokenCacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let entries = self.entries.read().unwrap_or_else(|e| e.into_inner()).len();
+        TokenCacheStats {
+            enabled: self.enabled(),
+            entries,
+            hits,
+            misses,
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+            hit_ratio: if hits + misses == 0 {
+                0.0
+            } else {
+                hits as f64 / (hits + misses) as f64
+            },
+        }
+    }
+
+    async fn publish(&self, payload: serde_json::Value) {
+        if let Err(e) = self
+            .db
+            .notify(INVALIDATION_CHANNEL, &payload.to_string())
+            .await
+        {
+            log::error!("Failed to publish token cache invalidation: {:?}", e);
+        }
+    }
+
+    fn apply(&self, invalidation: &Invalidation) {
+        let mut entries = self.entries.write().unwrap_or_else(|e| e.into_inner());
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.invalidations.fetch_add(1, Ordering::Relaxed);
+
+        match invalidation {
+            Invalidation::Token(token_hash) => {
+                if let Some(key) = hex::decode(token_hash)
+                    .ok()
+                    .and_then(|bytes| TokenHash::try_from(bytes).ok())
+                {
+                    entries.remove(&key);
+                }
+            }
+            Invalidation::Account(account_id) => {
+                entries.retain(|_, entry| entry.principal.user.account_id != *account_id);
+            }
+            Invalidation::All => entries.clear(),
+        }
+    }
+}
+
+enum Invalidation {
+    Token(String),
+    Account(Uuid),
+    All,
+}
+
+impl Invalidation {
+    fn parse(payload: &str) -> Option<Self> {
+        let value: serde_json::Value = serde_json::from_str(payload).ok()?;
+        if let Some(token_hash) = value.get("token_hash").and_then(|v| v.as_str()) {
+            return Some(Invalidation::Token(token_hash.to_string()));
+        }
+        value
+            .get("account_id")
+            .and_then(|v| v.as_str())
+            .and_then(|s| Uuid::parse_str(s).ok())
+            .map(Invalidation::Account)
+    }
+}
+
+fn hash_token(token: &str) -> TokenHash {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+/// Apply invalidations published by any replica to this replica's cache
+pub fn spawn_listener(cache: Arc<TokenCache>) {
+    if !cache.enabled() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        loop {
+            let mut listener = match PgListener::connect_with(cache.db.pool()).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    log::error!("Failed to connect token cache listener: {:?}", e);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    continue;
+                }
+            };
+            if let Err(e) = listener.listen(INVALIDATION_CHANNEL).await {
+                log::error!("Failed to listen for token cache invalidations: {:?}", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+
+            // Anything cached before we were listening may have missed an invalidation
+            cache.apply(&Invalidation::All);
+
+            loop {
+                match listener.try_recv().await {
+                    Ok(Some(notification)) => match Invalidation::parse(notification.payload()) {
+                        Some(invalidation) => cache.apply(&invalidation),
+                        None => log::warn!(
+                            "Ignoring malformed token cache invalidation: {}",
+                            notification.payload()
+                        ),
+                    },
+                    // Connection lost; notifications may have been missed
+                    Ok(None) => cache.apply(&Invalidation::All),
+                    Err(e) => {
+                        log::error!("Token cache listener error: {:?}", e);
+                        cache.apply(&Invalidation::All);
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,102 @@
+/// Shortest accepted username
+const MIN_LENGTH: usize = 3;
+/// Longest accepted username
+const MAX_LENGTH: usize = 32;
+
+/// Names that could be mistaken for the service itself or collide with routes.
+/// Migration 112 repeats this list to clear existing reserved usernames.
+const RESERVED_NAMES: &[&str] = &[
+    "admin", "administrator", "root", "system", "support", "help", "security", "staff",
+    "moderator", "owner", "official", "api", "www", "mail", "email", "postmaster",
+    "abuse", "noreply", "no-reply", "webmaster", "me", "settings", "account", "accounts",
+    "dashboard", "signin", "signup", "login", "logout", "register", "verify", "null",
+    "undefined", "anonymous", "everyone",
+];
+
+/// Canonical form in which usernames are stored and compared
+pub fn normalize(username: &str) -> String {
+    username.trim().to_lowercase()
+}
+
+/// Normalize a username and check it against the allowed character set and
+/// reserved names. Usernames never contain `@`, so they can't be confused
+/// with an email address at sign-in.
+pub fn validate(username: &str) -> Result<String, &'static str> {
+    let username = normalize(username);
+
+    if username.is_empty() {
+        return Err("Username cannot be empty");
+    }
+    let length = username.chars().count();
+    if length < MIN_LENGTH {
+        return Err("Username must be at least 3 characters");
+    }
+    if length > MAX_LENGTH {
+        return Err("Username must be 32 characters or less");
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '-' | '.'))
+    {
+        return Err("Username may only contain letters, digits, '_', '-' and '.'");
+    }
+    if !username.starts_with(|c: char| c.is_ascii_alphanumeric())
+        || !username.ends_with(|c: char| c.is_ascii_alphanumeric())
+    {
+        return Err("Username must start and end with a letter or digit");
+    }
+    if RESERVED_NAMES.contains(&username.as_str()) {
+        return Err("This username is reserved");
+    }
+
+    Ok(username)
+}
+
+/// Whether a sign-in identifier is an email address rather than a username
+pub fn is_email(identifier: &str) -> bool {
+    identifier.contains('@')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_valid_usernames() {
+        assert_eq!(validate("  Alice.Smith "), Ok("alice.smith".to_string()));
+        assert_eq!(validate("bob_99"), Ok("bob_99".to_string()));
+        assert_eq!(validate("a-b"), Ok("a-b".to_string()));
+    }
+
+    #[test]
+    fn rejects_reserved_names() {
+        assert!(validate("admin").is_err());
+        assert!(validate("Admin").is_err());
+        assert!(validate("no-reply").is_err());
+        assert!(validate("admins").is_ok());
+    }
+
+    #[test]
+    fn rejects_characters_outside_the_allowed_set() {
+        assert!(validate("alice@example.com").is_err());
+        assert!(validate("alice smith").is_err());
+        assert!(validate("élise").is_err());
+        assert!(validate("_alice").is_err());
+        assert!(validate("alice.").is_err());
+    }
+
+    #[test]
+    fn enforces_length_bounds() {
+        assert!(validate("").is_err());
+        assert!(validate("ab").is_err());
+        assert!(validate("abc").is_ok());
+        assert!(validate(&"a".repeat(MAX_LENGTH)).is_ok());
+        assert!(validate(&"a".repeat(MAX_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn detects_emails() {
+        assert!(is_email("alice@example.com"));
+        assert!(!is_email("alice"));
+    }
+}
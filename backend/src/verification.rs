@@ -0,0 +1,129 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use std::env;
+
+use crate::dba::DbContext;
+use crate::mailer;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Email verification configuration
+#[derive(Clone)]
+pub struct VerificationConfig {
+    /// Wrong tries a code survives; after that it no longer verifies and a new
+    /// one has to be requested
+    pub max_attempts: i32,
+    /// HMAC key for signing verification links; must be shared by all replicas
+    secret: Vec<u8>,
+    /// How long a verification link is valid, matching the code TTL
+    pub link_ttl: Duration,
+}
+
+impl VerificationConfig {
+    /// Create verification configuration from environment or defaults
+    pub fn from_env(link_ttl: Duration) -> Self {
+        let secret = match env::var("VERIFICATION_LINK_SECRET") {
+            Ok(secret) if !secret.is_empty() => secret.into_bytes(),
+            _ => {
+                log::warn!("VERIFICATION_LINK_SECRET not set, using a random key; verification links will not be valid across replicas or restarts");
+                let mut key = vec![0u8; 32];
+                rand::thread_rng().fill_bytes(&mut key);
+                key
+            }
+        };
+
+        Self {
+            max_attempts: env::var("VERIFICATION_MAX_ATTEMPTS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(5),
+            secret,
+            link_ttl,
+        }
+    }
+
+    /// Build a signed link that verifies `email` with `code` when opened
+    pub fn link(&self, email: &str, code: &str) -> String {
+        let expires_at = Utc::now() + self.link_ttl;
+        let payload = format!("{}.{}.{}", hex::encode(email), code, expires_at.timestamp());
+        let signature = hex::encode(self.sign(&payload));
+        format!(
+            "{}/api/auth/verify-email/link?token={}.{}",
+            mailer::frontend_url(),
+            payload,
+            signature
+        )
+    }
+
+    /// Check a link token's signature and expiry
+    pub fn parse_link_token(&self, token: &str) -> Result<LinkToken, LinkError> {
+        let (payload, signature) = token.rsplit_once('.').ok_or(LinkError::Invalid)?;
+        let signature = hex::decode(signature).map_err(|_| LinkError::Invalid)?;
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature).map_err(|_| LinkError::Invalid)?;
+
+        let parts: Vec<&str> = payload.split('.').collect();
+        let [email, code, expires_at] = parts[..] else {
+            return Err(LinkError::Invalid);
+        };
+        let expires_at: DateTime<Utc> = expires_at
+            .parse::<i64>()
+            .ok()
+            .and_then(|ts| Utc.timestamp_opt(ts, 0).single())
+            .ok_or(LinkError::Invalid)?;
+        if expires_at <= Utc::now() {
+            return Err(LinkError::Expired);
+        }
+
+        let email = hex::decode(email)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or(LinkError::Invalid)?;
+        Ok(LinkToken {
+            email,
+            code: code.to_string(),
+            expires_at,
+        })
+    }
+
+    fn sign(&self, payload: &str) -> Vec<u8> {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(payload.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+/// Contents of a valid verification link
+pub struct LinkToken {
+    pub email: String,
+    pub code: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum LinkError {
+    Invalid,
+    Expired,
+}
+
+/// Frontend page the verification link redirects to, carrying the outcome
+pub fn result_url(status: &str) -> String {
+    format!("{}/verify?status={}", mailer::frontend_url(), status)
+}
+
+/// Forget used verification links that can no longer be replayed, and
+/// attempt counters for codes that have expired
+pub async fn clear_expired_links(db: &DbContext, config: &VerificationConfig) {
+    if let Err(e) = db.delete_expired_verification_links().await {
+        log::error!("Failed to clear expired verification links: {:?}", e);
+    }
+    if let Err(e) = db
+        .delete_stale_verification_attempts(Utc::now() - config.link_ttl)
+        .await
+    {
+        log::error!("Failed to clear stale verification attempts: {:?}", e);
+    }
+}
@@ -5,7 +5,7 @@ import { Container, Row, Col, Card } from "react-bootstrap";
 import { useAuth } from "@/contexts/auth-context";
 import { useRouter } from "next/navigation";
 import DashboardSidebar from "@/components/dashboard-sidebar";
-import { getUnreadCount } from "@/backend/notifications";
+import { getUnreadCount, subscribeToNotifications } from "@/backend/notifications";
 import { useI18n } from "@/contexts/i18n-context";
 
 export default function DashboardPage() {
@@ -27,6 +27,14 @@ export default function DashboardPage() {
     }
   }, [user, tokens]);
 
+  // Keep the unread count live instead of polling
+  useEffect(() => {
+    if (!user || !tokens?.access_token) return;
+    return subscribeToNotifications(tokens.access_token, (event) => {
+      setUnreadCount(event.data.unread_count);
+    });
+  }, [user, tokens]);
+
   const loadUnreadCount = async () => {
     if (!tokens?.access_token) return;
 
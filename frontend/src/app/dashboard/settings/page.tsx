@@ -6,17 +6,25 @@ import { useAuth } from "@/contexts/auth-context";
 import { useRouter } from "next/navigation";
 import DashboardSidebar from "@/components/dashboard-sidebar";
 import AccountSettings from "@/components/account-settings";
+import { useReauth } from "@/components/reauth-modal";
 import {
   AccountSettings as AccountSettingsType,
   getAccountSettings,
   updateAccountSettings,
 } from "@/backend/account-settings";
+import {
+  DigestSettings,
+  getNotificationPreferences,
+  NotificationPreferences,
+  updateNotificationPreferences,
+} from "@/backend/notifications";
 import { useI18n } from "@/contexts/i18n-context";
 
 export default function AccountSettingsPage() {
   const { user, tokens, loading } = useAuth();
   const router = useRouter();
   const { t } = useI18n();
+  const { withReauth, reauthModal } = useReauth();
   const [settings, setSettings] = useState<AccountSettingsType | null>(null);
   const [loadingSettings, setLoadingSettings] = useState(true);
   const [saving, setSaving] = useState(false);
@@ -25,6 +33,12 @@ export default function AccountSettingsPage() {
   const [formData, setFormData] = useState({
     username: "",
   });
+  const [preferences, setPreferences] = useState<NotificationPreferences | null>(null);
+  const [preferencesForm, setPreferencesForm] = useState({
+    webhookUrl: "",
+    digestFrequency: "off" as DigestSettings["frequency"],
+  });
+  const [savingPreferences, setSavingPreferences] = useState(false);
 
   useEffect(() => {
     if (!loading && !user) {
@@ -35,6 +49,7 @@ export default function AccountSettingsPage() {
   useEffect(() => {
     if (user && tokens?.access_token) {
       loadSettings();
+      loadPreferences();
     }
   }, [user, tokens]);
 
@@ -56,6 +71,49 @@ export default function AccountSettingsPage() {
     }
   };
 
+  const loadPreferences = async () => {
+    if (!tokens?.access_token) return;
+
+    try {
+      const data = await getNotificationPreferences(tokens.access_token);
+      setPreferences(data);
+      setPreferencesForm({
+        webhookUrl: data.webhook_url || "",
+        digestFrequency: data.digest.frequency,
+      });
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "Failed to load notification preferences");
+    }
+  };
+
+  const handlePreferencesSubmit = async (e: React.FormEvent) => {
+    e.preventDefault();
+    if (!tokens?.access_token || !preferences) return;
+
+    try {
+      setSavingPreferences(true);
+      setError(null);
+      setSuccess(null);
+
+      const updated = await withReauth(() =>
+        updateNotificationPreferences(
+          {
+            rules: preferences.rules,
+            webhook_url: preferencesForm.webhookUrl.trim() || null,
+            digest: { ...preferences.digest, frequency: preferencesForm.digestFrequency },
+          },
+          tokens.access_token
+        )
+      );
+      setPreferences(updated);
+      setSuccess(t("settings.notificationsSaved"));
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "Failed to update notification preferences");
+    } finally {
+      setSavingPreferences(false);
+    }
+  };
+
   const handleSubmit = async (e: React.FormEvent) => {
     e.preventDefault();
     if (!tokens?.access_token) return;
@@ -72,7 +130,8 @@ export default function AccountSettingsPage() {
         updateData.username = null;
       }
 
-      const updated = await updateAccountSettings(updateData, tokens.access_token);
+      // Changing settings needs a recent sign-in; the modal confirms it and retries
+      const updated = await withReauth(() => updateAccountSettings(updateData, tokens.access_token));
       setSettings(updated);
       setSuccess("Account settings updated successfully!");
     } catch (err) {
@@ -143,7 +202,7 @@ export default function AccountSettingsPage() {
                         onChange={(e) =>
                           setFormData({ ...formData, username: e.target.value })
                         }
-                        maxLength={255}
+                        maxLength={32}
                       />
                       <Form.Text className="text-muted">
                         {t("settings.usernameHint")}
@@ -172,6 +231,50 @@ export default function AccountSettingsPage() {
               </Card>
             )}
 
+            {preferences && (
+              <Card className="border-0 shadow-sm mt-4">
+                <Card.Header className="bg-white">
+                  <h5 className="mb-0">{t("settings.notificationSettings")}</h5>
+                </Card.Header>
+                <Card.Body>
+                  <Form onSubmit={handlePreferencesSubmit}>
+                    <Form.Group className="mb-3">
+                      <Form.Label>{t("settings.digestFrequency")}</Form.Label>
+                      <Form.Select
+                        value={preferencesForm.digestFrequency}
+                        onChange={(e) =>
+                          setPreferencesForm({
+                            ...preferencesForm,
+                            digestFrequency: e.target.value as DigestSettings["frequency"],
+                          })
+                        }
+                      >
+                        <option value="off">{t("settings.digestOff")}</option>
+                        <option value="daily">{t("settings.digestDaily")}</option>
+                        <option value="weekly">{t("settings.digestWeekly")}</option>
+                      </Form.Select>
+                    </Form.Group>
+
+                    <Form.Group className="mb-3">
+                      <Form.Label>{t("settings.webhookUrl")}</Form.Label>
+                      <Form.Control
+                        type="url"
+                        placeholder="https://"
+                        value={preferencesForm.webhookUrl}
+                        onChange={(e) =>
+                          setPreferencesForm({ ...preferencesForm, webhookUrl: e.target.value })
+                        }
+                      />
+                    </Form.Group>
+
+                    <Button type="submit" variant="primary" disabled={savingPreferences}>
+                      {savingPreferences ? t("settings.saving") : t("settings.saveChanges")}
+                    </Button>
+                  </Form>
+                </Card.Body>
+              </Card>
+            )}
+
             <Card className="border-0 shadow-sm mt-4">
               <Card.Header className="bg-white">
                 <h5 className="mb-0">Account Information</h5>
@@ -183,6 +286,7 @@ export default function AccountSettingsPage() {
           </Container>
         </Col>
       </Row>
+      {reauthModal}
     </div>
   );
 }
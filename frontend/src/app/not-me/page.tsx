@@ -0,0 +1,97 @@
+"use client";
+
+import { useState, Suspense } from "react";
+import { Container, Card, Button, Alert } from "react-bootstrap";
+import { useSearchParams } from "next/navigation";
+import Link from "next/link";
+import { reportUnrecognizedLogin } from "@/backend/account";
+
+function NotMeContent() {
+  const [error, setError] = useState("");
+  const [message, setMessage] = useState("");
+  const [resetRequired, setResetRequired] = useState(false);
+  const [loading, setLoading] = useState(false);
+  const searchParams = useSearchParams();
+  const token = searchParams.get("token");
+
+  const handleReport = async () => {
+    if (!token) return;
+    setError("");
+    setLoading(true);
+
+    try {
+      const result = await reportUnrecognizedLogin(token);
+      setMessage(result.message);
+      setResetRequired(result.password_reset_required);
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "Failed to secure account");
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  return (
+    <div className="min-h-screen d-flex align-items-center bg-light py-5">
+      <Container className="max-w-md">
+        <Card className="shadow">
+          <Card.Body className="p-5">
+            <div className="text-center mb-4">
+              <h2 className="fw-bold">Secure Your Account</h2>
+              <p className="text-muted">
+                If you didn&apos;t sign in from the device in the alert, sign out every
+                session now. If you sign in with a password, you will then need to
+                choose a new one with the code we email you.
+              </p>
+            </div>
+
+            {!token && <Alert variant="danger">This link is missing its security token.</Alert>}
+            {error && <Alert variant="danger">{error}</Alert>}
+            {message && <Alert variant="success">{message}</Alert>}
+
+            {!message && (
+              <Button
+                variant="danger"
+                className="w-100 mb-3"
+                onClick={handleReport}
+                disabled={loading || !token}
+              >
+                {loading ? "Signing out..." : "This wasn't me"}
+              </Button>
+            )}
+
+            <hr className="my-4" />
+
+            <div className="text-center">
+              <p className="mb-0">
+                <Link
+                  href={resetRequired ? "/reset-password" : "/signin"}
+                  className="text-decoration-none"
+                >
+                  {resetRequired ? "Choose a New Password" : "Go to Sign In"}
+                </Link>
+              </p>
+            </div>
+          </Card.Body>
+        </Card>
+      </Container>
+    </div>
+  );
+}
+
+export default function NotMePage() {
+  return (
+    <Suspense fallback={
+      <div className="min-h-screen d-flex align-items-center bg-light py-5">
+        <Container className="max-w-md">
+          <Card className="shadow">
+            <Card.Body className="p-5 text-center">
+              <p>Loading...</p>
+            </Card.Body>
+          </Card>
+        </Container>
+      </div>
+    }>
+      <NotMeContent />
+    </Suspense>
+  );
+}
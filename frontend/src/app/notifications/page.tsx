@@ -7,11 +7,14 @@ import { useRouter } from "next/navigation";
 import DashboardSidebar from "@/components/dashboard-sidebar";
 import {
   Notification,
+  NotificationsQuery,
   getNotifications,
   updateNotification,
   updateNotificationsBatch,
   deleteNotification,
   deleteNotificationsBatch,
+  bulkUpdateNotifications,
+  bulkDeleteNotifications,
 } from "@/backend/notifications";
 import { useI18n } from "@/contexts/i18n-context";
 
@@ -21,6 +24,15 @@ export default function NotificationsPage() {
   const { t, locale } = useI18n();
   const [notifications, setNotifications] = useState<Notification[]>([]);
   const [loadingNotifications, setLoadingNotifications] = useState(true);
+  const [loadingMore, setLoadingMore] = useState(false);
+  const [nextCursor, setNextCursor] = useState<string | null>(null);
+  const [total, setTotal] = useState(0);
+  const [unreadCount, setUnreadCount] = useState(0);
+  const [levelFilter, setLevelFilter] = useState<NotificationsQuery["level"]>(undefined);
+  const [readFilter, setReadFilter] = useState<boolean | undefined>(undefined);
+  const [showArchived, setShowArchived] = useState(false);
+  const [grouped, setGrouped] = useState(false);
+  const [expandedGroups, setExpandedGroups] = useState<Record<string, Notification[]>>({});
   const [selectedIds, setSelectedIds] = useState<Set<string>>(new Set());
   const [error, setError] = useState<string | null>(null);
 
@@ -34,7 +46,7 @@ export default function NotificationsPage() {
     if (user && tokens?.access_token) {
       loadNotifications();
     }
-  }, [user, tokens]);
+  }, [user, tokens, levelFilter, readFilter, showArchived, grouped]);
 
   const loadNotifications = async () => {
     if (!tokens?.access_token) return;
@@ -42,8 +54,17 @@ export default function NotificationsPage() {
     try {
       setLoadingNotifications(true);
       setError(null);
-      const data = await getNotifications(tokens.access_token);
-      setNotifications(data);
+      const page = await getNotifications(tokens.access_token, {
+        level: levelFilter,
+        read: readFilter,
+        archived: showArchived,
+        grouped,
+      });
+      setNotifications(page.notifications);
+      setExpandedGroups({});
+      setNextCursor(page.next_cursor);
+      setTotal(page.total);
+      setUnreadCount(page.unread);
     } catch (err) {
       setError(err instanceof Error ? err.message : t("notifications.failedToLoad"));
     } finally {
@@ -51,6 +72,55 @@ export default function NotificationsPage() {
     }
   };
 
+  const loadMore = async () => {
+    if (!tokens?.access_token || !nextCursor) return;
+
+    try {
+      setLoadingMore(true);
+      setError(null);
+      const page = await getNotifications(tokens.access_token, {
+        cursor: nextCursor,
+        level: levelFilter,
+        read: readFilter,
+        archived: showArchived,
+        grouped,
+      });
+      setNotifications((current) => [...current, ...page.notifications]);
+      setNextCursor(page.next_cursor);
+      setTotal(page.total);
+      setUnreadCount(page.unread);
+    } catch (err) {
+      setError(err instanceof Error ? err.message : t("notifications.failedToLoad"));
+    } finally {
+      setLoadingMore(false);
+    }
+  };
+
+  const handleToggleGroup = async (groupKey: string) => {
+    if (!tokens?.access_token) return;
+
+    if (expandedGroups[groupKey]) {
+      const rest = { ...expandedGroups };
+      delete rest[groupKey];
+      setExpandedGroups(rest);
+      return;
+    }
+
+    try {
+      setError(null);
+      const page = await getNotifications(tokens.access_token, {
+        group_key: groupKey,
+        level: levelFilter,
+        read: readFilter,
+        archived: showArchived,
+      });
+      // The first member is the notification already shown
+      setExpandedGroups({ ...expandedGroups, [groupKey]: page.notifications.slice(1) });
+    } catch (err) {
+      setError(err instanceof Error ? err.message : t("notifications.failedToLoad"));
+    }
+  };
+
   const handleToggleSelect = (id: string) => {
     const newSelected = new Set(selectedIds);
     if (newSelected.has(id)) {
@@ -88,13 +158,33 @@ export default function NotificationsPage() {
 
     try {
       setError(null);
-      await updateNotification(notification.id, !notification.read, tokens.access_token);
+      await updateNotification(notification.id, { read: !notification.read }, tokens.access_token);
       await loadNotifications();
     } catch (err) {
       setError(err instanceof Error ? err.message : "Failed to update notification");
     }
   };
 
+  const handleToggleArchived = async (notification: Notification) => {
+    if (!tokens?.access_token) return;
+
+    try {
+      setError(null);
+      await updateNotification(
+        notification.id,
+        { archived: notification.archived_at === null },
+        tokens.access_token
+      );
+      await loadNotifications();
+      // Archiving moves it out of the current list
+      const newSelected = new Set(selectedIds);
+      newSelected.delete(notification.id);
+      setSelectedIds(newSelected);
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "Failed to update notification");
+    }
+  };
+
   const handleDelete = async (notificationId: string) => {
     if (!tokens?.access_token) return;
 
@@ -133,6 +223,35 @@ export default function NotificationsPage() {
     }
   };
 
+  const handleMarkEverythingRead = async () => {
+    if (!tokens?.access_token) return;
+
+    try {
+      setError(null);
+      await bulkUpdateNotifications({ all: true, archived: showArchived }, true, tokens.access_token);
+      await loadNotifications();
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "Failed to update notifications");
+    }
+  };
+
+  const handleDeleteAllRead = async () => {
+    if (!tokens?.access_token) return;
+
+    if (!confirm(t("notifications.confirmDeleteAllRead"))) {
+      return;
+    }
+
+    try {
+      setError(null);
+      await bulkDeleteNotifications({ read: true, archived: showArchived }, tokens.access_token);
+      await loadNotifications();
+      setSelectedIds(new Set());
+    } catch (err) {
+      setError(err instanceof Error ? err.message : t("notifications.failedToDeleteBatch"));
+    }
+  };
+
   const getLevelBadgeVariant = (level: string) => {
     switch (level) {
       case "error":
@@ -216,7 +335,6 @@ export default function NotificationsPage() {
     return null;
   }
 
-  const unreadCount = notifications.filter((n) => !n.read).length;
   const hasSelected = selectedIds.size > 0;
 
   return (
@@ -234,6 +352,26 @@ export default function NotificationsPage() {
                   {unreadCount > 0 ? `${unreadCount} unread` : "All caught up!"}
                 </p>
               </div>
+              {!hasSelected && notifications.length > 0 && (
+                <div className="d-flex gap-2">
+                  {unreadCount > 0 && (
+                    <Button
+                      variant="outline-primary"
+                      size="sm"
+                      onClick={handleMarkEverythingRead}
+                    >
+                      {t("notifications.markEverythingRead")}
+                    </Button>
+                  )}
+                  <Button
+                    variant="outline-danger"
+                    size="sm"
+                    onClick={handleDeleteAllRead}
+                  >
+                    {t("notifications.deleteAllRead")}
+                  </Button>
+                </div>
+              )}
               {hasSelected && (
                 <div className="d-flex gap-2">
                   <Button
@@ -267,6 +405,51 @@ export default function NotificationsPage() {
               </div>
             )}
 
+            <div className="mb-3 d-flex gap-2">
+              <Form.Select
+                size="sm"
+                style={{ maxWidth: "12rem" }}
+                value={showArchived ? "archived" : "inbox"}
+                onChange={(e) => setShowArchived(e.target.value === "archived")}
+              >
+                <option value="inbox">{t("notifications.inbox")}</option>
+                <option value="archived">{t("notifications.archived")}</option>
+              </Form.Select>
+              <Form.Select
+                size="sm"
+                style={{ maxWidth: "12rem" }}
+                value={levelFilter ?? ""}
+                onChange={(e) =>
+                  setLevelFilter((e.target.value || undefined) as NotificationsQuery["level"])
+                }
+              >
+                <option value="">{t("notifications.allLevels")}</option>
+                <option value="info">{t("notifications.info")}</option>
+                <option value="warning">{t("notifications.warning")}</option>
+                <option value="error">{t("notifications.error")}</option>
+              </Form.Select>
+              <Form.Select
+                size="sm"
+                style={{ maxWidth: "12rem" }}
+                value={readFilter === undefined ? "" : String(readFilter)}
+                onChange={(e) =>
+                  setReadFilter(e.target.value === "" ? undefined : e.target.value === "true")
+                }
+              >
+                <option value="">{t("notifications.allStatuses")}</option>
+                <option value="false">{t("notifications.unread")}</option>
+                <option value="true">{t("notifications.read")}</option>
+              </Form.Select>
+              <Form.Check
+                type="switch"
+                id="group-similar"
+                className="align-self-center ms-2"
+                label={t("notifications.groupSimilar")}
+                checked={grouped}
+                onChange={(e) => setGrouped(e.target.checked)}
+              />
+            </div>
+
             {loadingNotifications ? (
               <div className="text-center py-5">
                 <div className="spinner-border" role="status">
@@ -338,7 +521,49 @@ export default function NotificationsPage() {
                                 </div>
                               </div>
                             </div>
+                            {notification.title && (
+                              <h6 className="mb-1">
+                                {notification.action_url ? (
+                                  <a href={notification.action_url} className="text-decoration-none">
+                                    {notification.title}
+                                  </a>
+                                ) : (
+                                  notification.title
+                                )}
+                              </h6>
+                            )}
                             <p className="mb-2">{notification.message}</p>
+                            {notification.occurrences > 1 && notification.last_occurred_at && (
+                              <p className="small text-muted mb-2">
+                                {t("notifications.occurrences", {
+                                  count: notification.occurrences,
+                                  latest: formatDate(notification.last_occurred_at),
+                                })}
+                              </p>
+                            )}
+                            {notification.group_key && expandedGroups[notification.group_key] && (
+                              <ul className="small text-muted mb-2 ps-3">
+                                {expandedGroups[notification.group_key].map((member) => (
+                                  <li key={member.id}>
+                                    {member.message} · {formatDate(member.created_at)}
+                                  </li>
+                                ))}
+                              </ul>
+                            )}
+                            {notification.actions.length > 0 && (
+                              <div className="d-flex gap-2 mb-2">
+                                {notification.actions.map((action) => (
+                                  <Button
+                                    key={action.url}
+                                    href={action.url}
+                                    variant="outline-primary"
+                                    size="sm"
+                                  >
+                                    {action.label}
+                                  </Button>
+                                ))}
+                              </div>
+                            )}
                             <div className="d-flex gap-2 align-items-center">
                               <Button
                                 variant="link"
@@ -348,6 +573,30 @@ export default function NotificationsPage() {
                               >
                                 {notification.read ? t("notifications.markAllUnread") : t("notifications.markAllRead")}
                               </Button>
+                              {notification.group_key && (notification.group_size ?? 0) > 1 && (
+                                <>
+                                  <span className="text-muted">•</span>
+                                  <Button
+                                    variant="link"
+                                    size="sm"
+                                    className="p-0 text-decoration-none"
+                                    onClick={() => handleToggleGroup(notification.group_key!)}
+                                  >
+                                    {expandedGroups[notification.group_key]
+                                      ? t("notifications.hideGroup")
+                                      : t("notifications.showGroup", { count: (notification.group_size ?? 1) - 1 })}
+                                  </Button>
+                                </>
+                              )}
+                              <span className="text-muted">•</span>
+                              <Button
+                                variant="link"
+                                size="sm"
+                                className="p-0 text-decoration-none"
+                                onClick={() => handleToggleArchived(notification)}
+                              >
+                                {notification.archived_at ? t("notifications.unarchive") : t("notifications.archive")}
+                              </Button>
                               <span className="text-muted">•</span>
                               <Button
                                 variant="link"
@@ -364,6 +613,22 @@ export default function NotificationsPage() {
                     </Card>
                   ))}
                 </div>
+
+                <div className="mt-3 text-center">
+                  <p className="small text-muted mb-2">
+                    {t("notifications.showing", { shown: notifications.length, total })}
+                  </p>
+                  {nextCursor && (
+                    <Button
+                      variant="outline-primary"
+                      size="sm"
+                      onClick={loadMore}
+                      disabled={loadingMore}
+                    >
+                      {loadingMore ? t("common.loading") : t("notifications.loadMore")}
+                    </Button>
+                  )}
+                </div>
               </>
             )}
           </Container>
@@ -0,0 +1,174 @@
+"use client";
+
+import { useState, Suspense } from "react";
+import { Container, Card, Form, Button, Alert } from "react-bootstrap";
+import { useRouter, useSearchParams } from "next/navigation";
+import Link from "next/link";
+import { requestPasswordReset, resetPassword } from "@/backend/account";
+
+function ResetPasswordContent() {
+  const searchParams = useSearchParams();
+  const [email, setEmail] = useState(searchParams.get("email") ?? "");
+  const [code, setCode] = useState("");
+  const [newPassword, setNewPassword] = useState("");
+  const [confirmPassword, setConfirmPassword] = useState("");
+  const [error, setError] = useState("");
+  const [message, setMessage] = useState("");
+  const [loading, setLoading] = useState(false);
+  const [sendLoading, setSendLoading] = useState(false);
+  const router = useRouter();
+
+  const handleSendCode = async () => {
+    setError("");
+    setMessage("");
+    setSendLoading(true);
+
+    try {
+      setMessage(await requestPasswordReset(email));
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "Failed to send password reset code");
+    } finally {
+      setSendLoading(false);
+    }
+  };
+
+  const handleSubmit = async (e: React.FormEvent) => {
+    e.preventDefault();
+    setError("");
+    setMessage("");
+
+    if (newPassword !== confirmPassword) {
+      setError("Passwords do not match");
+      return;
+    }
+
+    setLoading(true);
+    try {
+      setMessage(await resetPassword(email, code, newPassword));
+      setTimeout(() => {
+        router.push("/signin");
+      }, 2000);
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "Failed to reset password");
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  return (
+    <div className="min-h-screen d-flex align-items-center bg-light py-5">
+      <Container className="max-w-md">
+        <Card className="shadow">
+          <Card.Body className="p-5">
+            <div className="text-center mb-4">
+              <h2 className="fw-bold">Reset Your Password</h2>
+              <p className="text-muted small">
+                Enter the code from the password reset email and choose a new password.
+              </p>
+            </div>
+
+            {error && <Alert variant="danger">{error}</Alert>}
+            {message && <Alert variant="success">{message}</Alert>}
+
+            <Form onSubmit={handleSubmit}>
+              <Form.Group className="mb-3">
+                <Form.Label>Email</Form.Label>
+                <Form.Control
+                  type="email"
+                  autoComplete="username"
+                  value={email}
+                  onChange={(e) => setEmail(e.target.value)}
+                  required
+                />
+              </Form.Group>
+
+              <Form.Group className="mb-3">
+                <Form.Label>Reset Code</Form.Label>
+                <Form.Control
+                  type="text"
+                  autoComplete="one-time-code"
+                  value={code}
+                  onChange={(e) => setCode(e.target.value.trim())}
+                  required
+                />
+              </Form.Group>
+
+              <Form.Group className="mb-3">
+                <Form.Label>New Password</Form.Label>
+                <Form.Control
+                  type="password"
+                  autoComplete="new-password"
+                  value={newPassword}
+                  onChange={(e) => setNewPassword(e.target.value)}
+                  required
+                />
+              </Form.Group>
+
+              <Form.Group className="mb-3">
+                <Form.Label>Confirm New Password</Form.Label>
+                <Form.Control
+                  type="password"
+                  autoComplete="new-password"
+                  value={confirmPassword}
+                  onChange={(e) => setConfirmPassword(e.target.value)}
+                  required
+                />
+              </Form.Group>
+
+              <Button
+                variant="primary"
+                type="submit"
+                className="w-100 mb-3"
+                disabled={loading || !email || !code || !newPassword}
+              >
+                {loading ? "Resetting..." : "Reset Password"}
+              </Button>
+            </Form>
+
+            <div className="text-center">
+              <p className="text-muted small mb-2">
+                No code, or has it expired?
+              </p>
+              <Button
+                variant="link"
+                onClick={handleSendCode}
+                disabled={sendLoading || !email}
+                className="p-0"
+              >
+                {sendLoading ? "Sending..." : "Email Me a Reset Code"}
+              </Button>
+            </div>
+
+            <hr className="my-4" />
+
+            <div className="text-center">
+              <p className="mb-0">
+                <Link href="/signin" className="text-decoration-none">
+                  Back to Sign In
+                </Link>
+              </p>
+            </div>
+          </Card.Body>
+        </Card>
+      </Container>
+    </div>
+  );
+}
+
+export default function ResetPasswordPage() {
+  return (
+    <Suspense fallback={
+      <div className="min-h-screen d-flex align-items-center bg-light py-5">
+        <Container className="max-w-md">
+          <Card className="shadow">
+            <Card.Body className="p-5 text-center">
+              <p>Loading...</p>
+            </Card.Body>
+          </Card>
+        </Container>
+      </div>
+    }>
+      <ResetPasswordContent />
+    </Suspense>
+  );
+}
@@ -12,6 +12,7 @@ import { useI18n } from "@/contexts/i18n-context";
 function SignInContent() {
   const [email, setEmail] = useState("");
   const [password, setPassword] = useState("");
+  const [rememberMe, setRememberMe] = useState(false);
   const [error, setError] = useState("");
   const [success, setSuccess] = useState("");
   const [loading, setLoading] = useState(false);
@@ -41,7 +42,7 @@ function SignInContent() {
     setLoading(true);
 
     try {
-      await login(email, password);
+      await login(email, password, rememberMe);
     } catch (err) {
       setError(err instanceof Error ? err.message : "Login failed");
     } finally {
@@ -67,7 +68,8 @@ function SignInContent() {
               <Form.Group className="mb-3">
                 <Form.Label>{t("signin.email")}</Form.Label>
                 <Form.Control
-                  type="email"
+                  type="text"
+                  autoComplete="username"
                   placeholder={t("signin.emailPlaceholder")}
                   value={email}
                   onChange={(e) => setEmail(e.target.value)}
@@ -86,6 +88,19 @@ function SignInContent() {
                 />
               </Form.Group>
 
+              <Form.Group className="mb-3 d-flex justify-content-between">
+                <Form.Check
+                  type="checkbox"
+                  id="remember-me"
+                  label={t("signin.rememberMe")}
+                  checked={rememberMe}
+                  onChange={(e) => setRememberMe(e.target.checked)}
+                />
+                <Link href="/reset-password" className="text-decoration-none">
+                  {t("signin.forgotPassword")}
+                </Link>
+              </Form.Group>
+
               <Button
                 variant="primary"
                 type="submit"
@@ -0,0 +1,90 @@
+"use client";
+
+import { useState, Suspense } from "react";
+import { Container, Card, Button, Alert } from "react-bootstrap";
+import { useSearchParams } from "next/navigation";
+import Link from "next/link";
+import { unsubscribeDigest } from "@/backend/notifications";
+
+function UnsubscribeContent() {
+  const [error, setError] = useState("");
+  const [message, setMessage] = useState("");
+  const [loading, setLoading] = useState(false);
+  const searchParams = useSearchParams();
+  const token = searchParams.get("token");
+
+  const handleUnsubscribe = async () => {
+    if (!token) return;
+    setError("");
+    setLoading(true);
+
+    try {
+      setMessage(await unsubscribeDigest(token));
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "Failed to unsubscribe");
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  return (
+    <div className="min-h-screen d-flex align-items-center bg-light py-5">
+      <Container className="max-w-md">
+        <Card className="shadow">
+          <Card.Body className="p-5">
+            <div className="text-center mb-4">
+              <h2 className="fw-bold">Unsubscribe</h2>
+              <p className="text-muted">
+                Stop receiving notification digest emails. You can turn them back on
+                in your notification preferences at any time.
+              </p>
+            </div>
+
+            {!token && <Alert variant="danger">This link is missing its unsubscribe token.</Alert>}
+            {error && <Alert variant="danger">{error}</Alert>}
+            {message && <Alert variant="success">{message}</Alert>}
+
+            {!message && (
+              <Button
+                variant="primary"
+                className="w-100 mb-3"
+                onClick={handleUnsubscribe}
+                disabled={loading || !token}
+              >
+                {loading ? "Unsubscribing..." : "Unsubscribe from digests"}
+              </Button>
+            )}
+
+            <hr className="my-4" />
+
+            <div className="text-center">
+              <p className="mb-0">
+                <Link href="/signin" className="text-decoration-none">
+                  Go to Sign In
+                </Link>
+              </p>
+            </div>
+          </Card.Body>
+        </Card>
+      </Container>
+    </div>
+  );
+}
+
+export default function UnsubscribePage() {
+  return (
+    <Suspense fallback={
+      <div className="min-h-screen d-flex align-items-center bg-light py-5">
+        <Container className="max-w-md">
+          <Card className="shadow">
+            <Card.Body className="p-5 text-center">
+              <p>Loading...</p>
+            </Card.Body>
+          </Card>
+        </Container>
+      </div>
+    }>
+      <UnsubscribeContent />
+    </Suspense>
+  );
+}
@@ -6,34 +6,70 @@ import { useRouter, useSearchParams } from "next/navigation";
 import Link from "next/link";
 import { verifyEmail, resendVerification } from "@/backend/verify";
 
+// Outcomes of the verification link in the email, passed back as ?status=
+const LINK_STATUS_MESSAGES: Record<string, { title: string; message: string; ok: boolean }> = {
+  verified: {
+    title: "Email Verified!",
+    message: "Your email has been successfully verified. You can now sign in.",
+    ok: true,
+  },
+  already_verified: {
+    title: "Already Verified",
+    message: "This email address has already been verified. You can sign in.",
+    ok: true,
+  },
+  expired: {
+    title: "Link Expired",
+    message: "This verification link has expired. Sign in to request a new code.",
+    ok: false,
+  },
+  invalid: {
+    title: "Invalid Link",
+    message: "This verification link is not valid. Try entering the code from the email instead.",
+    ok: false,
+  },
+  too_many_attempts: {
+    title: "Too Many Attempts",
+    message: "This code has had too many incorrect attempts. Sign in to request a new one.",
+    ok: false,
+  },
+  error: {
+    title: "Verification Failed",
+    message: "Something went wrong while verifying your email. Please try again later.",
+    ok: false,
+  },
+};
+
 function VerifyContent() {
   const [code, setCode] = useState("");
   const [email, setEmail] = useState("");
-  const [accountId, setAccountId] = useState("");
   const [error, setError] = useState("");
   const [success, setSuccess] = useState(false);
   const [loading, setLoading] = useState(false);
   const [resendLoading, setResendLoading] = useState(false);
   const router = useRouter();
   const searchParams = useSearchParams();
+  const linkStatus = searchParams.get("status");
 
   useEffect(() => {
-    // Get email and account_id from URL params (passed from signup)
+    // Arrived from the verification link; nothing to enter
+    if (linkStatus) {
+      return;
+    }
+
+
+    // Get email from URL params (passed from signup)
     const emailParam = searchParams.get("email");
-    const accountIdParam = searchParams.get("account_id");
 
     if (emailParam) {
       setEmail(emailParam);
     }
-    if (accountIdParam) {
-      setAccountId(accountIdParam);
-    }
 
     // If missing params, redirect to signup
-    if (!emailParam || !accountIdParam) {
+    if (!emailParam) {
       router.push("/signup");
     }
-  }, [searchParams, router]);
+  }, [searchParams, router, linkStatus]);
 
   const handleSubmit = async (e: React.FormEvent) => {
     e.preventDefault();
@@ -41,7 +77,7 @@ function VerifyContent() {
     setLoading(true);
 
     try {
-      await verifyEmail(accountId, code);
+      await verifyEmail(email, code);
       setSuccess(true);
       // Redirect to signin after 2 seconds
       setTimeout(() => {
@@ -69,6 +105,25 @@ function VerifyContent() {
     }
   };
 
+  if (linkStatus) {
+    const result = LINK_STATUS_MESSAGES[linkStatus] ?? LINK_STATUS_MESSAGES.invalid;
+    return (
+      <div className="min-h-screen d-flex align-items-center bg-light py-5">
+        <Container className="max-w-md">
+          <Card className="shadow">
+            <Card.Body className="p-5 text-center">
+              <h2 className="fw-bold mb-3">{result.title}</h2>
+              <Alert variant={result.ok ? "success" : "warning"}>{result.message}</Alert>
+              <Link href="/signin" className="btn btn-primary w-100">
+                Go to Sign In
+              </Link>
+            </Card.Body>
+          </Card>
+        </Container>
+      </div>
+    );
+  }
+
   if (success && !loading) {
     return (
       <div className="min-h-screen d-flex align-items-center bg-light py-5">
@@ -8,6 +8,11 @@ export interface UpdateAccountSettingsRequest {
   username?: string | null;
 }
 
+/**
+ * Thrown when the session must re-authenticate before the change is accepted
+ */
+export class ReauthRequiredError extends Error {}
+
 /**
  * Get account settings for the current user
  */
@@ -48,6 +53,9 @@ export async function updateAccountSettings(
 
   if (!response.ok) {
     const error = await response.json();
+    if (error.reauth_required) {
+      throw new ReauthRequiredError(error.error);
+    }
     throw new Error(error.error || "Failed to update account settings");
   }
 
@@ -1,4 +1,5 @@
 import { getApiUrl } from "./config";
+import { powHeaders } from "./pow";
 
 /**
  * Get authentication headers with access token
@@ -43,6 +44,67 @@ export const changePassword = async (
   }
 };
 
+/**
+ * Email a password reset code to an address
+ */
+export const requestPasswordReset = async (email: string): Promise<string> => {
+  const apiUrl = getApiUrl();
+  const response = await fetch(`${apiUrl}/auth/password-reset`, {
+    method: "POST",
+    headers: { "Content-Type": "application/json", ...(await powHeaders("password_reset")) },
+    body: JSON.stringify({ email }),
+  });
+
+  const data = await response.json();
+  if (!response.ok) {
+    throw new Error(data.error || "Failed to send password reset code");
+  }
+  return data.message;
+};
+
+/**
+ * Choose a new password with the code from a password reset email
+ */
+export const resetPassword = async (
+  email: string,
+  code: string,
+  newPassword: string
+): Promise<string> => {
+  const apiUrl = getApiUrl();
+  const response = await fetch(`${apiUrl}/auth/password-reset/confirm`, {
+    method: "POST",
+    headers: { "Content-Type": "application/json", ...(await powHeaders("password_reset")) },
+    body: JSON.stringify({ email, code, new_password: newPassword }),
+  });
+
+  const data = await response.json();
+  if (!response.ok) {
+    throw new Error(data.error || "Failed to reset password");
+  }
+  return data.message;
+};
+
+/**
+ * Confirm identity again before a sensitive operation.
+ * Pass the password, or a fresh Google ID token for Google accounts.
+ */
+export const reauthenticate = async (
+  proof: { password: string } | { id_token: string }
+): Promise<string> => {
+  const apiUrl = getApiUrl();
+  const response = await fetch(`${apiUrl}/auth/reauth`, {
+    method: "POST",
+    headers: { ...getAuthHeaders(), ...(await powHeaders("reauth")) },
+    body: JSON.stringify(proof),
+  });
+
+  const data = await response.json();
+  if (!response.ok) {
+    throw new Error(data.error || "Failed to confirm identity");
+  }
+  return data.elevated_until;
+};
+
 /**
  * Delete user account
  */
@@ -50,7 +112,7 @@ export const deleteAccount = async (password: string): Promise<void> => {
   const apiUrl = getApiUrl();
   const response = await fetch(`${apiUrl}/auth/delete-account`, {
     method: "POST",
-    headers: getAuthHeaders(),
+    headers: { ...getAuthHeaders(), ...(await powHeaders("reauth")) },
     body: JSON.stringify({
       password: password,
     }),
@@ -61,3 +123,39 @@ export const deleteAccount = async (password: string): Promise<void> => {
     throw new Error(error.error || "Failed to delete account");
   }
 };
+
+/**
+ * Restore an account that is scheduled for deletion
+ */
+export const restoreAccount = async (): Promise<void> => {
+  const apiUrl = getApiUrl();
+  const response = await fetch(`${apiUrl}/account/restore`, {
+    method: "POST",
+    headers: getAuthHeaders(),
+  });
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || "Failed to restore account");
+  }
+};
+
+/**
+ * Report a sign-in from a new-device alert as not made by the account owner
+ */
+export const reportUnrecognizedLogin = async (
+  token: string
+): Promise<{ message: string; password_reset_required: boolean }> => {
+  const apiUrl = getApiUrl();
+  const response = await fetch(`${apiUrl}/auth/report-login`, {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ token }),
+  });
+
+  const data = await response.json();
+  if (!response.ok) {
+    throw new Error(data.error || "Failed to secure account");
+  }
+  return data;
+};
@@ -1,4 +1,5 @@
 import { getApiUrl } from "./config";
+import { powHeaders } from "./pow";
 
 export interface AccountInfo {
   id: string;
@@ -8,6 +9,8 @@ export interface AccountInfo {
   avatar_url: string | null;
   username: string | null;
   auth_type: string;
+  deletion_scheduled_for: string | null;
+  password_reset_required: boolean;
 }
 
 export interface AuthTokens {
@@ -27,7 +30,7 @@ export interface LoginResponse {
 
 export interface SignupResponse {
   email: string;
-  account_id: string;
+  message: string;
 }
 
 export interface RefreshResponse {
@@ -61,12 +64,16 @@ const getAuthHeaders = (): HeadersInit => {
 /**
  * Sign up a new user
  */
-export const signup = async (email: string, password: string): Promise<SignupResponse> => {
+export const signup = async (
+  email: string,
+  password: string,
+  inviteCode?: string
+): Promise<SignupResponse> => {
   const apiUrl = getApiUrl();
   const response = await fetch(`${apiUrl}/auth/signup`, {
     method: "POST",
-    headers: { "Content-Type": "application/json" },
-    body: JSON.stringify({ email, password }),
+    headers: { "Content-Type": "application/json", ...(await powHeaders("signup")) },
+    body: JSON.stringify({ email, password, invite_code: inviteCode }),
   });
 
   if (!response.ok) {
@@ -80,12 +87,16 @@ export const signup = async (email: string, password: string): Promise<SignupRes
 /**
  * Login with email and password
  */
-export const login = async (email: string, password: string): Promise<LoginResponse> => {
+export const login = async (
+  email: string,
+  password: string,
+  rememberMe = false
+): Promise<LoginResponse> => {
   const apiUrl = getApiUrl();
   const response = await fetch(`${apiUrl}/auth/login`, {
     method: "POST",
-    headers: { "Content-Type": "application/json" },
-    body: JSON.stringify({ email, password }),
+    headers: { "Content-Type": "application/json", ...(await powHeaders("login")) },
+    body: JSON.stringify({ email, password, remember_me: rememberMe }),
   });
 
   if (!response.ok) {
@@ -99,12 +110,15 @@ export const login = async (email: string, password: string): Promise<LoginRespo
 /**
  * Login with Google ID token
  */
-export const googleLogin = async (idToken: string): Promise<LoginResponse> => {
+export const googleLogin = async (
+  idToken: string,
+  inviteCode?: string
+): Promise<LoginResponse> => {
   const apiUrl = getApiUrl();
   const response = await fetch(`${apiUrl}/auth/google`, {
     method: "POST",
     headers: { "Content-Type": "application/json" },
-    body: JSON.stringify({ id_token: idToken }),
+    body: JSON.stringify({ id_token: idToken, invite_code: inviteCode }),
   });
 
   if (!response.ok) {
@@ -112,6 +126,12 @@ export const googleLogin = async (idToken: string): Promise<LoginResponse> => {
     throw new Error(error.error || "Google login failed");
   }
 
+  // A first sign-in may be put on the waitlist instead of signed in
+  if (response.status === 202) {
+    const data = await response.json();
+    throw new Error(data.message);
+  }
+
   return await response.json();
 };
 
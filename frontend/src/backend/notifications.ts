@@ -1,22 +1,84 @@
 import { getApiUrl } from "./config";
+import { ReauthRequiredError } from "./account-settings";
+
+export interface NotificationAction {
+  label: string;
+  url: string;
+}
 
 export interface Notification {
   id: string;
   account_id: string;
+  kind: string;
   level: "info" | "warning" | "error";
+  title: string | null;
   message: string;
+  data: Record<string, unknown>;
+  action_url: string | null;
+  actions: NotificationAction[];
+  source: string | null;
+  group_key: string | null;
+  occurrences: number;
+  last_occurred_at: string | null;
   read: boolean;
+  expires_at: string | null;
+  archived_at: string | null;
   created_at: string;
   updated_at: string;
+  /** Only set when listing with `grouped: true` */
+  group_size?: number;
 }
 
 export interface CreateNotificationRequest {
-  level: "info" | "warning" | "error";
+  kind?: string;
+  level?: "info" | "warning" | "error";
+  title?: string;
   message: string;
+  data?: Record<string, unknown>;
+  action_url?: string;
+  actions?: NotificationAction[];
+  source?: string;
+  expires_at?: string;
+  group_key?: string;
+  /** Seconds within which a repeat with the same group_key is collapsed */
+  dedupe_window?: number;
 }
 
 export interface UpdateNotificationRequest {
-  read: boolean;
+  read?: boolean;
+  archived?: boolean;
+}
+
+export interface NotificationsQuery {
+  limit?: number;
+  cursor?: string;
+  kind?: string;
+  level?: Notification["level"];
+  read?: boolean;
+  since?: string;
+  until?: string;
+  archived?: boolean;
+  grouped?: boolean;
+  group_key?: string;
+}
+
+/**
+ * Notifications a bulk operation applies to. Set `all` when no other field is set.
+ */
+export interface NotificationFilter {
+  all?: boolean;
+  kind?: string;
+  level?: Notification["level"];
+  read?: boolean;
+  before?: string;
+  archived?: boolean;
+}
+
+export interface NotificationPage {
+  notifications: Notification[];
+  next_cursor: string | null;
+  total: number;
+  unread: number;
 }
 
 export interface UnreadCountResponse {
@@ -48,12 +110,21 @@ export async function createNotification(
 }
 
 /**
- * Get all notifications for the current user
+ * Get a page of notifications for the current user, most recently occurred first.
+ * Pass the returned `next_cursor` as `cursor` to fetch the following page.
  */
 export async function getNotifications(
-  token: string
-): Promise<Notification[]> {
-  const response = await fetch(`${getApiUrl()}/notifications`, {
+  token: string,
+  query: NotificationsQuery = {}
+): Promise<NotificationPage> {
+  const params = new URLSearchParams();
+  Object.entries(query).forEach(([key, value]) => {
+    if (value !== undefined) {
+      params.set(key, String(value));
+    }
+  });
+  const search = params.toString();
+  const response = await fetch(`${getApiUrl()}/notifications${search ? `?${search}` : ""}`, {
     method: "GET",
     headers: {
       Authorization: `Bearer ${token}`,
@@ -93,11 +164,11 @@ export async function getUnreadCount(
 }
 
 /**
- * Update a single notification's read status
+ * Mark a single notification read or unread, or archive or unarchive it
  */
 export async function updateNotification(
   notificationId: string,
-  read: boolean,
+  changes: UpdateNotificationRequest,
   token: string
 ): Promise<Notification> {
   const response = await fetch(`${getApiUrl()}/notifications/${notificationId}`, {
@@ -106,7 +177,7 @@ export async function updateNotification(
       Authorization: `Bearer ${token}`,
       "Content-Type": "application/json",
     },
-    body: JSON.stringify({ read }),
+    body: JSON.stringify(changes),
   });
 
   if (!response.ok) {
@@ -191,3 +262,225 @@ export async function deleteNotificationsBatch(
 
   return response.json();
 }
+
+/**
+ * Mark every notification matching a filter read or unread
+ */
+export async function bulkUpdateNotifications(
+  filter: NotificationFilter,
+  read: boolean,
+  token: string
+): Promise<{ updated_count: number }> {
+  const response = await fetch(`${getApiUrl()}/notifications/bulk`, {
+    method: "PUT",
+    headers: {
+      Authorization: `Bearer ${token}`,
+      "Content-Type": "application/json",
+    },
+    body: JSON.stringify({ filter, read }),
+  });
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || "Failed to update notifications");
+  }
+
+  return response.json();
+}
+
+/**
+ * Delete every notification matching a filter
+ */
+export async function bulkDeleteNotifications(
+  filter: NotificationFilter,
+  token: string
+): Promise<{ deleted_count: number }> {
+  const response = await fetch(`${getApiUrl()}/notifications/bulk`, {
+    method: "DELETE",
+    headers: {
+      Authorization: `Bearer ${token}`,
+      "Content-Type": "application/json",
+    },
+    body: JSON.stringify({ filter }),
+  });
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || "Failed to delete notifications");
+  }
+
+  return response.json();
+}
+
+export interface NotificationPreferenceRule {
+  kind: string | null;
+  level: Notification["level"] | null;
+  /** Empty to receive nothing */
+  channels: string[];
+}
+
+export interface DigestSettings {
+  frequency: "off" | "daily" | "weekly";
+  time_zone?: string;
+  hour?: number;
+  weekday?: number;
+}
+
+export interface NotificationPreferences {
+  rules: NotificationPreferenceRule[];
+  webhook_url: string | null;
+  webhook_secret: string | null;
+  digest: DigestSettings;
+  channels: string[];
+}
+
+export interface UpdateNotificationPreferencesRequest {
+  webhook_url: string | null;
+  /** Replaces every existing rule */
+  rules: NotificationPreferenceRule[];
+  /** Left unchanged when absent */
+  digest?: DigestSettings;
+}
+
+/**
+ * Get the current user's notification channel rules, webhook and digest settings
+ */
+export async function getNotificationPreferences(
+  token: string
+): Promise<NotificationPreferences> {
+  const response = await fetch(`${getApiUrl()}/account/notification-preferences`, {
+    method: "GET",
+    headers: {
+      Authorization: `Bearer ${token}`,
+      "Content-Type": "application/json",
+    },
+  });
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || "Failed to get notification preferences");
+  }
+
+  return response.json();
+}
+
+/**
+ * Update notification preferences. Needs a recent re-authentication.
+ */
+export async function updateNotificationPreferences(
+  request: UpdateNotificationPreferencesRequest,
+  token: string
+): Promise<NotificationPreferences> {
+  const response = await fetch(`${getApiUrl()}/account/notification-preferences`, {
+    method: "PUT",
+    headers: {
+      Authorization: `Bearer ${token}`,
+      "Content-Type": "application/json",
+    },
+    body: JSON.stringify(request),
+  });
+
+  if (!response.ok) {
+    const error = await response.json();
+    if (error.reauth_required) {
+      throw new ReauthRequiredError(error.error);
+    }
+    throw new Error(error.error || "Failed to update notification preferences");
+  }
+
+  return response.json();
+}
+
+export type NotificationStreamEvent =
+  | { type: "unread_count"; data: { unread_count: number } }
+  | { type: "created"; data: { notification: Notification; unread_count: number } }
+  | { type: "updated"; data: { notifications: Notification[]; unread_count: number } }
+  | { type: "deleted"; data: { ids: string[]; unread_count: number } };
+
+/**
+ * Subscribe to real-time notification changes over Server-Sent Events.
+ * Reconnects with Last-Event-ID after network errors so no change is missed.
+ * Returns a function that closes the stream.
+ */
+export function subscribeToNotifications(
+  token: string,
+  onEvent: (event: NotificationStreamEvent) => void
+): () => void {
+  const controller = new AbortController();
+  let lastEventId: string | null = null;
+
+  const dispatch = (block: string) => {
+    let type = "message";
+    const data: string[] = [];
+    for (const line of block.split("\n")) {
+      if (line.startsWith(":")) continue;
+      const separator = line.indexOf(":");
+      const field = separator === -1 ? line : line.slice(0, separator);
+      const value = separator === -1 ? "" : line.slice(separator + 1).replace(/^ /, "");
+      if (field === "id") lastEventId = value;
+      else if (field === "event") type = value;
+      else if (field === "data") data.push(value);
+    }
+    if (data.length > 0) {
+      onEvent({ type, data: JSON.parse(data.join("\n")) } as NotificationStreamEvent);
+    }
+  };
+
+  const connect = async () => {
+    while (!controller.signal.aborted) {
+      try {
+        const headers: Record<string, string> = {
+          Authorization: `Bearer ${token}`,
+          Accept: "text/event-stream",
+        };
+        if (lastEventId) {
+          headers["Last-Event-ID"] = lastEventId;
+        }
+        const response = await fetch(`${getApiUrl()}/notifications/stream`, {
+          headers,
+          signal: controller.signal,
+        });
+        // The token expired or was revoked; the caller resubscribes with a new one
+        if (response.status === 401 || response.status === 403) return;
+        if (!response.ok || !response.body) throw new Error("Failed to open notification stream");
+
+        const reader = response.body.pipeThrough(new TextDecoderStream()).getReader();
+        let buffer = "";
+        for (;;) {
+          const { value, done } = await reader.read();
+          if (done) break;
+          buffer += value.replace(/\r\n?/g, "\n");
+          let end;
+          while ((end = buffer.indexOf("\n\n")) !== -1) {
+            dispatch(buffer.slice(0, end));
+            buffer = buffer.slice(end + 2);
+          }
+        }
+      } catch (err) {
+        if (controller.signal.aborted) return;
+        console.error("Notification stream error:", err);
+      }
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+    }
+  };
+
+  connect();
+  return () => controller.abort();
+}
+
+/**
+ * Turn off notification digest emails using the token from a digest's unsubscribe link
+ */
+export async function unsubscribeDigest(token: string): Promise<string> {
+  const response = await fetch(`${getApiUrl()}/notification-digest/unsubscribe`, {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ token }),
+  });
+
+  const data = await response.json();
+  if (!response.ok) {
+    throw new Error(data.error || "Failed to unsubscribe");
+  }
+  return data.message;
+}
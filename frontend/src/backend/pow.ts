@@ -0,0 +1,52 @@
+import { getApiUrl } from "./config";
+
+export type PowAction = "signup" | "login" | "resend_verification" | "password_reset" | "reauth";
+
+interface PowChallenge {
+  enabled: boolean;
+  challenge?: string;
+  difficulty?: number;
+}
+
+const leadingZeroBits = (hash: Uint8Array): number => {
+  let bits = 0;
+  for (const byte of hash) {
+    if (byte === 0) {
+      bits += 8;
+      continue;
+    }
+    bits += Math.clz32(byte) - 24;
+    break;
+  }
+  return bits;
+};
+
+/**
+ * Solve a proof-of-work challenge for an action and return the headers proving
+ * it, or no headers when proof-of-work is disabled. A solution is found when
+ * SHA-256 of "{challenge}:{solution}" starts with `difficulty` zero bits.
+ */
+export const powHeaders = async (action: PowAction): Promise<Record<string, string>> => {
+  const response = await fetch(`${getApiUrl()}/auth/challenge?action=${action}`);
+  if (!response.ok) {
+    // Let the request itself report the problem
+    return {};
+  }
+
+  const data: PowChallenge = await response.json();
+  if (!data.enabled || !data.challenge || data.difficulty === undefined) {
+    return {};
+  }
+
+  const encoder = new TextEncoder();
+  for (let counter = 0; ; counter++) {
+    const solution = counter.toString(16);
+    const digest = await crypto.subtle.digest(
+      "SHA-256",
+      encoder.encode(`${data.challenge}:${solution}`)
+    );
+    if (leadingZeroBits(new Uint8Array(digest)) >= data.difficulty) {
+      return { "X-PoW-Challenge": data.challenge, "X-PoW-Solution": solution };
+    }
+  }
+};
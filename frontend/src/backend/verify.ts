@@ -1,15 +1,16 @@
 import { getApiUrl } from "./config";
+import { powHeaders } from "./pow";
 
 /**
  * Verify email with verification code
  */
-export const verifyEmail = async (accountId: string, code: string): Promise<void> => {
+export const verifyEmail = async (email: string, code: string): Promise<void> => {
   const apiUrl = getApiUrl();
   const response = await fetch(`${apiUrl}/auth/verify-email`, {
     method: "POST",
     headers: { "Content-Type": "application/json" },
     body: JSON.stringify({
-      account_id: accountId,
+      email: email,
       code: code,
     }),
   });
@@ -27,7 +28,10 @@ export const resendVerification = async (email: string): Promise<void> => {
   const apiUrl = getApiUrl();
   const response = await fetch(`${apiUrl}/auth/resend-verification`, {
     method: "POST",
-    headers: { "Content-Type": "application/json" },
+    headers: {
+      "Content-Type": "application/json",
+      ...(await powHeaders("resend_verification")),
+    },
     body: JSON.stringify({ email }),
   });
 
@@ -0,0 +1,183 @@
+"use client";
+
+import { useCallback, useEffect, useRef, useState } from "react";
+import { Alert, Button, Form, Modal } from "react-bootstrap";
+import { useAuth } from "@/contexts/auth-context";
+import { useI18n } from "@/contexts/i18n-context";
+import { reauthenticate } from "@/backend/account";
+import { ReauthRequiredError } from "@/backend/account-settings";
+import { getGoogleOAuthClientId } from "@/lib/google-oauth";
+
+const GOOGLE_SCRIPT_SRC = "https://accounts.google.com/gsi/client";
+
+interface ReauthModalProps {
+  show: boolean;
+  onConfirmed: () => void;
+  onCancel: () => void;
+}
+
+/**
+ * Asks the user to confirm their identity again: their password for email
+ * accounts, or a fresh Google sign-in for Google accounts
+ */
+export default function ReauthModal({ show, onConfirmed, onCancel }: ReauthModalProps) {
+  const { user } = useAuth();
+  const { t } = useI18n();
+  const [password, setPassword] = useState("");
+  const [error, setError] = useState("");
+  const [loading, setLoading] = useState(false);
+  const googleButtonRef = useRef<HTMLDivElement>(null);
+  const isGoogle = user?.auth_type === "google";
+  // Kept in a ref so the Google button isn't re-initialized on every render
+  const onConfirmedRef = useRef(onConfirmed);
+  onConfirmedRef.current = onConfirmed;
+
+  const confirm = useCallback(
+    async (proof: { password: string } | { id_token: string }) => {
+      setError("");
+      setLoading(true);
+      try {
+        await reauthenticate(proof);
+        setPassword("");
+        onConfirmedRef.current();
+      } catch (err) {
+        setError(err instanceof Error ? err.message : "Failed to confirm identity");
+      } finally {
+        setLoading(false);
+      }
+    },
+    []
+  );
+
+  // Google accounts confirm with a fresh ID token from Google Identity Services
+  useEffect(() => {
+    if (!show || !isGoogle) return;
+
+    let cancelled = false;
+    const renderButton = async () => {
+      const clientId = await getGoogleOAuthClientId();
+      if (cancelled || !clientId || !window.google || !googleButtonRef.current) return;
+      window.google.accounts.id.initialize({
+        client_id: clientId,
+        callback: (response) => confirm({ id_token: response.credential }),
+      });
+      window.google.accounts.id.renderButton(googleButtonRef.current, {
+        type: "standard",
+        theme: "outline",
+        size: "large",
+        text: "continue_with",
+        width: "100%",
+      });
+    };
+
+    if (window.google) {
+      renderButton();
+    } else {
+      const script = document.createElement("script");
+      script.src = GOOGLE_SCRIPT_SRC;
+      script.async = true;
+      script.defer = true;
+      script.onload = renderButton;
+      script.onerror = () => setError("Failed to load Google Sign-In");
+      document.head.appendChild(script);
+    }
+
+    return () => {
+      cancelled = true;
+    };
+  }, [show, isGoogle, confirm]);
+
+  const handleCancel = () => {
+    setPassword("");
+    setError("");
+    onCancel();
+  };
+
+  const handleSubmit = (e: React.FormEvent) => {
+    e.preventDefault();
+    if (password) confirm({ password });
+  };
+
+  return (
+    <Modal show={show} onHide={handleCancel}>
+      <Form onSubmit={handleSubmit}>
+        <Modal.Header closeButton>
+          <Modal.Title>{t("reauth.title")}</Modal.Title>
+        </Modal.Header>
+        <Modal.Body>
+          {error && <Alert variant="danger">{error}</Alert>}
+          {isGoogle ? (
+            <>
+              <p className="text-muted">{t("reauth.googleHint")}</p>
+              <div ref={googleButtonRef} style={{ width: "100%" }} />
+            </>
+          ) : (
+            <Form.Group>
+              <Form.Label>{t("settings.confirmPassword")}</Form.Label>
+              <Form.Control
+                type="password"
+                autoComplete="current-password"
+                value={password}
+                onChange={(e) => setPassword(e.target.value)}
+                placeholder={t("reauth.passwordPlaceholder")}
+                autoFocus
+                required
+              />
+            </Form.Group>
+          )}
+        </Modal.Body>
+        <Modal.Footer>
+          <Button variant="secondary" onClick={handleCancel} disabled={loading}>
+            {t("common.cancel")}
+          </Button>
+          {!isGoogle && (
+            <Button type="submit" variant="primary" disabled={loading || !password}>
+              {loading ? t("reauth.confirming") : t("reauth.confirm")}
+            </Button>
+          )}
+        </Modal.Footer>
+      </Form>
+    </Modal>
+  );
+}
+
+/**
+ * Run a request that may need step-up re-authentication: when the server asks
+ * for it, show the reauth modal and retry the request once confirmed.
+ * Render the returned `reauthModal` somewhere in the page.
+ */
+export function useReauth() {
+  const [pending, setPending] = useState<{
+    resolve: () => void;
+    reject: () => void;
+  } | null>(null);
+
+  const withReauth = useCallback(async <T,>(request: () => Promise<T>): Promise<T> => {
+    try {
+      return await request();
+    } catch (err) {
+      if (!(err instanceof ReauthRequiredError)) throw err;
+      // Cancelling the modal fails the request with the original error
+      await new Promise<void>((resolve, reject) =>
+        setPending({ resolve, reject: () => reject(err) })
+      );
+      return request();
+    }
+  }, []);
+
+  const reauthModal = (
+    <ReauthModal
+      show={pending !== null}
+      onConfirmed={() => {
+        pending?.resolve();
+        setPending(null);
+      }}
+      onCancel={() => {
+        pending?.reject();
+        setPending(null);
+      }}
+    />
+  );
+
+  return { withReauth, reauthModal };
+}
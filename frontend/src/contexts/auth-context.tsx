@@ -18,8 +18,8 @@ interface AuthContextType {
   tokens: AuthTokens | null;
   loading: boolean;
   signup: (email: string, password: string) => Promise<void>;
-  login: (email: string, password: string) => Promise<void>;
-  googleLogin: (idToken: string) => Promise<void>;
+  login: (email: string, password: string, rememberMe?: boolean) => Promise<void>;
+  googleLogin: (idToken: string, inviteCode?: string) => Promise<void>;
   logout: () => Promise<void>;
   refreshUser: () => Promise<void>;
 }
@@ -167,12 +167,12 @@ export function AuthProvider({ children }: { children: ReactNode }) {
 
   const signup = async (email: string, password: string) => {
     const data = await signupApi(email, password);
-    // Redirect to verification page with account info
-    router.push(`/verify?email=${encodeURIComponent(data.email)}&account_id=${data.account_id}`);
+    // Redirect to verification page for the address that was signed up
+    router.push(`/verify?email=${encodeURIComponent(data.email)}`);
   };
 
-  const login = async (email: string, password: string) => {
-    const data = await loginApi(email, password);
+  const login = async (email: string, password: string, rememberMe = false) => {
+    const data = await loginApi(email, password, rememberMe);
     setUser(data.account);
     const tokenData = {
       access_token: data.access_token,
@@ -185,8 +185,8 @@ export function AuthProvider({ children }: { children: ReactNode }) {
     router.push("/dashboard");
   };
 
-  const googleLogin = async (idToken: string) => {
-    const data = await googleLoginApi(idToken);
+  const googleLogin = async (idToken: string, inviteCode?: string) => {
+    const data = await googleLoginApi(idToken, inviteCode);
     setUser(data.account);
     const tokenData = {
       access_token: data.access_token,